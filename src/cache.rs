@@ -0,0 +1,304 @@
+use crate::config::CacheConfig;
+use crate::constants::CACHE_FILE_NAME;
+use inline_colorization::{color_reset, color_yellow};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// New answers are written to the file in batches of this many, and whatever is left once a
+/// library is done.
+const SAVE_BATCH_SIZE: usize = 25;
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CacheEntry {
+    /// Seconds since the Unix epoch
+    stored_at: u64,
+    value: serde_json::Value,
+}
+
+/// Answers of earlier metadata and subtitle lookups, kept in a JSON file in the user's cache
+/// directory so repeated runs don't hit the same sites again.
+pub struct Cache {
+    path: Option<PathBuf>,
+    offline: bool,
+    /// Dry runs answer from the file without ever writing it
+    read_only: bool,
+    search_ttl: Duration,
+    details_ttl: Duration,
+    entries: Mutex<CacheEntries>,
+}
+
+struct CacheEntries {
+    entries: HashMap<String, CacheEntry>,
+    /// Answers stored since the file was last written
+    unsaved_count: usize,
+}
+
+pub struct CacheStats {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Entry count per kind of lookup, with how many of them are still fresh
+    pub entries: Vec<(String, usize, usize)>,
+}
+
+impl Cache {
+    /// Loads the cache, starting over if the file is unreadable. Expired answers are dropped
+    /// unless offline, where they are all there is. A read-only cache keeps new answers in
+    /// memory only.
+    pub fn open(config: &CacheConfig, offline: bool, read_only: bool) -> Cache {
+        let path = match config.enabled {
+            true => cache_file_path(config),
+            false => None,
+        };
+        let mut entries = path.as_deref().map(load_entries).unwrap_or_default();
+        let entry_count = entries.len();
+        if !offline {
+            entries.retain(|key, entry| is_fresh(key, entry, config));
+        }
+
+        Cache {
+            path,
+            offline,
+            read_only,
+            search_ttl: config.search_ttl(),
+            details_ttl: config.details_ttl(),
+            entries: Mutex::new(CacheEntries {
+                unsaved_count: entry_count - entries.len(),
+                entries,
+            }),
+        }
+    }
+
+    /// In offline mode lookups only use cached answers, however old they are.
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// How long title searches are answered from the cache.
+    pub fn search_ttl(&self) -> Duration {
+        self.search_ttl
+    }
+
+    /// How long lookups by id are answered from the cache.
+    pub fn details_ttl(&self) -> Duration {
+        self.details_ttl
+    }
+
+    /// Returns the cached answer for the key unless it is older than the given age.
+    pub fn get<T: DeserializeOwned>(&self, key: &str, max_age: Duration) -> Option<T> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.entries.get(key)?;
+        if !self.offline && now().saturating_sub(entry.stored_at) > max_age.as_secs() {
+            return None;
+        }
+
+        serde_json::from_value(entry.value.clone()).ok()
+    }
+
+    pub fn put<T: Serialize>(&self, key: &str, value: &T) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let Ok(value) = serde_json::to_value(value) else {
+            return;
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.entries.insert(key.to_string(), CacheEntry { stored_at: now(), value });
+        entries.unsaved_count += 1;
+        if !self.read_only && entries.unsaved_count >= SAVE_BATCH_SIZE {
+            save(path, &mut entries);
+        }
+    }
+
+    /// Writes the answers stored since the last write to the file.
+    pub fn flush(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if self.read_only {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.unsaved_count > 0 {
+            save(path, &mut entries);
+        }
+    }
+}
+
+impl Drop for Cache {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Builds a cache key from a kind of lookup and its query, ignoring case and spacing.
+pub fn cache_key(kind: &str, query: &str) -> String {
+    let query = query.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    format!("{}:{}", kind, query)
+}
+
+/// Removes the cache file and returns how many entries it held.
+pub fn clear_cache(config: &CacheConfig) -> io::Result<usize> {
+    let Some(path) = cache_file_path(config).filter(|path| path.exists()) else {
+        return Ok(0);
+    };
+
+    let entry_count = load_entries(&path).len();
+    fs::remove_file(&path)?;
+    Ok(entry_count)
+}
+
+pub fn get_cache_stats(config: &CacheConfig) -> Option<CacheStats> {
+    let path = cache_file_path(config)?;
+    let size = fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or_default();
+
+    let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+    for (key, entry) in load_entries(&path) {
+        let kind = key.split(':').next().unwrap_or_default().to_string();
+        let is_fresh = is_fresh(&key, &entry, config);
+
+        let count = counts.entry(kind).or_default();
+        count.0 += 1;
+        count.1 += usize::from(is_fresh);
+    }
+
+    let mut entries: Vec<(String, usize, usize)> = counts
+        .into_iter()
+        .map(|(kind, (total, fresh))| (kind, total, fresh))
+        .collect();
+    entries.sort();
+
+    Some(CacheStats { path, size, entries })
+}
+
+/// Title searches expire sooner than lookups by id.
+fn is_fresh(key: &str, entry: &CacheEntry, config: &CacheConfig) -> bool {
+    let kind = key.split(':').next().unwrap_or_default();
+    let max_age = match kind.ends_with("search") {
+        true => config.search_ttl(),
+        false => config.details_ttl(),
+    };
+
+    now().saturating_sub(entry.stored_at) <= max_age.as_secs()
+}
+
+fn cache_file_path(config: &CacheConfig) -> Option<PathBuf> {
+    config
+        .directory
+        .clone()
+        .or_else(|| dirs::cache_dir().map(|cache_dir| cache_dir.join("media-organizer")))
+        .map(|directory| directory.join(CACHE_FILE_NAME))
+}
+
+fn load_entries(path: &Path) -> HashMap<String, CacheEntry> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        eprintln!("{color_yellow}Ignoring the unreadable lookup cache {:?}: {}{color_reset}", path, e);
+        HashMap::new()
+    })
+}
+
+fn save(path: &Path, entries: &mut CacheEntries) {
+    match save_entries(path, &entries.entries) {
+        Ok(()) => entries.unsaved_count = 0,
+        Err(e) => eprintln!("{color_yellow}Failed to update the lookup cache {:?}: {}{color_reset}", path, e),
+    }
+}
+
+fn save_entries(path: &Path, entries: &HashMap<String, CacheEntry>) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // write next to it and rename so an interrupted run never leaves half a file
+    let temporary_path = path.with_extension("json.tmp");
+    fs::write(&temporary_path, serde_json::to_vec(entries).map_err(io::Error::other)?)?;
+    fs::rename(&temporary_path, path)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(name: &str) -> CacheConfig {
+        let directory = std::env::temp_dir().join(format!("media-organizer-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        CacheConfig { directory: Some(directory), ..CacheConfig::default() }
+    }
+
+    #[test]
+    fn answers_are_written_in_batches() {
+        let config = config("batches");
+        let path = cache_file_path(&config).unwrap();
+        let cache = Cache::open(&config, false, false);
+
+        cache.put("imdb-search:alien", &1);
+        assert!(!path.exists());
+        for index in 1..SAVE_BATCH_SIZE {
+            cache.put(&format!("imdb:tt{}", index), &index);
+        }
+        assert_eq!(load_entries(&path).len(), SAVE_BATCH_SIZE);
+
+        cache.put("imdb:tt0078748", &2);
+        drop(cache);
+        assert_eq!(load_entries(&path).len(), SAVE_BATCH_SIZE + 1);
+        let _ = fs::remove_dir_all(config.directory.unwrap());
+    }
+
+    #[test]
+    fn expired_answers_are_dropped_on_load() {
+        let config = config("expired");
+        let path = cache_file_path(&config).unwrap();
+        let expired_at = now() - config.details_ttl().as_secs() - 1;
+        let entries = HashMap::from([
+            ("imdb:tt1".to_string(), CacheEntry { stored_at: expired_at, value: 1.into() }),
+            ("imdb:tt2".to_string(), CacheEntry { stored_at: now(), value: 2.into() }),
+        ]);
+        save_entries(&path, &entries).unwrap();
+
+        let offline_cache = Cache::open(&config, true, false);
+        assert_eq!(offline_cache.get::<u32>("imdb:tt1", Duration::ZERO), Some(1));
+        drop(offline_cache);
+        assert_eq!(load_entries(&path).len(), 2);
+
+        let cache = Cache::open(&config, false, false);
+        assert_eq!(cache.get::<u32>("imdb:tt1", Duration::MAX), None);
+        assert_eq!(cache.get::<u32>("imdb:tt2", config.details_ttl()), Some(2));
+        cache.flush();
+        assert_eq!(load_entries(&path).len(), 1);
+        let _ = fs::remove_dir_all(config.directory.unwrap());
+    }
+
+    #[test]
+    fn read_only_cache_never_writes_the_file() {
+        let config = config("read-only");
+        let path = cache_file_path(&config).unwrap();
+        let cache = Cache::open(&config, false, true);
+
+        for index in 0..SAVE_BATCH_SIZE {
+            cache.put(&format!("imdb:tt{}", index), &index);
+        }
+        assert_eq!(cache.get::<usize>("imdb:tt1", Duration::MAX), Some(1));
+        cache.flush();
+        drop(cache);
+        assert!(!path.exists());
+        let _ = fs::remove_dir_all(config.directory.unwrap());
+    }
+}
@@ -0,0 +1,148 @@
+use crate::constants::DEFAULT_PURGE_AGE_DAYS;
+use crate::file_operations::TransferMode;
+use clap::{ArgGroup, Parser, Subcommand};
+use clap_complete::Shell;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "media-organizer",
+    version,
+    about = "Reorganize your movie and TV show libraries"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// Print the planned changes without touching the disk
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Config file to use instead of the one in the user's config directory
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Print every filesystem operation as it is performed
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    /// Directory removed items are moved to, overriding the config file
+    #[arg(long, global = true, value_name = "DIR")]
+    pub quarantine: Option<PathBuf>,
+
+    /// Library root to put organized media in instead of organizing in place
+    #[arg(long, global = true, value_name = "DIR")]
+    pub output_dir: Option<PathBuf>,
+
+    /// How media gets into the organized library, overriding the config file
+    #[arg(long, global = true, value_enum, value_name = "MODE")]
+    pub transfer: Option<TransferMode>,
+
+    /// Ask which match is right when a metadata lookup is not confident enough
+    #[arg(long, global = true)]
+    pub interactive: bool,
+
+    /// Only use metadata matches above the confidence threshold, never asking
+    #[arg(long, global = true, conflicts_with = "interactive")]
+    pub yes: bool,
+
+    /// Only use cached metadata and subtitle lookups, never the network
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Move removed items to the desktop trash instead of the quarantine
+    #[arg(long, global = true, conflicts_with = "quarantine")]
+    pub trash: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Reorganize your movie directories
+    Movies {
+        #[arg(required = true, value_name = "DIRECTORY")]
+        directories: Vec<PathBuf>,
+    },
+    /// Reorganize your TV series directories
+    #[command(name = "tvshows", alias = "tv-shows")]
+    TvShows {
+        #[arg(required = true, value_name = "DIRECTORY")]
+        directories: Vec<PathBuf>,
+    },
+    /// Revert the last run on each directory
+    Undo {
+        #[arg(required = true, value_name = "DIRECTORY")]
+        directories: Vec<PathBuf>,
+    },
+    /// Permanently remove quarantined items
+    Purge {
+        #[arg(required = true, value_name = "DIRECTORY")]
+        directories: Vec<PathBuf>,
+
+        /// Only purge items quarantined more than this many days ago
+        #[arg(long, value_name = "DAYS", default_value_t = DEFAULT_PURGE_AGE_DAYS)]
+        older_than: u64,
+    },
+    /// Inspect or empty the metadata and subtitle lookup cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommand,
+    },
+    /// Fix the timing of subtitles
+    Subtitles {
+        #[command(subcommand)]
+        action: SubtitlesCommand,
+    },
+    /// Print a shell completion script
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Print the man page
+    Man,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheCommand {
+    /// Remove every cached lookup
+    Clear,
+    /// Show how many lookups are cached and how many are still fresh
+    Stats,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SubtitlesCommand {
+    /// Shift, stretch or align a subtitle and write it as SRT next to its movie
+    #[command(group(ArgGroup::new("timing").required(true).multiple(true).args(["offset", "from_fps", "reference"])))]
+    Sync {
+        #[arg(value_name = "SUBTITLE")]
+        subtitle: PathBuf,
+
+        /// Seconds to move every cue by, negative to show them earlier
+        #[arg(long, value_name = "SECONDS", allow_negative_numbers = true)]
+        offset: Option<f64>,
+
+        /// Frame rate the subtitle was timed for, like 25
+        #[arg(long, value_name = "FPS", requires = "to_fps")]
+        from_fps: Option<f64>,
+
+        /// Frame rate of the video, like 23.976
+        #[arg(long, value_name = "FPS", requires = "from_fps")]
+        to_fps: Option<f64>,
+
+        /// Subtitle with the right timing to align against, like one extracted from the video
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["offset", "from_fps", "to_fps"])]
+        reference: Option<PathBuf>,
+    },
+}
+
+impl Command {
+    pub fn directories(&self) -> &[PathBuf] {
+        match self {
+            Command::Movies { directories }
+            | Command::TvShows { directories }
+            | Command::Undo { directories }
+            | Command::Purge { directories, .. } => directories,
+            Command::Cache { .. } | Command::Subtitles { .. } | Command::Completions { .. } | Command::Man => &[],
+        }
+    }
+}
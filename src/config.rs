@@ -0,0 +1,510 @@
+use crate::constants::{
+    CONFIG_FILE_NAME, DEFAULT_CONFIDENCE_THRESHOLD, DEFAULT_DETAILS_CACHE_DAYS, DEFAULT_HTTP_RETRIES,
+    DEFAULT_HTTP_TIMEOUT_SECONDS, DEFAULT_REQUESTS_PER_SECOND, DEFAULT_SEARCH_CACHE_DAYS, DEFAULT_USER_AGENT, QUARANTINE_DIRECTORY_NAME, SUBTITLE_FILE_EXTENSIONS, VIDEO_FILE_EXTENSIONS,
+};
+use crate::file_operations::TransferMode;
+use crate::http::interval_for;
+use crate::languages::{find_language, Language};
+use crate::naming::{check_movie_template, Template, DEFAULT_MOVIE_TEMPLATE, DEFAULT_SUBTITLE_TEMPLATE};
+use crate::quarantine::DeletionPolicy;
+use crate::subtitle_formats::SubtitleFormat;
+use inline_colorization::{color_reset, color_yellow};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+const METADATA_PROVIDERS: [&str; 3] = ["imdb", "tmdb", "omdb"];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub video_extensions: Vec<String>,
+    pub subtitle_extensions: Vec<String>,
+    pub subtitle_languages: Vec<String>,
+    /// Library root organized media is moved to, media is organized in place when unset
+    pub output_directory: Option<PathBuf>,
+    /// Anything but `move` leaves the source untouched and needs an output directory
+    pub transfer: TransferMode,
+    pub naming: NamingConfig,
+    pub subtitles: SubtitleConfig,
+    pub metadata: MetadataConfig,
+    pub cache: CacheConfig,
+    pub http: HttpConfig,
+    pub deletion: DeletionConfig,
+    pub libraries: Vec<LibraryConfig>,
+}
+
+/// Templates for the names given to organized movies, see [`Template`] for the syntax.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NamingConfig {
+    pub movie_directory: Template,
+    pub movie_file: Template,
+    /// Rendered without the extension, which is kept from the original subtitle
+    pub subtitle_file: Template,
+}
+
+impl NamingConfig {
+    /// Each template with its key and whether it names subtitles.
+    fn templates(&self) -> [(&'static str, &Template, bool); 3] {
+        [
+            ("movie_directory", &self.movie_directory, false),
+            ("movie_file", &self.movie_file, false),
+            ("subtitle_file", &self.subtitle_file, true),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SubtitleConfig {
+    /// Text subtitles kept with a movie are converted to this format, each keeps its own when unset
+    pub convert_to: Option<SubtitleFormat>,
+    /// Re-encode subtitles to UTF-8 without a BOM, renumber their cues and remove ads
+    pub clean: bool,
+    /// Download subtitles from OpenSubtitles in the preferred languages a movie has none in
+    pub download: bool,
+    pub opensubtitles: ProviderConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetadataConfig {
+    /// Providers to look titles up with, in order of priority
+    pub providers: Vec<String>,
+    /// Matches below this confidence, from 0 to 1, are asked about or skipped
+    pub confidence_threshold: f32,
+    pub imdb: ProviderConfig,
+    pub tmdb: ProviderConfig,
+    pub omdb: ProviderConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProviderConfig {
+    /// Overrides the provider's public endpoint, e.g. to point it at a mock server
+    pub base_url: Option<String>,
+    pub api_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    /// Defaults to a directory in the user's cache directory
+    pub directory: Option<PathBuf>,
+    /// How long title searches are answered from the cache
+    pub search_ttl_days: u64,
+    /// How long lookups by id are answered from the cache
+    pub details_ttl_days: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub user_agent: String,
+    pub timeout_seconds: u64,
+    /// Retries of throttled or failed requests, with exponential backoff in between
+    pub max_retries: u32,
+    pub requests_per_second: f32,
+    /// Rate limits for specific hosts, e.g. `"www.imdb.com" = 0.5`
+    pub rate_limits: HashMap<String, f32>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeletionConfig {
+    pub policy: DeletionMode,
+    /// Defaults to a hidden directory inside each library
+    pub quarantine_directory: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeletionMode {
+    #[default]
+    Quarantine,
+    Trash,
+    Delete,
+}
+
+/// Settings for one library root, overriding the top-level ones.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LibraryConfig {
+    pub path: PathBuf,
+    pub video_extensions: Option<Vec<String>>,
+    pub subtitle_extensions: Option<Vec<String>>,
+    pub subtitle_languages: Option<Vec<String>>,
+    pub output_directory: Option<PathBuf>,
+    pub transfer: Option<TransferMode>,
+    pub naming: Option<NamingConfig>,
+    pub deletion: Option<DeletionConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            video_extensions: VIDEO_FILE_EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+            subtitle_extensions: SUBTITLE_FILE_EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+            subtitle_languages: vec!["en".to_string()],
+            output_directory: None,
+            transfer: TransferMode::default(),
+            naming: NamingConfig::default(),
+            subtitles: SubtitleConfig::default(),
+            metadata: MetadataConfig::default(),
+            cache: CacheConfig::default(),
+            http: HttpConfig::default(),
+            deletion: DeletionConfig::default(),
+            libraries: Vec::new(),
+        }
+    }
+}
+
+impl Default for NamingConfig {
+    fn default() -> Self {
+        NamingConfig {
+            movie_directory: Template::parse(DEFAULT_MOVIE_TEMPLATE).unwrap(),
+            movie_file: Template::parse(DEFAULT_MOVIE_TEMPLATE).unwrap(),
+            subtitle_file: Template::parse(DEFAULT_SUBTITLE_TEMPLATE).unwrap(),
+        }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            enabled: true,
+            directory: None,
+            search_ttl_days: DEFAULT_SEARCH_CACHE_DAYS,
+            details_ttl_days: DEFAULT_DETAILS_CACHE_DAYS,
+        }
+    }
+}
+
+impl Default for SubtitleConfig {
+    fn default() -> Self {
+        SubtitleConfig {
+            convert_to: None,
+            clean: true,
+            download: true,
+            opensubtitles: ProviderConfig::default(),
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            timeout_seconds: DEFAULT_HTTP_TIMEOUT_SECONDS,
+            max_retries: DEFAULT_HTTP_RETRIES,
+            requests_per_second: DEFAULT_REQUESTS_PER_SECOND,
+            rate_limits: HashMap::new(),
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn search_ttl(&self) -> Duration {
+        Duration::from_secs(self.search_ttl_days * SECONDS_PER_DAY)
+    }
+
+    pub fn details_ttl(&self) -> Duration {
+        Duration::from_secs(self.details_ttl_days * SECONDS_PER_DAY)
+    }
+}
+
+impl Default for MetadataConfig {
+    fn default() -> Self {
+        MetadataConfig {
+            providers: vec!["imdb".to_string()],
+            confidence_threshold: DEFAULT_CONFIDENCE_THRESHOLD,
+            imdb: ProviderConfig::default(),
+            tmdb: ProviderConfig::default(),
+            omdb: ProviderConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the given config file, or the one in the user's config directory if it exists.
+    pub fn load(config_path: Option<&Path>) -> Result<Config, Box<dyn Error>> {
+        let config_path = match config_path {
+            Some(config_path) => config_path.to_path_buf(),
+            None => match default_config_path().filter(|path| path.is_file()) {
+                Some(config_path) => config_path,
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let content = fs::read_to_string(&config_path)
+            .map_err(|e| format!("Failed to read config file {:?}: {}", config_path, e))?;
+        let mut config: Config = toml::from_str(&content)
+            .map_err(|e| format!("Invalid config file {:?}: {}", config_path, e))?;
+
+        config.normalize();
+        let warnings = config
+            .validate()
+            .map_err(|e| format!("Invalid config file {:?}: {}", config_path, e))?;
+        for warning in warnings {
+            println!("{color_yellow}Config file {:?}: {}{color_reset}", config_path, warning);
+        }
+
+        Ok(config)
+    }
+
+    /// Applies the overrides of the library the directory belongs to, if any.
+    pub fn for_library(&self, library_path: &Path) -> Config {
+        let mut config = self.clone();
+        let Some(library) = self.libraries.iter().find(|library| same_path(&library.path, library_path)) else {
+            return config;
+        };
+
+        if let Some(video_extensions) = &library.video_extensions {
+            config.video_extensions = video_extensions.clone();
+        }
+        if let Some(subtitle_extensions) = &library.subtitle_extensions {
+            config.subtitle_extensions = subtitle_extensions.clone();
+        }
+        if let Some(subtitle_languages) = &library.subtitle_languages {
+            config.subtitle_languages = subtitle_languages.clone();
+        }
+        if let Some(output_directory) = &library.output_directory {
+            config.output_directory = Some(output_directory.clone());
+        }
+        if let Some(transfer) = library.transfer {
+            config.transfer = transfer;
+        }
+        if let Some(naming) = &library.naming {
+            config.naming = naming.clone();
+        }
+        if let Some(deletion) = &library.deletion {
+            config.deletion = deletion.clone();
+        }
+
+        config
+    }
+
+    /// Where organized media ends up, the library itself unless an output directory is set.
+    pub fn output_path(&self, library_path: &Path) -> PathBuf {
+        self.output_directory
+            .clone()
+            .unwrap_or_else(|| library_path.to_path_buf())
+    }
+
+    pub fn deletion_policy(&self, library_path: &Path) -> DeletionPolicy {
+        match self.deletion.policy {
+            DeletionMode::Quarantine => DeletionPolicy::Quarantine(self.quarantine_path(library_path)),
+            DeletionMode::Trash => DeletionPolicy::Trash,
+            DeletionMode::Delete => DeletionPolicy::Delete,
+        }
+    }
+
+    pub fn quarantine_path(&self, library_path: &Path) -> PathBuf {
+        self.deletion
+            .quarantine_directory
+            .clone()
+            .unwrap_or_else(|| library_path.join(QUARANTINE_DIRECTORY_NAME))
+    }
+
+    pub fn is_video_file(&self, path: &Path) -> bool {
+        has_extension(path, &self.video_extensions)
+    }
+
+    pub fn is_subtitle_file(&self, path: &Path) -> bool {
+        has_extension(path, &self.subtitle_extensions)
+    }
+
+    /// Languages to keep a subtitle in, most preferred first.
+    pub fn subtitle_languages(&self) -> Vec<&'static Language> {
+        self.subtitle_languages
+            .iter()
+            .filter_map(|language| find_language(language))
+            .collect()
+    }
+
+    fn normalize(&mut self) {
+        normalize_extensions(&mut self.video_extensions);
+        normalize_extensions(&mut self.subtitle_extensions);
+        normalize_languages(&mut self.subtitle_languages);
+
+        for library in self.libraries.iter_mut() {
+            if let Some(video_extensions) = library.video_extensions.as_mut() {
+                normalize_extensions(video_extensions);
+            }
+            if let Some(subtitle_extensions) = library.subtitle_extensions.as_mut() {
+                normalize_extensions(subtitle_extensions);
+            }
+            if let Some(subtitle_languages) = library.subtitle_languages.as_mut() {
+                normalize_languages(subtitle_languages);
+            }
+        }
+    }
+
+    /// Returns warnings about settings that work but likely not as intended.
+    fn validate(&self) -> Result<Vec<String>, String> {
+        validate_extensions("video_extensions", &self.video_extensions)?;
+        validate_extensions("subtitle_extensions", &self.subtitle_extensions)?;
+        validate_languages("subtitle_languages", &self.subtitle_languages)?;
+        if self.output_directory.as_ref().is_some_and(|path| path.as_os_str().is_empty()) {
+            return Err("`output_directory`: must not be empty".to_string());
+        }
+
+        for (index, provider) in self.metadata.providers.iter().enumerate() {
+            if !METADATA_PROVIDERS.contains(&provider.as_str()) {
+                return Err(format!(
+                    "`metadata.providers[{}]`: unknown provider \"{}\", expected one of {:?}",
+                    index, provider, METADATA_PROVIDERS
+                ));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.metadata.confidence_threshold) {
+            return Err(format!(
+                "`metadata.confidence_threshold`: {} is not between 0 and 1",
+                self.metadata.confidence_threshold
+            ));
+        }
+        if self.http.user_agent.trim().is_empty() {
+            return Err("`http.user_agent`: must not be empty".to_string());
+        }
+        if self.http.timeout_seconds == 0 {
+            return Err("`http.timeout_seconds`: must be at least 1".to_string());
+        }
+        if !is_valid_rate(self.http.requests_per_second) {
+            return Err(format!(
+                "`http.requests_per_second`: {} is not greater than 0 or too small to wait between requests",
+                self.http.requests_per_second
+            ));
+        }
+        for (host, requests_per_second) in &self.http.rate_limits {
+            if !is_valid_rate(*requests_per_second) {
+                return Err(format!(
+                    "`http.rate_limits.\"{}\"`: {} is not greater than 0 or too small to wait between requests",
+                    host, requests_per_second
+                ));
+            }
+        }
+        for (provider, provider_config) in [("tmdb", &self.metadata.tmdb), ("omdb", &self.metadata.omdb)] {
+            let api_key_missing = provider_config.api_key.as_deref().is_none_or(|api_key| api_key.trim().is_empty());
+            if self.metadata.providers.iter().any(|name| name == provider) && api_key_missing {
+                return Err(format!(
+                    "`metadata.{}.api_key`: required when the {} provider is enabled",
+                    provider, provider
+                ));
+            }
+        }
+
+        let mut warnings = validate_naming("naming", &self.naming)?;
+        for (index, library) in self.libraries.iter().enumerate() {
+            if library.path.as_os_str().is_empty() {
+                return Err(format!("`libraries[{}].path`: must not be empty", index));
+            }
+            if library.output_directory.as_ref().is_some_and(|path| path.as_os_str().is_empty()) {
+                return Err(format!("`libraries[{}].output_directory`: must not be empty", index));
+            }
+            if let Some(video_extensions) = &library.video_extensions {
+                validate_extensions(&format!("libraries[{}].video_extensions", index), video_extensions)?;
+            }
+            if let Some(subtitle_extensions) = &library.subtitle_extensions {
+                validate_extensions(&format!("libraries[{}].subtitle_extensions", index), subtitle_extensions)?;
+            }
+            if let Some(subtitle_languages) = &library.subtitle_languages {
+                validate_languages(&format!("libraries[{}].subtitle_languages", index), subtitle_languages)?;
+            }
+            if let Some(naming) = &library.naming {
+                warnings.extend(validate_naming(&format!("libraries[{}].naming", index), naming)?);
+            }
+        }
+
+        Ok(warnings)
+    }
+}
+
+fn validate_naming(key: &str, naming: &NamingConfig) -> Result<Vec<String>, String> {
+    let mut warnings = Vec::new();
+    for (name, template, is_subtitle_template) in naming.templates() {
+        let warning = check_movie_template(template, is_subtitle_template).map_err(|e| format!("`{}.{}`: {}", key, name, e))?;
+        warnings.extend(warning.map(|warning| format!("`{}.{}`: {}", key, name, warning)));
+    }
+
+    Ok(warnings)
+}
+
+pub fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|config_dir| config_dir.join("media-organizer").join(CONFIG_FILE_NAME))
+}
+
+fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    path.extension().is_some_and(|ext| {
+        let ext = ext.to_string_lossy().to_lowercase();
+        extensions.contains(&ext)
+    })
+}
+
+/// A rate of requests per second that leaves a finite gap between requests.
+fn is_valid_rate(requests_per_second: f32) -> bool {
+    requests_per_second.is_finite() && requests_per_second > 0.0 && interval_for(requests_per_second).is_ok()
+}
+
+pub fn same_path(left: &Path, right: &Path) -> bool {
+    match (fs::canonicalize(left), fs::canonicalize(right)) {
+        (Ok(left), Ok(right)) => left == right,
+        _ => left == right,
+    }
+}
+
+fn normalize_extensions(extensions: &mut [String]) {
+    for extension in extensions.iter_mut() {
+        *extension = extension.trim().trim_start_matches('.').to_lowercase();
+    }
+}
+
+/// Spells every known language by its ISO 639-1 code, so `eng` and `English` both become `en`.
+fn normalize_languages(languages: &mut [String]) {
+    for language in languages.iter_mut() {
+        *language = match find_language(language) {
+            Some(known_language) => known_language.code.to_string(),
+            None => language.trim().to_lowercase(),
+        };
+    }
+}
+
+fn validate_extensions(key: &str, extensions: &[String]) -> Result<(), String> {
+    if extensions.is_empty() {
+        return Err(format!("`{}`: must list at least one extension", key));
+    }
+
+    for (index, extension) in extensions.iter().enumerate() {
+        if extension.is_empty() || !extension.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(format!("`{}[{}]`: \"{}\" is not a file extension", key, index, extension));
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_languages(key: &str, languages: &[String]) -> Result<(), String> {
+    if languages.is_empty() {
+        return Err(format!("`{}`: must list at least one language", key));
+    }
+
+    for (index, language) in languages.iter().enumerate() {
+        if find_language(language).is_none() {
+            return Err(format!(
+                "`{}[{}]`: \"{}\" is not a known ISO 639 language code or name",
+                key, index, language
+            ));
+        }
+        if languages[..index].contains(language) {
+            return Err(format!("`{}[{}]`: \"{}\" is listed twice", key, index, language));
+        }
+    }
+
+    Ok(())
+}
@@ -1,7 +1,30 @@
 pub const VIDEO_FILE_EXTENSIONS: [&str; 4] = ["avi", "mkv", "mp4", "mov"];
 
-pub const SUBTITLE_FILE_EXTENSION: &str = "srt";
+/// VobSub subtitles come as an `.idx` index with the images in a `.sub` of the same name.
+pub const SUBTITLE_FILE_EXTENSIONS: [&str; 7] = ["srt", "ass", "ssa", "vtt", "sub", "idx", "sup"];
 
 pub const METADATA_FILE_NAME: &str = "metadata.json";
 
-pub const SCRAPER_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/114.0.0.0 Safari/537.36";
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+pub const CACHE_FILE_NAME: &str = "lookups.json";
+
+pub const DEFAULT_SEARCH_CACHE_DAYS: u64 = 7;
+
+pub const DEFAULT_DETAILS_CACHE_DAYS: u64 = 30;
+
+pub const JOURNAL_FILE_NAME: &str = ".media-organizer-journal.jsonl";
+
+pub const QUARANTINE_DIRECTORY_NAME: &str = ".media-organizer-trash";
+
+pub const DEFAULT_PURGE_AGE_DAYS: u64 = 30;
+
+pub const DEFAULT_CONFIDENCE_THRESHOLD: f32 = 0.8;
+
+pub const DEFAULT_HTTP_TIMEOUT_SECONDS: u64 = 30;
+
+pub const DEFAULT_HTTP_RETRIES: u32 = 3;
+
+pub const DEFAULT_REQUESTS_PER_SECOND: f32 = 2.0;
+
+pub const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/114.0.0.0 Safari/537.36";
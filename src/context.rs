@@ -0,0 +1,27 @@
+use crate::cache::Cache;
+use crate::config::{same_path, Config};
+use crate::file_operations::FileOperations;
+use crate::http::HttpClient;
+use crate::metadata_providers::MatchMode;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Everything a run over one library needs: its settings and the way it changes the disk.
+pub struct Context {
+    pub config: Config,
+    pub ops: FileOperations,
+    /// Library root organized media goes to, `None` when organizing in place
+    pub output_path: Option<PathBuf>,
+    pub match_mode: MatchMode,
+    pub cache: Arc<Cache>,
+    pub http: Arc<HttpClient>,
+}
+
+impl Context {
+    /// An output directory inside the library must not be organized or cleaned up itself.
+    pub fn is_output_path(&self, path: &Path) -> bool {
+        self.output_path
+            .as_deref()
+            .is_some_and(|output_path| same_path(output_path, path))
+    }
+}
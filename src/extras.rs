@@ -0,0 +1,161 @@
+use regex::Regex;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// How each kind of extra is named, tried in order so that `Deleted Scenes` is not taken for
+/// plain scenes. Names are matched lowercased, with words split by spaces.
+const EXTRA_PATTERNS: [(ExtraKind, &str); 9] = [
+    (ExtraKind::Sample, r"\bsample\b"),
+    (ExtraKind::Trailer, r"\b(trailers?|teasers?)\b"),
+    (ExtraKind::BehindTheScenes, r"\b(behind ?the ?scenes|making of|bts)\b"),
+    (ExtraKind::DeletedScene, r"\bdeleted( ?scenes?)?\b"),
+    (ExtraKind::Featurette, r"\bfeaturettes?\b"),
+    (ExtraKind::Interview, r"\binterviews?\b"),
+    (ExtraKind::Scene, r"\bscenes?\b"),
+    (ExtraKind::Short, r"\bshorts?\b"),
+    (ExtraKind::Other, r"\b(extras?|bonus|other|special features?)\b"),
+];
+
+/// Samples are cut to a minute or two. Ones whose length can't be read are told apart by being
+/// a sliver of the movie's size.
+const SAMPLE_MAX_DURATION_MS: u64 = 2 * 60 * 1000;
+const SAMPLE_MAX_SIZE_SHARE: f64 = 0.01;
+
+/// Bonus material that comes with a movie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtraKind {
+    Sample,
+    Trailer,
+    Featurette,
+    BehindTheScenes,
+    DeletedScene,
+    Interview,
+    Scene,
+    Short,
+    Other,
+}
+
+impl ExtraKind {
+    /// The folder Plex and Jellyfin both look for this kind of extra in. Samples have none, as
+    /// they are not worth keeping.
+    pub fn folder_name(&self) -> Option<&'static str> {
+        match self {
+            ExtraKind::Sample => None,
+            ExtraKind::Trailer => Some("Trailers"),
+            ExtraKind::Featurette => Some("Featurettes"),
+            ExtraKind::BehindTheScenes => Some("Behind The Scenes"),
+            ExtraKind::DeletedScene => Some("Deleted Scenes"),
+            ExtraKind::Interview => Some("Interviews"),
+            ExtraKind::Scene => Some("Scenes"),
+            ExtraKind::Short => Some("Shorts"),
+            ExtraKind::Other => Some("Other"),
+        }
+    }
+}
+
+/// A video in a movie directory that is not the movie itself.
+#[derive(Debug, Clone)]
+pub struct Extra {
+    pub kind: ExtraKind,
+    pub path: PathBuf,
+}
+
+/// Tells what kind of extra a video is from its own name or else from the folders it is in,
+/// like `Sample/` or `Featurettes/`. Words of the movie directory's name are left out, so
+/// the video of a movie called "Trailer Park Boys" is not taken for a trailer.
+pub fn classify_extra(path: &Path, movie_dir_path: &Path) -> Option<ExtraKind> {
+    let title_words = get_title_words(movie_dir_path);
+    let relative_path = path.strip_prefix(movie_dir_path).unwrap_or(path);
+
+    // the file name says the most, then the folders from the innermost out
+    let file_stem = relative_path.file_stem().map(|stem| stem.to_string_lossy());
+    let folder_names = relative_path
+        .parent()
+        .into_iter()
+        .flat_map(|parent| parent.components().rev())
+        .map(|component| component.as_os_str().to_string_lossy());
+
+    file_stem
+        .into_iter()
+        .chain(folder_names)
+        .find_map(|name| classify_name(&name, &title_words))
+}
+
+/// Extras whose names don't say what they are: samples by their length, or by their size next
+/// to the movie's when their length can't be read, and any other video is kept as an extra.
+pub fn classify_unnamed_extra(duration_ms: Option<u64>, size: u64, movie_size: u64) -> ExtraKind {
+    let is_sample = match duration_ms {
+        Some(duration_ms) => duration_ms <= SAMPLE_MAX_DURATION_MS,
+        None => (size as f64) < movie_size as f64 * SAMPLE_MAX_SIZE_SHARE,
+    };
+
+    match is_sample {
+        true => ExtraKind::Sample,
+        false => ExtraKind::Other,
+    }
+}
+
+/// Whether a folder of the movie directory holds extras by its name, like `Extras` or `Sample`.
+pub fn is_extras_folder(name: &str) -> bool {
+    classify_name(name, &HashSet::new()).is_some()
+}
+
+/// Like `is_extras_folder`, leaving out the words of the directory the folder is in, so the
+/// `Trailer.Park.Boys.S01` season folder of a show is not taken for trailers.
+pub fn is_extras_folder_of(name: &str, parent_dir_path: &Path) -> bool {
+    classify_name(name, &get_title_words(parent_dir_path)).is_some()
+}
+
+fn get_title_words(dir_path: &Path) -> HashSet<String> {
+    dir_path
+        .file_name()
+        .map(|name| split_words(&name.to_string_lossy()).collect())
+        .unwrap_or_default()
+}
+
+fn classify_name(name: &str, title_words: &HashSet<String>) -> Option<ExtraKind> {
+    let words: Vec<String> = split_words(name).filter(|word| !title_words.contains(word)).collect();
+    let text = words.join(" ");
+
+    EXTRA_PATTERNS
+        .iter()
+        .find(|(_, pattern)| Regex::new(pattern).unwrap().is_match(&text))
+        .map(|(kind, _)| *kind)
+}
+
+fn split_words(name: &str) -> impl Iterator<Item = String> + '_ {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extras_are_told_by_their_names_and_folders() {
+        let movie_dir_path = Path::new("/movies/Alien (1979)");
+        let classify = |path: &str| classify_extra(&movie_dir_path.join(path), movie_dir_path);
+        assert_eq!(classify("alien-sample.mkv"), Some(ExtraKind::Sample));
+        assert_eq!(classify("Deleted Scenes/Cut 1.mkv"), Some(ExtraKind::DeletedScene));
+        assert_eq!(classify("Extras/Making of Alien.mkv"), Some(ExtraKind::BehindTheScenes));
+        assert_eq!(classify("Featurettes/The Beast Within.mkv"), Some(ExtraKind::Featurette));
+        assert_eq!(classify("Alien.1979.1080p.mkv"), None);
+    }
+
+    #[test]
+    fn title_words_are_not_taken_for_extras() {
+        let movie_dir_path = Path::new("/movies/Trailer Park Boys The Movie (2006)");
+        let path = movie_dir_path.join("Trailer.Park.Boys.The.Movie.2006.mkv");
+        assert_eq!(classify_extra(&path, movie_dir_path), None);
+    }
+
+    #[test]
+    fn short_videos_are_samples() {
+        assert_eq!(classify_unnamed_extra(Some(60_000), 50, 1000), ExtraKind::Sample);
+        assert_eq!(classify_unnamed_extra(Some(20 * 60_000), 50, 1000), ExtraKind::Other);
+        assert_eq!(classify_unnamed_extra(None, 5, 1000), ExtraKind::Sample);
+        assert_eq!(classify_unnamed_extra(None, 500, 1000), ExtraKind::Other);
+    }
+}
@@ -0,0 +1,715 @@
+use crate::journal::Journal;
+use crate::quarantine::{move_to_quarantine, move_to_trash, quarantine_path_for, DeletionPolicy};
+use inline_colorization::{color_blue, color_cyan, color_green, color_red, color_reset, color_yellow};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum FileOperation {
+    CreateDirectory { path: PathBuf },
+    Move { source: PathBuf, destination: PathBuf },
+    Copy { source: PathBuf, destination: PathBuf },
+    HardLink { source: PathBuf, destination: PathBuf },
+    Symlink { source: PathBuf, destination: PathBuf },
+    Reflink { source: PathBuf, destination: PathBuf },
+    Delete { path: PathBuf, holding_path: Option<PathBuf> },
+    WriteFile { path: PathBuf, previous_path: Option<PathBuf> },
+}
+
+/// How media gets from the library it was found in to the organized one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferMode {
+    /// Rename in place or move into the output directory
+    #[default]
+    Move,
+    /// Hard link into the output directory, which must be on the same filesystem
+    Hardlink,
+    /// Symlink into the output directory
+    Symlink,
+    /// Copy-on-write clone into the output directory, or a full copy on filesystems that can't
+    /// clone
+    Reflink,
+    /// Full copy into the output directory
+    Copy,
+}
+
+/// Every change the organizers make to the library goes through here so that a dry run can
+/// record the plan instead of touching the disk, and a real run can be journaled for undo.
+pub struct FileOperations {
+    dry_run: bool,
+    verbose: bool,
+    journal: Option<Journal>,
+    deletion_policy: DeletionPolicy,
+    operations: Mutex<Vec<FileOperation>>,
+    /// What the disk would look like after the recorded operations, for a dry run to read
+    planned_tree: Mutex<PlannedTree>,
+}
+
+/// The items of every directory a dry run has looked at or changed, with whether each is a
+/// directory. Directories are read from disk the first time they are needed, which is always
+/// before an operation changes them.
+#[derive(Default)]
+struct PlannedTree {
+    items: BTreeMap<PathBuf, bool>,
+    loaded_dir_paths: HashSet<PathBuf>,
+}
+
+impl FileOperations {
+    pub fn new(dry_run: bool, verbose: bool) -> FileOperations {
+        FileOperations {
+            dry_run,
+            verbose,
+            journal: None,
+            deletion_policy: DeletionPolicy::Delete,
+            operations: Mutex::new(Vec::new()),
+            planned_tree: Mutex::new(PlannedTree::default()),
+        }
+    }
+
+    pub fn with_journal(
+        dry_run: bool,
+        verbose: bool,
+        library_root: &Path,
+        deletion_policy: DeletionPolicy,
+    ) -> FileOperations {
+        FileOperations {
+            journal: Some(Journal::new(library_root)),
+            deletion_policy,
+            ..FileOperations::new(dry_run, verbose)
+        }
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// The items of a directory, as they would be after the recorded operations in a dry run.
+    pub fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        if !self.dry_run {
+            return fs::read_dir(path)?.map(|entry| entry.map(|entry| entry.path())).collect();
+        }
+
+        let mut planned_tree = self.planned_tree.lock().unwrap();
+        if planned_tree.get(path) != Some(true) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{:?} is not a directory", path)));
+        }
+        planned_tree.load_dir(path);
+        Ok(planned_tree.children(path))
+    }
+
+    pub fn exists(&self, path: &Path) -> bool {
+        match self.dry_run {
+            true => self.planned_tree.lock().unwrap().get(path).is_some(),
+            false => path.exists(),
+        }
+    }
+
+    pub fn is_dir(&self, path: &Path) -> bool {
+        match self.dry_run {
+            true => self.planned_tree.lock().unwrap().get(path) == Some(true),
+            false => path.is_dir(),
+        }
+    }
+
+    pub fn is_file(&self, path: &Path) -> bool {
+        match self.dry_run {
+            true => self.planned_tree.lock().unwrap().get(path) == Some(false),
+            false => path.is_file(),
+        }
+    }
+
+    /// Where the contents of `path` are on disk to be read: the path itself, or in a dry run
+    /// the path it would have been moved or copied from.
+    pub fn current_path(&self, path: &Path) -> PathBuf {
+        let mut current_path = path.to_path_buf();
+        if !self.dry_run {
+            return current_path;
+        }
+
+        for operation in self.operations.lock().unwrap().iter().rev() {
+            match operation {
+                FileOperation::Move { source, destination }
+                | FileOperation::Copy { source, destination }
+                | FileOperation::HardLink { source, destination }
+                | FileOperation::Symlink { source, destination }
+                | FileOperation::Reflink { source, destination } => {
+                    if let Some(source_path) = rebase(&current_path, destination, source) {
+                        current_path = source_path;
+                    }
+                }
+                FileOperation::CreateDirectory { .. } | FileOperation::Delete { .. } | FileOperation::WriteFile { .. } => {}
+            }
+        }
+
+        current_path
+    }
+
+    pub fn create_dir(&self, path: &Path) -> io::Result<()> {
+        if self.dry_run && self.exists(path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{:?} already exists", path),
+            ));
+        }
+
+        self.apply(FileOperation::CreateDirectory { path: path.to_path_buf() }, || {
+            fs::create_dir(path)
+        })
+    }
+
+    pub fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        // record each missing level so undo can remove all of them again, and only once in a
+        // dry run, where they never come to exist
+        let mut missing_paths: Vec<&Path> = path
+            .ancestors()
+            .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !self.exists(ancestor))
+            .collect();
+        missing_paths.reverse();
+
+        for missing_path in missing_paths {
+            self.apply(FileOperation::CreateDirectory { path: missing_path.to_path_buf() }, || {
+                fs::create_dir(missing_path)
+            })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, source: P, destination: Q) -> io::Result<()> {
+        let (source, destination) = (source.as_ref(), destination.as_ref());
+        let operation = FileOperation::Move {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+        };
+
+        self.apply(operation, || move_path(source, destination))
+    }
+
+    pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(&self, source: P, destination: Q) -> io::Result<()> {
+        let (source, destination) = (source.as_ref(), destination.as_ref());
+        let operation = FileOperation::Copy {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+        };
+
+        self.apply(operation, || fs::copy(source, destination).map(|_| ()))
+    }
+
+    /// Puts a file at the destination the given way, leaving the source alone unless moving.
+    pub fn transfer(&self, source: &Path, destination: &Path, mode: TransferMode) -> io::Result<()> {
+        let (source_path, destination_path) = (source.to_path_buf(), destination.to_path_buf());
+        match mode {
+            TransferMode::Move => self.rename(source, destination),
+            TransferMode::Copy => self.copy(source, destination),
+            TransferMode::Hardlink => {
+                let operation = FileOperation::HardLink { source: source_path, destination: destination_path };
+                self.apply(operation, || fs::hard_link(source, destination))
+            }
+            TransferMode::Symlink => {
+                // relative targets would resolve against the link's directory
+                let target = fs::canonicalize(source)?;
+                let operation = FileOperation::Symlink { source: target.clone(), destination: destination_path };
+                self.apply(operation, || symlink(&target, destination))
+            }
+            TransferMode::Reflink => {
+                let operation = FileOperation::Reflink { source: source_path, destination: destination_path };
+                self.apply(operation, || reflink_copy::reflink_or_copy(source, destination).map(|_| ()))
+            }
+        }
+    }
+
+    pub fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.delete(path, || fs::remove_file(path))
+    }
+
+    pub fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        self.delete(path, || fs::remove_dir(path))
+    }
+
+    pub fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.delete(path, || fs::remove_dir_all(path))
+    }
+
+    pub fn write<C: AsRef<[u8]>>(&self, path: &Path, contents: C) -> io::Result<()> {
+        if self.dry_run {
+            let operation = FileOperation::WriteFile {
+                path: path.to_path_buf(),
+                previous_path: None,
+            };
+            return self.apply(operation, || Ok(()));
+        }
+
+        // keep whatever gets overwritten so the write can be undone
+        let previous_path = match path.is_file() {
+            true => self.set_aside(path, || fs::remove_file(path))?,
+            false => None,
+        };
+        fs::write(path, contents)?;
+
+        let operation = FileOperation::WriteFile {
+            path: path.to_path_buf(),
+            previous_path,
+        };
+        self.apply(operation, || Ok(()))
+    }
+
+    /// Prints every recorded operation followed by the tree each root would end up as.
+    pub fn print_plan(&self, root_paths: &[&Path]) {
+        let operations = self.operations.lock().unwrap();
+
+        println!();
+        println!("{color_cyan}Planned operations ({}):{color_reset}", operations.len());
+        for operation in operations.iter() {
+            println!("  {}", describe_operation(operation));
+        }
+
+        let mut tree = BTreeMap::new();
+        for root_path in root_paths {
+            collect_tree(root_path, &mut tree);
+        }
+        for operation in operations.iter() {
+            apply_to_tree(operation, &mut tree);
+        }
+
+        println!();
+        println!("{color_cyan}Resulting tree:{color_reset}");
+        for root_path in root_paths {
+            println!("{}", root_path.to_string_lossy());
+            for (path, is_dir) in tree.iter() {
+                let Ok(relative_path) = path.strip_prefix(root_path) else {
+                    continue;
+                };
+                let depth = relative_path.components().count();
+                if depth == 0 {
+                    continue;
+                }
+
+                let name = relative_path.file_name().unwrap_or_default().to_string_lossy();
+                let suffix = if *is_dir { "/" } else { "" };
+                println!("{}{}{}", "  ".repeat(depth), name, suffix);
+            }
+        }
+
+        println!();
+        println!("{color_yellow}Dry run, nothing was changed on disk{color_reset}");
+    }
+
+    fn delete<F: FnOnce() -> io::Result<()>>(&self, path: &Path, remove: F) -> io::Result<()> {
+        let holding_path = match self.dry_run {
+            true => None,
+            false => self.set_aside(path, remove)?,
+        };
+        let operation = FileOperation::Delete {
+            path: path.to_path_buf(),
+            holding_path,
+        };
+
+        self.apply(operation, || Ok(()))
+    }
+
+    /// Gets an item out of the way according to the deletion policy and returns where it was
+    /// kept, if anywhere. Only journaled runs keep items since nothing else could restore them.
+    fn set_aside<F: FnOnce() -> io::Result<()>>(&self, path: &Path, remove: F) -> io::Result<Option<PathBuf>> {
+        let Some(journal) = &self.journal else {
+            remove()?;
+            return Ok(None);
+        };
+
+        match &self.deletion_policy {
+            DeletionPolicy::Quarantine(quarantine_path) => {
+                let holding_path =
+                    quarantine_path_for(quarantine_path, journal.library_root(), journal.run_id(), path);
+                move_to_quarantine(path, &holding_path)?;
+                Ok(Some(holding_path))
+            }
+            DeletionPolicy::Trash => move_to_trash(path).map(Some),
+            DeletionPolicy::Delete => {
+                remove()?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn apply<F: FnOnce() -> io::Result<()>>(&self, operation: FileOperation, action: F) -> io::Result<()> {
+        if self.dry_run {
+            println!("{color_yellow}[dry-run] {}{color_reset}", describe_operation(&operation));
+            self.planned_tree.lock().unwrap().apply(&operation);
+        } else {
+            action()?;
+            if self.verbose {
+                println!("{}", describe_operation(&operation));
+            }
+            if let Some(journal) = &self.journal {
+                journal.record(&operation)?;
+            }
+        }
+
+        self.operations.lock().unwrap().push(operation);
+        Ok(())
+    }
+}
+
+impl PlannedTree {
+    /// Whether the item is a directory, or `None` when there is no such item.
+    fn get(&mut self, path: &Path) -> Option<bool> {
+        let Some(parent) = path.parent() else {
+            return Some(true);
+        };
+        // a relative path's first component is in the working directory
+        if parent.as_os_str().is_empty() {
+            self.load_dir(Path::new(""));
+            return self.items.get(path).copied();
+        }
+
+        self.get(parent)?;
+        self.load_dir(parent);
+        self.items.get(path).copied()
+    }
+
+    fn load_dir(&mut self, path: &Path) {
+        if !self.loaded_dir_paths.insert(path.to_path_buf()) {
+            return;
+        }
+
+        let read_path = match path.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => path,
+        };
+        let Ok(dir_entries) = fs::read_dir(read_path) else {
+            return;
+        };
+        for entry in dir_entries.filter_map(Result::ok) {
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir()) || entry.path().is_dir();
+            self.items.insert(path.join(entry.file_name()), is_dir);
+        }
+    }
+
+    /// Loads a directory and everything in it, before it is moved, copied or deleted whole.
+    fn load_subtree(&mut self, path: &Path) {
+        if self.get(path) != Some(true) {
+            return;
+        }
+
+        self.load_dir(path);
+        for child_path in self.children(path) {
+            self.load_subtree(&child_path);
+        }
+    }
+
+    fn children(&self, path: &Path) -> Vec<PathBuf> {
+        self.items
+            .keys()
+            .filter(|item_path| item_path.parent() == Some(path))
+            .cloned()
+            .collect()
+    }
+
+    fn apply(&mut self, operation: &FileOperation) {
+        match operation {
+            FileOperation::CreateDirectory { path } => {
+                self.get(path);
+                self.loaded_dir_paths.insert(path.clone());
+            }
+            FileOperation::Move { source, destination }
+            | FileOperation::Copy { source, destination }
+            | FileOperation::HardLink { source, destination }
+            | FileOperation::Symlink { source, destination }
+            | FileOperation::Reflink { source, destination } => {
+                self.load_subtree(source);
+                self.get(destination);
+                let moved_dir_paths: Vec<PathBuf> = self
+                    .loaded_dir_paths
+                    .iter()
+                    .filter_map(|dir_path| rebase(dir_path, source, destination))
+                    .collect();
+                self.loaded_dir_paths.extend(moved_dir_paths);
+            }
+            FileOperation::Delete { path, .. } => self.load_subtree(path),
+            FileOperation::WriteFile { path, .. } => {
+                self.get(path);
+            }
+        }
+
+        apply_to_tree(operation, &mut self.items);
+        // links to a path that was canonicalized have no subtree to carry over
+        if let FileOperation::Symlink { destination, .. } = operation {
+            self.items.entry(destination.clone()).or_insert(false);
+        }
+    }
+}
+
+/// Renames an item, falling back to copying, verifying and deleting it when the destination
+/// is on another filesystem.
+pub fn move_path(source: &Path, destination: &Path) -> io::Result<()> {
+    match fs::rename(source, destination) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let destination_existed = destination.exists();
+            if let Err(e) = copy_verified(source, destination) {
+                // never leave a partial copy behind, the source is still intact
+                if !destination_existed {
+                    let _ = remove_path(destination);
+                }
+                return Err(e);
+            }
+            remove_path(source)
+        }
+        result => result,
+    }
+}
+
+/// Copies a file or directory tree, checking that every file arrived with its full size.
+fn copy_verified(source: &Path, destination: &Path) -> io::Result<()> {
+    let metadata = fs::metadata(source)?;
+    if metadata.is_dir() {
+        fs::create_dir(destination)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_verified(&entry.path(), &destination.join(entry.file_name()))?;
+        }
+        return Ok(());
+    }
+
+    let copied_size = fs::copy(source, destination)?;
+    if copied_size != metadata.len() || fs::metadata(destination)?.len() != metadata.len() {
+        return Err(io::Error::other(format!(
+            "Copy of {:?} to {:?} is incomplete",
+            source, destination
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Symlinks are only supported on Unix"))
+}
+
+fn remove_path(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+fn describe_operation(operation: &FileOperation) -> String {
+    match operation {
+        FileOperation::CreateDirectory { path } => {
+            format!("{color_blue}create{color_reset} {:?}", path)
+        }
+        FileOperation::Move { source, destination } => {
+            format!("{color_green}move{color_reset}   {:?} -> {:?}", source, destination)
+        }
+        FileOperation::Copy { source, destination } => {
+            format!("{color_green}copy{color_reset}   {:?} -> {:?}", source, destination)
+        }
+        FileOperation::HardLink { source, destination } => {
+            format!("{color_green}link{color_reset}   {:?} -> {:?}", source, destination)
+        }
+        FileOperation::Symlink { source, destination } => {
+            format!("{color_green}symlink{color_reset} {:?} -> {:?}", source, destination)
+        }
+        FileOperation::Reflink { source, destination } => {
+            format!("{color_green}reflink{color_reset} {:?} -> {:?}", source, destination)
+        }
+        FileOperation::Delete { path, .. } => format!("{color_red}delete{color_reset} {:?}", path),
+        FileOperation::WriteFile { path, .. } => {
+            format!("{color_blue}write{color_reset}  {:?}", path)
+        }
+    }
+}
+
+fn collect_tree(dir_path: &Path, tree: &mut BTreeMap<PathBuf, bool>) {
+    let Ok(dir_entries) = fs::read_dir(dir_path) else {
+        return;
+    };
+
+    for entry in dir_entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let is_dir = path.is_dir();
+        tree.insert(path.clone(), is_dir);
+        if is_dir {
+            collect_tree(&path, tree);
+        }
+    }
+}
+
+fn apply_to_tree(operation: &FileOperation, tree: &mut BTreeMap<PathBuf, bool>) {
+    match operation {
+        FileOperation::CreateDirectory { path } => {
+            for ancestor in path.ancestors() {
+                tree.entry(ancestor.to_path_buf()).or_insert(true);
+            }
+        }
+        FileOperation::Move { source, destination } => {
+            let moved = take_subtree(source, tree, true);
+            insert_subtree(source, destination, moved, tree);
+        }
+        FileOperation::Copy { source, destination }
+        | FileOperation::HardLink { source, destination }
+        | FileOperation::Symlink { source, destination }
+        | FileOperation::Reflink { source, destination } => {
+            let copied = take_subtree(source, tree, false);
+            insert_subtree(source, destination, copied, tree);
+        }
+        FileOperation::Delete { path, .. } => {
+            take_subtree(path, tree, true);
+        }
+        FileOperation::WriteFile { path, .. } => {
+            tree.insert(path.clone(), false);
+        }
+    }
+}
+
+fn take_subtree(root: &Path, tree: &mut BTreeMap<PathBuf, bool>, remove: bool) -> Vec<(PathBuf, bool)> {
+    let subtree: Vec<(PathBuf, bool)> = tree
+        .iter()
+        .filter(|(path, _)| path.starts_with(root))
+        .map(|(path, is_dir)| (path.clone(), *is_dir))
+        .collect();
+
+    if remove {
+        for (path, _) in &subtree {
+            tree.remove(path);
+        }
+    }
+
+    subtree
+}
+
+fn insert_subtree(
+    source: &Path,
+    destination: &Path,
+    subtree: Vec<(PathBuf, bool)>,
+    tree: &mut BTreeMap<PathBuf, bool>,
+) {
+    for (path, is_dir) in subtree {
+        tree.insert(rebase(&path, source, destination).unwrap(), is_dir);
+    }
+}
+
+/// The path under `to` that `path` has under `from`, if it is under `from` at all.
+fn rebase(path: &Path, from: &Path, to: &Path) -> Option<PathBuf> {
+    let relative_path = path.strip_prefix(from).ok()?;
+    match relative_path.as_os_str().is_empty() {
+        true => Some(to.to_path_buf()),
+        false => Some(to.join(relative_path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dry_run_reads_the_planned_tree() {
+        let root = std::env::temp_dir().join(format!("media-organizer-planned-tree-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("Alien.1979.mkv"), "movie").unwrap();
+
+        let ops = FileOperations::new(true, false);
+        let movie_dir_path = root.join("Alien (1979)");
+        ops.create_dir(&movie_dir_path).unwrap();
+        ops.rename(root.join("Alien.1979.mkv"), movie_dir_path.join("Alien.1979.mkv")).unwrap();
+
+        assert_eq!(ops.read_dir(&root).unwrap(), vec![movie_dir_path.clone()]);
+        assert!(ops.is_dir(&movie_dir_path));
+        assert!(ops.is_file(&movie_dir_path.join("Alien.1979.mkv")));
+        assert!(!ops.exists(&root.join("Alien.1979.mkv")));
+        assert_eq!(ops.current_path(&movie_dir_path.join("Alien.1979.mkv")), root.join("Alien.1979.mkv"));
+        assert!(root.join("Alien.1979.mkv").is_file());
+        assert!(!movie_dir_path.exists());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir_path = std::env::temp_dir().join(format!("media-organizer-transfer-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir_path);
+        fs::create_dir_all(dir_path.join("downloads")).unwrap();
+        fs::create_dir_all(dir_path.join("library")).unwrap();
+        fs::write(dir_path.join("downloads").join("Alien.1979.mkv"), "movie").unwrap();
+        dir_path
+    }
+
+    #[test]
+    fn only_moving_takes_the_source_away() {
+        let modes = [
+            TransferMode::Move,
+            TransferMode::Hardlink,
+            TransferMode::Symlink,
+            TransferMode::Reflink,
+            TransferMode::Copy,
+        ];
+        for mode in modes {
+            let dir_path = test_dir(&format!("{:?}", mode).to_lowercase());
+            let source = dir_path.join("downloads").join("Alien.1979.mkv");
+            let destination = dir_path.join("library").join("Alien (1979).mkv");
+
+            FileOperations::new(false, false).transfer(&source, &destination, mode).unwrap();
+            assert_eq!(fs::read_to_string(&destination).unwrap(), "movie", "{:?}", mode);
+            assert_eq!(source.exists(), mode != TransferMode::Move, "{:?}", mode);
+            let _ = fs::remove_dir_all(&dir_path);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_share_the_file_or_point_at_it() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir_path = test_dir("links");
+        let source = dir_path.join("downloads").join("Alien.1979.mkv");
+        let ops = FileOperations::new(false, false);
+
+        let hard_link = dir_path.join("library").join("hardlink.mkv");
+        ops.transfer(&source, &hard_link, TransferMode::Hardlink).unwrap();
+        assert_eq!(fs::metadata(&hard_link).unwrap().ino(), fs::metadata(&source).unwrap().ino());
+
+        let symlink = dir_path.join("library").join("symlink.mkv");
+        ops.transfer(&source, &symlink, TransferMode::Symlink).unwrap();
+        assert_eq!(fs::read_link(&symlink).unwrap(), fs::canonicalize(&source).unwrap());
+
+        // most filesystems, like ext4 and tmpfs, can't clone and get a full copy
+        let clone = dir_path.join("library").join("reflink.mkv");
+        ops.transfer(&source, &clone, TransferMode::Reflink).unwrap();
+        assert_ne!(fs::metadata(&clone).unwrap().ino(), fs::metadata(&source).unwrap().ino());
+        assert_eq!(fs::read_to_string(&clone).unwrap(), "movie");
+        let _ = fs::remove_dir_all(&dir_path);
+    }
+
+    /// Needs a second filesystem at `/dev/shm`, as most Linux systems have.
+    #[cfg(unix)]
+    #[test]
+    fn moves_across_filesystems_copy_and_remove_the_source() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir_path = test_dir("cross-device");
+        let other_device_path = Path::new("/dev/shm").join(format!("media-organizer-cross-device-{}", std::process::id()));
+        let same_device = |path: &Path| fs::metadata(path).is_ok_and(|metadata| metadata.dev() == fs::metadata(&dir_path).unwrap().dev());
+        if same_device(Path::new("/dev/shm")) || !Path::new("/dev/shm").is_dir() {
+            let _ = fs::remove_dir_all(&dir_path);
+            return;
+        }
+
+        fs::create_dir_all(dir_path.join("downloads").join("Alien (1979)").join("Subs")).unwrap();
+        fs::write(dir_path.join("downloads").join("Alien (1979)").join("Subs").join("en.srt"), "subtitle").unwrap();
+        move_path(&dir_path.join("downloads").join("Alien (1979)"), &other_device_path).unwrap();
+
+        assert!(!dir_path.join("downloads").join("Alien (1979)").exists());
+        assert_eq!(fs::read_to_string(other_device_path.join("Subs").join("en.srt")).unwrap(), "subtitle");
+        let _ = fs::remove_dir_all(&other_device_path);
+        let _ = fs::remove_dir_all(&dir_path);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::config::HttpConfig;
+use inline_colorization::{color_reset, color_yellow};
+use reqwest::{Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::{sleep_until, Instant};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// One client shared by every lookup so connections are reused, requests to each host are
+/// spaced out, and throttled or failed requests are retried with exponential backoff.
+pub struct HttpClient {
+    client: reqwest::Client,
+    max_retries: u32,
+    default_interval: Duration,
+    host_intervals: HashMap<String, Duration>,
+    /// When the next request to each host may go out
+    next_request_at: Mutex<HashMap<String, Instant>>,
+}
+
+impl HttpClient {
+    pub fn new(config: &HttpConfig) -> Result<HttpClient, Box<dyn Error>> {
+        let client = reqwest::Client::builder()
+            .user_agent(&config.user_agent)
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .build()?;
+
+        let mut host_intervals = HashMap::new();
+        for (host, requests_per_second) in &config.rate_limits {
+            host_intervals.insert(host.to_lowercase(), interval_for(*requests_per_second)?);
+        }
+
+        Ok(HttpClient {
+            client,
+            max_retries: config.max_retries,
+            default_interval: interval_for(config.requests_per_second)?,
+            host_intervals,
+            next_request_at: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub async fn get_text(&self, url: &str) -> Result<String, Box<dyn Error>> {
+        let no_query: &[(&str, &str)] = &[];
+        Ok(self.get(url, no_query).await?.text().await?)
+    }
+
+    pub async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let no_query: &[(&str, &str)] = &[];
+        Ok(self.get(url, no_query).await?.bytes().await?.to_vec())
+    }
+
+    pub async fn get_json<T: DeserializeOwned, Q: Serialize + ?Sized>(
+        &self,
+        url: &str,
+        query: &Q,
+    ) -> Result<T, Box<dyn Error>> {
+        Ok(self.get(url, query).await?.json().await?)
+    }
+
+    /// Sends a GET request, retrying on rate limiting, server errors and network failures.
+    pub async fn get<Q: Serialize + ?Sized>(&self, url: &str, query: &Q) -> Result<Response, Box<dyn Error>> {
+        let host = Url::parse(url)?.host_str().unwrap_or_default().to_lowercase();
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+
+        loop {
+            self.wait_for_turn(&host).await;
+            let result = self.client.get(url).query(query).send().await;
+
+            let retry_after = match &result {
+                Ok(response) if is_retryable_status(response.status()) => {
+                    Some(get_retry_after(response).unwrap_or(backoff))
+                }
+                Err(e) if e.is_timeout() || e.is_connect() || e.is_request() => Some(backoff),
+                _ => None,
+            };
+
+            match retry_after {
+                Some(delay) if attempt < self.max_retries => {
+                    attempt += 1;
+                    println!(
+                        "{color_yellow}Request to {} failed, retrying in {}s ({}/{}){color_reset}",
+                        host,
+                        delay.as_secs_f32(),
+                        attempt,
+                        self.max_retries
+                    );
+                    tokio::time::sleep(delay).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+                _ => return Ok(result?.error_for_status()?),
+            }
+        }
+    }
+
+    /// Waits until the host's rate limit allows another request and reserves that slot.
+    async fn wait_for_turn(&self, host: &str) {
+        let interval = self.host_intervals.get(host).copied().unwrap_or(self.default_interval);
+        let scheduled_at = {
+            let mut next_request_at = self.next_request_at.lock().unwrap();
+            let now = Instant::now();
+            let scheduled_at = next_request_at.get(host).copied().filter(|at| *at > now).unwrap_or(now);
+            next_request_at.insert(host.to_string(), scheduled_at + interval);
+            scheduled_at
+        };
+
+        sleep_until(scheduled_at).await;
+    }
+}
+
+/// The gap between requests at the given rate, which fails for rates too small to wait for.
+pub fn interval_for(requests_per_second: f32) -> Result<Duration, String> {
+    Duration::try_from_secs_f32(1.0 / requests_per_second)
+        .map_err(|_| format!("{} requests per second can't be waited for", requests_per_second))
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Servers that throttle usually say how long to back off, in seconds.
+fn get_retry_after(response: &Response) -> Option<Duration> {
+    let seconds: u64 = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+
+    Some(Duration::from_secs(seconds).min(MAX_BACKOFF))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    /// A local server standing in for a site, answering each request with the captured
+    /// response of the first path prefix it starts with, or a 404. Returns its base URL and
+    /// the paths requested so far, queries included.
+    pub(crate) fn serve(responses: Vec<(&'static str, u16, &'static str)>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requested_paths = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded_paths = Arc::clone(&requested_paths);
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().filter_map(Result::ok) {
+                let mut lines = BufReader::new(stream.try_clone().unwrap()).lines().map_while(Result::ok);
+                let request_line = lines.next().unwrap_or_default();
+                // the headers end at the first empty line
+                lines.find(|line| line.is_empty());
+
+                let path = request_line.split(' ').nth(1).unwrap_or_default().to_string();
+                let (status, body) = responses
+                    .iter()
+                    .find(|(prefix, _, _)| path.starts_with(prefix))
+                    .map_or((404, ""), |(_, status, body)| (*status, *body));
+                recorded_paths.lock().unwrap().push(path);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {} Canned\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+            }
+        });
+
+        (base_url, requested_paths)
+    }
+
+    /// A client that fails at once instead of retrying, and doesn't space requests out.
+    pub(crate) fn test_http() -> Arc<HttpClient> {
+        let config = HttpConfig { max_retries: 0, requests_per_second: 1000.0, ..HttpConfig::default() };
+        Arc::new(HttpClient::new(&config).unwrap())
+    }
+
+    #[test]
+    fn rates_too_small_to_wait_for_are_an_error() {
+        assert_eq!(interval_for(4.0), Ok(Duration::from_millis(250)));
+        assert!(interval_for(1e-40).is_err());
+        assert!(interval_for(0.0).is_err());
+    }
+}
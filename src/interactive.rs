@@ -0,0 +1,201 @@
+use crate::metadata_providers::scoring::confident_match;
+use crate::metadata_providers::{find_movie, find_movie_by_imdb_id, MatchMode, MetadataProvider, MovieMatch};
+use crate::models::MovieMetadata;
+use inline_colorization::{color_cyan, color_reset, color_yellow, style_bold, style_reset};
+use regex::Regex;
+use std::io;
+use std::io::{BufRead, Write};
+
+/// Settles on one of the ranked candidates for a movie the way the match mode says, asking
+/// through `input` when it is interactive. Returns `None` when none of them is used.
+pub async fn settle_match(
+    metadata: &MovieMetadata,
+    candidates: Vec<MovieMatch>,
+    providers: &[Box<dyn MetadataProvider>],
+    match_mode: MatchMode,
+    confidence_threshold: f32,
+    input: &mut impl BufRead,
+) -> Option<MovieMatch> {
+    if let Some(movie) = confident_match(&candidates, confidence_threshold) {
+        return Some(movie.clone());
+    }
+
+    match match_mode {
+        MatchMode::Best => candidates.first().cloned(),
+        MatchMode::Interactive => choose_match(metadata, candidates, providers, input).await,
+        MatchMode::Confident => {
+            if let Some(movie) = candidates.first() {
+                println!(
+                    "{color_yellow}Not using {:?}, the match is too uncertain ({:.0}% confident){color_reset}",
+                    movie.title,
+                    movie.confidence * 100.0
+                );
+            }
+            None
+        }
+    }
+}
+
+/// Shows the candidates for a movie and lets the user pick one, search again, enter an IMDb id
+/// or skip the lookup. Returns the chosen movie, or `None` when skipped.
+async fn choose_match(
+    metadata: &MovieMetadata,
+    mut candidates: Vec<MovieMatch>,
+    providers: &[Box<dyn MetadataProvider>],
+    input: &mut impl BufRead,
+) -> Option<MovieMatch> {
+    let imdb_id_pattern = Regex::new(r"^tt\d{7,}$").unwrap();
+
+    loop {
+        println!();
+        println!("{style_bold}Choose a match for {:?}:{style_reset}", metadata.original_file_name);
+        if candidates.is_empty() {
+            println!("  {color_yellow}No candidates found{color_reset}");
+        }
+        for (index, candidate) in candidates.iter().enumerate() {
+            println!("  {}) {}", index + 1, describe_candidate(candidate));
+        }
+
+        let answer = read_answer("Enter a number, an IMDb id, a title to search for, or nothing to skip: ", input)?;
+        if answer.is_empty() {
+            println!("{color_yellow}Skipped the lookup{color_reset}");
+            return None;
+        }
+
+        if let Ok(number) = answer.parse::<usize>() {
+            match candidates.get(number.wrapping_sub(1)) {
+                // confirmed by the user, so no longer in doubt
+                Some(candidate) => return Some(MovieMatch { confidence: 1.0, ..candidate.clone() }),
+                None => println!("{color_yellow}There is no candidate {}{color_reset}", number),
+            }
+            continue;
+        }
+
+        if imdb_id_pattern.is_match(&answer) {
+            match find_movie_by_imdb_id(providers, &answer).await {
+                Some(movie) => return Some(movie),
+                None => {
+                    println!("{color_yellow}No details found for {}, using the id as it is{color_reset}", answer);
+                    return Some(MovieMatch {
+                        title: metadata.media_name.clone(),
+                        year: metadata.release_year,
+                        imdb_id: Some(answer.clone()),
+                        id: answer,
+                        kind: None,
+                        confidence: 1.0,
+                    });
+                }
+            }
+        }
+
+        candidates = find_movie(providers, &answer, None)
+            .await
+            .map(|(_, candidates)| candidates)
+            .unwrap_or_default();
+    }
+}
+
+fn describe_candidate(candidate: &MovieMatch) -> String {
+    let year = candidate.year.map(|year| format!(" ({})", year)).unwrap_or_default();
+    let kind = candidate.kind.as_ref().map(|kind| format!(" [{}]", kind)).unwrap_or_default();
+    let id = candidate.imdb_id.as_deref().unwrap_or(&candidate.id);
+
+    format!(
+        "{}{}{} {color_cyan}{}{color_reset} {:.0}%",
+        candidate.title,
+        year,
+        kind,
+        id,
+        candidate.confidence * 100.0
+    )
+}
+
+/// Returns `None` when the input is closed, which counts as skipping.
+fn read_answer(prompt: &str, input: &mut impl BufRead) -> Option<String> {
+    print!("{}", prompt);
+    io::stdout().flush().ok()?;
+
+    let mut answer = String::new();
+    match input.read_line(&mut answer) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(answer.trim().to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> MovieMetadata {
+        MovieMetadata {
+            original_file_name: "Alien.1979.mkv".to_string(),
+            media_name: "Alien".to_string(),
+            release_year: Some(1979),
+            edition: None,
+            encoding_format: None,
+            resolution: None,
+            additional_data: Vec::new(),
+            file_extension: "mkv".to_string(),
+            imdb_id: None,
+            movie_hash: None,
+            duration_seconds: None,
+            audio_tracks: Vec::new(),
+            subtitle_tracks: Vec::new(),
+        }
+    }
+
+    fn candidates() -> Vec<MovieMatch> {
+        [("Alien", 1979, "tt0078748", 0.75), ("Aliens", 1986, "tt0090605", 0.6)]
+            .into_iter()
+            .map(|(title, year, imdb_id, confidence)| MovieMatch {
+                title: title.to_string(),
+                year: Some(year),
+                imdb_id: Some(imdb_id.to_string()),
+                id: imdb_id.to_string(),
+                kind: None,
+                confidence,
+            })
+            .collect()
+    }
+
+    async fn settle(match_mode: MatchMode, answers: &str) -> Option<MovieMatch> {
+        settle_match(&metadata(), candidates(), &[], match_mode, 0.8, &mut answers.as_bytes()).await
+    }
+
+    #[tokio::test]
+    async fn a_chosen_candidate_is_taken_as_certain() {
+        let movie = settle(MatchMode::Interactive, "2\n").await.unwrap();
+        assert_eq!((movie.title.as_str(), movie.confidence), ("Aliens", 1.0));
+    }
+
+    #[tokio::test]
+    async fn an_empty_answer_or_closed_input_skips() {
+        assert!(settle(MatchMode::Interactive, "\n1\n").await.is_none());
+        assert!(settle(MatchMode::Interactive, "").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn an_imdb_id_is_used_as_it_is_when_no_provider_knows_it() {
+        let movie = settle(MatchMode::Interactive, "tt0078748\n").await.unwrap();
+        assert_eq!((movie.title.as_str(), movie.imdb_id.as_deref()), ("Alien", Some("tt0078748")));
+    }
+
+    #[tokio::test]
+    async fn invalid_answers_ask_again() {
+        // a search without providers leaves no candidates to choose from
+        assert!(settle(MatchMode::Interactive, "3\nAlien 1979\n1\n").await.is_none());
+        let movie = settle(MatchMode::Interactive, "0\n3\n1\n").await.unwrap();
+        assert_eq!(movie.title, "Alien");
+    }
+
+    #[tokio::test]
+    async fn only_confident_matches_are_used_without_asking() {
+        assert!(settle(MatchMode::Confident, "1\n").await.is_none());
+        assert_eq!(settle(MatchMode::Best, "").await.unwrap().title, "Alien");
+
+        let mut confident_candidates = candidates();
+        confident_candidates[0].confidence = 0.9;
+        let movie = settle_match(&metadata(), confident_candidates, &[], MatchMode::Confident, 0.8, &mut "".as_bytes()).await;
+        assert_eq!(movie.unwrap().title, "Alien");
+    }
+}
@@ -0,0 +1,251 @@
+use crate::constants::JOURNAL_FILE_NAME;
+use crate::file_operations::{FileOperation, FileOperations};
+use crate::quarantine::restore;
+use inline_colorization::{color_green, color_red, color_reset, color_yellow};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Deserialize, Serialize)]
+struct JournalEntry {
+    run_id: String,
+    operation: FileOperation,
+}
+
+/// Append-only record of the operations performed on a library, one JSON entry per line.
+pub struct Journal {
+    library_root: PathBuf,
+    run_id: String,
+}
+
+impl Journal {
+    pub fn new(library_root: &Path) -> Journal {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis())
+            .unwrap_or_default();
+
+        Journal {
+            library_root: library_root.to_path_buf(),
+            run_id: started_at.to_string(),
+        }
+    }
+
+    pub fn library_root(&self) -> &Path {
+        &self.library_root
+    }
+
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    pub fn record(&self, operation: &FileOperation) -> io::Result<()> {
+        let entry = JournalEntry {
+            run_id: self.run_id.clone(),
+            operation: operation.clone(),
+        };
+        let line = serde_json::to_string(&entry).map_err(io::Error::other)?;
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.library_root.join(JOURNAL_FILE_NAME))?;
+        writeln!(file, "{}", line)
+    }
+}
+
+/// Reverts the most recent run recorded in the library's journal.
+pub fn undo_last_run(
+    library_root: &Path,
+    quarantine_path: Option<&Path>,
+    ops: &FileOperations,
+) -> io::Result<()> {
+    let journal_path = library_root.join(JOURNAL_FILE_NAME);
+    if !journal_path.exists() {
+        println!("{color_yellow}Nothing to undo, no journal found in {:?}{color_reset}", library_root);
+        return Ok(());
+    }
+
+    let mut entries: Vec<JournalEntry> = fs::read_to_string(&journal_path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(io::Error::other))
+        .collect::<io::Result<_>>()?;
+
+    let Some(run_id) = entries.last().map(|entry| entry.run_id.clone()) else {
+        println!("{color_yellow}Nothing to undo, the journal is empty{color_reset}");
+        return Ok(());
+    };
+
+    let split_at = entries
+        .iter()
+        .position(|entry| entry.run_id == run_id)
+        .unwrap_or(entries.len());
+    let run_entries = entries.split_off(split_at);
+
+    println!("Undoing {} operations from run {}", run_entries.len(), run_id);
+    for entry in run_entries.iter().rev() {
+        if let Err(e) = undo_operation(&entry.operation, ops) {
+            eprintln!("{color_red}Failed to undo {:?}: {}{color_reset}", entry.operation, e);
+        }
+    }
+
+    if ops.is_dry_run() {
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        fs::remove_file(&journal_path)?;
+    } else {
+        let remaining_lines: Vec<String> = entries
+            .iter()
+            .map(|entry| serde_json::to_string(entry).map_err(io::Error::other))
+            .collect::<io::Result<_>>()?;
+        fs::write(&journal_path, remaining_lines.join("\n") + "\n")?;
+    }
+
+    if let Some(quarantine_path) = quarantine_path {
+        remove_empty_directories(&quarantine_path.join(&run_id));
+    }
+
+    println!("{color_green}Undo complete{color_reset}");
+    Ok(())
+}
+
+fn undo_operation(operation: &FileOperation, ops: &FileOperations) -> io::Result<()> {
+    match operation {
+        FileOperation::CreateDirectory { path } => {
+            if path.is_dir() && fs::read_dir(path)?.next().is_none() {
+                ops.remove_dir(path)?;
+            }
+        }
+        FileOperation::Move { source, destination } => {
+            create_parent_dir(source, ops)?;
+            ops.rename(destination, source)?;
+        }
+        FileOperation::Copy { destination, .. }
+        | FileOperation::HardLink { destination, .. }
+        | FileOperation::Symlink { destination, .. }
+        | FileOperation::Reflink { destination, .. } => {
+            remove_path(destination, ops)?;
+        }
+        FileOperation::Delete { path, holding_path } => match holding_path {
+            Some(holding_path) => restore(holding_path, path, ops)?,
+            None => {
+                println!("{color_yellow}Cannot restore permanently deleted {:?}{color_reset}", path);
+            }
+        },
+        FileOperation::WriteFile { path, previous_path } => {
+            ops.remove_file(path)?;
+            if let Some(previous_path) = previous_path {
+                restore(previous_path, path, ops)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn create_parent_dir(path: &Path, ops: &FileOperations) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) => ops.create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+fn remove_path(path: &Path, ops: &FileOperations) -> io::Result<()> {
+    // a symlink is removed itself, never the directory it points to
+    if path.is_dir() && !path.is_symlink() {
+        ops.remove_dir_all(path)
+    } else {
+        ops.remove_file(path)
+    }
+}
+
+fn remove_empty_directories(dir_path: &Path) {
+    let Ok(dir_entries) = fs::read_dir(dir_path) else {
+        return;
+    };
+
+    for entry in dir_entries.filter_map(Result::ok) {
+        if entry.path().is_dir() {
+            remove_empty_directories(&entry.path());
+        }
+    }
+
+    let _ = fs::remove_dir(dir_path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::QUARANTINE_DIRECTORY_NAME;
+    use crate::quarantine::DeletionPolicy;
+
+    fn library(name: &str) -> PathBuf {
+        let library_root = std::env::temp_dir().join(format!("media-organizer-journal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&library_root);
+        fs::create_dir_all(&library_root).unwrap();
+        fs::write(library_root.join("Alien.1979.mkv"), "movie").unwrap();
+        fs::write(library_root.join("Alien.1979.en.srt"), "subtitle").unwrap();
+        fs::write(library_root.join("Alien.1979.nfo"), "info").unwrap();
+        library_root
+    }
+
+    /// Every file under the library with its contents, leaving out the quarantine.
+    fn snapshot(dir_path: &Path, library_root: &Path, files: &mut Vec<(PathBuf, String)>) {
+        for entry in fs::read_dir(dir_path).unwrap().map(Result::unwrap) {
+            let path = entry.path();
+            if path.ends_with(QUARANTINE_DIRECTORY_NAME) {
+                continue;
+            }
+            match path.is_dir() {
+                true => snapshot(&path, library_root, files),
+                false => files.push((path.strip_prefix(library_root).unwrap().to_path_buf(), fs::read_to_string(&path).unwrap())),
+            }
+        }
+        files.sort();
+    }
+
+    #[test]
+    fn undo_restores_the_library_as_it_was() {
+        let library_root = library("undo");
+        let quarantine_path = library_root.join(QUARANTINE_DIRECTORY_NAME);
+        let mut before = Vec::new();
+        snapshot(&library_root, &library_root, &mut before);
+
+        let ops = FileOperations::with_journal(false, false, &library_root, DeletionPolicy::Quarantine(quarantine_path.clone()));
+        let movie_dir_path = library_root.join("Alien (1979)");
+        ops.create_dir(&movie_dir_path).unwrap();
+        ops.rename(library_root.join("Alien.1979.mkv"), movie_dir_path.join("Alien (1979).mkv")).unwrap();
+        ops.copy(library_root.join("Alien.1979.en.srt"), movie_dir_path.join("Alien (1979).en.srt")).unwrap();
+        ops.remove_file(&library_root.join("Alien.1979.nfo")).unwrap();
+        assert!(!library_root.join("Alien.1979.nfo").exists());
+
+        undo_last_run(&library_root, Some(&quarantine_path), &FileOperations::new(false, false)).unwrap();
+        let mut after = Vec::new();
+        snapshot(&library_root, &library_root, &mut after);
+        assert_eq!(after, before);
+        assert!(!movie_dir_path.exists());
+        assert!(!library_root.join(JOURNAL_FILE_NAME).exists());
+        let _ = fs::remove_dir_all(&library_root);
+    }
+
+    #[test]
+    fn dry_runs_are_not_journaled() {
+        let library_root = library("dry-run");
+
+        let ops = FileOperations::with_journal(true, false, &library_root, DeletionPolicy::Delete);
+        ops.create_dir(&library_root.join("Alien (1979)")).unwrap();
+        ops.rename(library_root.join("Alien.1979.mkv"), library_root.join("Alien (1979)").join("Alien (1979).mkv")).unwrap();
+        ops.remove_file(&library_root.join("Alien.1979.nfo")).unwrap();
+
+        assert!(!library_root.join(JOURNAL_FILE_NAME).exists());
+        assert!(library_root.join("Alien.1979.mkv").is_file());
+        assert!(library_root.join("Alien.1979.nfo").is_file());
+        let _ = fs::remove_dir_all(&library_root);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::subtitle_formats::{decode, is_text};
+use regex::Regex;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// How much of a subtitle is read to tell its language from the text.
+const SAMPLE_SIZE: u64 = 64 * 1024;
+
+/// Fewer common words than this is too little text to go by.
+const MIN_STOPWORD_HITS: usize = 10;
+
+/// Tokens that may follow a language tag in a subtitle's name, like `movie.en.forced.srt`
+/// or `movie.pt-br.srt`.
+const TAG_SUFFIXES: [&str; 9] = ["forced", "sdh", "cc", "default", "full", "br", "gb", "us", "latam"];
+
+/// Marks subtitles for the hearing impaired after a language tag, as in `movie.en.hi.srt`, but
+/// is the code for Hindi on its own.
+const HEARING_IMPAIRED_SUFFIX: &str = "hi";
+
+pub struct Language {
+    /// ISO 639-1, used when naming files
+    pub code: &'static str,
+    /// ISO 639-2/B, as used by OpenSubtitles
+    pub bibliographic_code: &'static str,
+    /// ISO 639-2/T, which differs from the bibliographic code for a few languages
+    pub terminology_code: &'static str,
+    /// English name first, then native names and common aliases
+    pub names: &'static [&'static str],
+}
+
+impl Language {
+    pub fn name(&self) -> &'static str {
+        self.names[0]
+    }
+}
+
+macro_rules! language {
+    ($code:literal, $bibliographic_code:literal, $terminology_code:literal, [$($name:literal),+]) => {
+        Language {
+            code: $code,
+            bibliographic_code: $bibliographic_code,
+            terminology_code: $terminology_code,
+            names: &[$($name),+],
+        }
+    };
+}
+
+pub const LANGUAGES: [Language; 38] = [
+    language!("en", "eng", "eng", ["English"]),
+    language!("fr", "fre", "fra", ["French", "Français", "Francais"]),
+    language!("de", "ger", "deu", ["German", "Deutsch"]),
+    language!("es", "spa", "spa", ["Spanish", "Español", "Espanol", "Castellano", "Latino"]),
+    language!("it", "ita", "ita", ["Italian", "Italiano"]),
+    language!("pt", "por", "por", ["Portuguese", "Português", "Portugues", "Brazilian"]),
+    language!("nl", "dut", "nld", ["Dutch", "Nederlands", "Flemish"]),
+    language!("sv", "swe", "swe", ["Swedish", "Svenska"]),
+    language!("no", "nor", "nor", ["Norwegian", "Norsk", "Bokmål", "Bokmal"]),
+    language!("da", "dan", "dan", ["Danish", "Dansk"]),
+    language!("fi", "fin", "fin", ["Finnish", "Suomi"]),
+    language!("pl", "pol", "pol", ["Polish", "Polski"]),
+    language!("cs", "cze", "ces", ["Czech", "Čeština", "Cestina"]),
+    language!("sk", "slo", "slk", ["Slovak", "Slovenčina", "Slovencina"]),
+    language!("hu", "hun", "hun", ["Hungarian", "Magyar"]),
+    language!("ro", "rum", "ron", ["Romanian", "Română", "Romana"]),
+    language!("el", "gre", "ell", ["Greek", "Ελληνικά"]),
+    language!("tr", "tur", "tur", ["Turkish", "Türkçe", "Turkce"]),
+    language!("ru", "rus", "rus", ["Russian", "Русский"]),
+    language!("uk", "ukr", "ukr", ["Ukrainian", "Українська"]),
+    language!("bg", "bul", "bul", ["Bulgarian", "Български"]),
+    language!("hr", "hrv", "hrv", ["Croatian", "Hrvatski"]),
+    language!("sr", "srp", "srp", ["Serbian", "Srpski", "Српски"]),
+    language!("sl", "slv", "slv", ["Slovenian", "Slovene", "Slovenščina"]),
+    language!("et", "est", "est", ["Estonian", "Eesti"]),
+    language!("lv", "lav", "lav", ["Latvian", "Latviešu"]),
+    language!("lt", "lit", "lit", ["Lithuanian", "Lietuvių"]),
+    language!("he", "heb", "heb", ["Hebrew", "עברית"]),
+    language!("ar", "ara", "ara", ["Arabic", "العربية"]),
+    language!("fa", "per", "fas", ["Persian", "Farsi", "فارسی"]),
+    language!("hi", "hin", "hin", ["Hindi", "हिन्दी"]),
+    language!("th", "tha", "tha", ["Thai", "ไทย"]),
+    language!("vi", "vie", "vie", ["Vietnamese", "Tiếng việt"]),
+    language!("id", "ind", "ind", ["Indonesian", "Bahasa indonesia"]),
+    language!("ms", "may", "msa", ["Malay", "Bahasa melayu"]),
+    language!("zh", "chi", "zho", ["Chinese", "中文", "Mandarin"]),
+    language!("ja", "jpn", "jpn", ["Japanese", "日本語"]),
+    language!("ko", "kor", "kor", ["Korean", "한국어"]),
+];
+
+/// Words common in everyday speech in each language written in the Latin script.
+const STOPWORDS: [(&str, &[&str]); 19] = [
+    ("en", &["the", "and", "you", "that", "what", "this", "have", "was", "are", "with", "your", "from", "there", "know", "just"]),
+    ("fr", &["le", "les", "et", "est", "vous", "je", "pas", "une", "des", "pour", "dans", "qui", "mais", "nous", "avec"]),
+    ("de", &["der", "die", "das", "und", "ist", "nicht", "ich", "sie", "ein", "mit", "auf", "den", "wir", "haben", "auch"]),
+    ("es", &["el", "los", "las", "es", "por", "una", "para", "pero", "está", "qué", "usted", "muy", "eso", "esto", "tengo"]),
+    ("it", &["il", "che", "non", "sono", "per", "questo", "cosa", "gli", "perché", "anche", "della", "sei", "ho", "hai"]),
+    ("pt", &["não", "você", "um", "uma", "com", "isso", "está", "mas", "eu", "do", "da", "ele", "tem", "muito", "aqui"]),
+    ("nl", &["het", "een", "ik", "je", "niet", "dat", "wat", "van", "hij", "zijn", "maar", "er", "hebben", "dit", "jij"]),
+    ("sv", &["och", "det", "att", "jag", "är", "inte", "som", "på", "har", "vi", "för", "vad", "han", "hon", "den"]),
+    ("no", &["og", "ikke", "jeg", "er", "hva", "meg", "deg", "har", "på", "vi", "det", "han", "hun", "til", "skal"]),
+    ("da", &["og", "ikke", "jeg", "er", "hvad", "mig", "dig", "har", "på", "vi", "det", "han", "hun", "til", "skal"]),
+    ("fi", &["on", "ja", "ei", "se", "että", "hän", "mitä", "mutta", "minä", "sinä", "tämä", "kun", "niin", "olen", "oli"]),
+    ("pl", &["nie", "to", "się", "jest", "że", "co", "na", "jak", "ale", "tak", "mnie", "ty", "czy", "już", "jestem"]),
+    ("cs", &["je", "to", "se", "na", "že", "ne", "jsem", "jak", "co", "ale", "tak", "jsi", "už", "mě", "není"]),
+    ("hu", &["az", "hogy", "nem", "és", "egy", "is", "van", "meg", "ez", "mi", "csak", "nincs", "igen", "mit", "vagy"]),
+    ("ro", &["și", "nu", "că", "în", "este", "pe", "la", "un", "ce", "eu", "mai", "ești", "asta", "sunt", "vreau"]),
+    ("tr", &["bir", "ve", "bu", "ne", "için", "değil", "ben", "sen", "mi", "çok", "ama", "var", "yok", "evet", "misin"]),
+    ("hr", &["je", "da", "ne", "se", "to", "što", "sam", "ali", "smo", "nije", "koji", "ovo", "si", "će", "sve"]),
+    ("id", &["yang", "tidak", "aku", "kau", "ini", "itu", "dan", "apa", "kita", "saya", "ada", "akan", "dengan", "kamu", "bisa"]),
+    ("vi", &["không", "tôi", "anh", "là", "có", "của", "một", "này", "được", "cô", "và", "đi", "những", "gì", "em"]),
+];
+
+/// Looks a language up by any of its ISO 639 codes or names, ignoring case.
+pub fn find_language(code_or_name: &str) -> Option<&'static Language> {
+    let code_or_name = code_or_name.trim().to_lowercase();
+    LANGUAGES.iter().find(|language| {
+        language.code == code_or_name
+            || language.bibliographic_code == code_or_name
+            || language.terminology_code == code_or_name
+            || language.names.iter().any(|name| name.to_lowercase() == code_or_name)
+    })
+}
+
+/// Tells a subtitle's language from its name or, failing that, from its text.
+pub fn detect_subtitle_language(path: &Path) -> Option<&'static Language> {
+    let file_name = path.file_stem()?.to_string_lossy();
+    detect_language_from_file_name(&file_name).or_else(|| {
+        let mut sample = Vec::new();
+        File::open(path).ok()?.take(SAMPLE_SIZE).read_to_end(&mut sample).ok()?;
+        // image based subtitles have no text to go by
+        if !is_text(&sample) {
+            return None;
+        }
+
+        let text = decode(&sample);
+        detect_language_from_vobsub_index(&text).or_else(|| detect_language_from_text(&text))
+    })
+}
+
+/// VobSub `.idx` files name the language of their first track in a line like `id: en, index: 0`.
+fn detect_language_from_vobsub_index(text: &str) -> Option<&'static Language> {
+    let id_line = text.lines().find_map(|line| line.trim().strip_prefix("id:"))?;
+    find_language(id_line.split(',').next()?)
+}
+
+/// Reads the language tag at the end of a name, like `Movie.2010.en`, `Movie.eng.forced` or
+/// `2_English`. Only the end is looked at so titles like "The Italian Job" are not mistaken
+/// for one. Two-letter codes read just like words and release groups, as in `Just.Do.It` or
+/// `Movie-NO`, so they only count when set off by a dot, underscore or space and written in a
+/// single case.
+pub fn detect_language_from_file_name(file_name: &str) -> Option<&'static Language> {
+    let tokens: Vec<(Option<char>, &str)> = Regex::new(r"[\p{L}\p{N}]+")
+        .unwrap()
+        .find_iter(file_name)
+        .map(|token| (file_name[..token.start()].chars().last(), token.as_str()))
+        .collect();
+
+    let is_tag_suffix = |index: usize| {
+        let token = tokens[index].1.to_lowercase();
+        match token == HEARING_IMPAIRED_SUFFIX {
+            true => index > 0 && find_language(tokens[index - 1].1).is_some(),
+            false => TAG_SUFFIXES.contains(&token.as_str()),
+        }
+    };
+    let (separator, tag) = (0..tokens.len())
+        .rev()
+        .take(3)
+        .find(|index| !is_tag_suffix(*index))
+        .map(|index| tokens[index])?;
+    let is_two_letter_code = tag.chars().count() == 2;
+    let is_single_case = tag.chars().all(char::is_lowercase) || tag.chars().all(char::is_uppercase);
+    let is_set_off = separator.is_none_or(|separator| matches!(separator, '.' | '_' | ' '));
+    if is_two_letter_code && !(is_single_case && is_set_off) {
+        return None;
+    }
+
+    find_language(tag)
+}
+
+/// Guesses the language of a text from its script, or from how often each language's most
+/// common words appear in it.
+pub fn detect_language_from_text(text: &str) -> Option<&'static Language> {
+    let letters: Vec<char> = text.chars().filter(|c| c.is_alphabetic()).collect();
+    let latin_count = letters.iter().filter(|c| c.is_ascii_alphabetic() || ('\u{00C0}'..='\u{024F}').contains(c)).count();
+    if letters.len() > latin_count * 2 {
+        return detect_language_from_script(&letters);
+    }
+
+    let text = text.to_lowercase();
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let mut hits: Vec<(usize, &str)> = STOPWORDS
+        .iter()
+        .map(|(code, stopwords)| (words.iter().filter(|word| stopwords.contains(word)).count(), *code))
+        .collect();
+    hits.sort_by_key(|(hit_count, _)| std::cmp::Reverse(*hit_count));
+
+    match hits.as_slice() {
+        [(best, code), (runner_up, _), ..] if *best >= MIN_STOPWORD_HITS && best > runner_up => find_language(code),
+        _ => None,
+    }
+}
+
+/// Languages with a script of their own, or that have letters no other language uses.
+fn detect_language_from_script(letters: &[char]) -> Option<&'static Language> {
+    let has_any = |range: std::ops::RangeInclusive<char>| letters.iter().any(|c| range.contains(c));
+    let count = |range: std::ops::RangeInclusive<char>| letters.iter().filter(|c| range.contains(c)).count();
+    let has_letter = |chars: &[char]| letters.iter().any(|c| chars.contains(c));
+
+    let scripts = [
+        (count('\u{0400}'..='\u{04FF}'), "ru"),
+        (count('\u{0370}'..='\u{03FF}'), "el"),
+        (count('\u{0590}'..='\u{05FF}'), "he"),
+        (count('\u{0600}'..='\u{06FF}'), "ar"),
+        (count('\u{0900}'..='\u{097F}'), "hi"),
+        (count('\u{0E00}'..='\u{0E7F}'), "th"),
+        (count('\u{AC00}'..='\u{D7AF}') + count('\u{1100}'..='\u{11FF}'), "ko"),
+        (count('\u{3040}'..='\u{30FF}') + count('\u{4E00}'..='\u{9FFF}'), "zh"),
+    ];
+    let (letter_count, code) = scripts.into_iter().max_by_key(|(letter_count, _)| *letter_count)?;
+    if letter_count == 0 {
+        return None;
+    }
+
+    let code = match code {
+        "ru" if has_letter(&['ђ', 'ћ', 'џ', 'љ', 'њ', 'ј']) => "sr",
+        "ru" if has_letter(&['і', 'ї', 'є', 'ґ']) => "uk",
+        "ru" if !has_letter(&['ы', 'э']) && has_letter(&['ъ']) => "bg",
+        "ar" if has_letter(&['پ', 'چ', 'ژ', 'گ']) => "fa",
+        // kana only appears in Japanese, which also uses Chinese characters
+        "zh" if has_any('\u{3040}'..='\u{30FF}') => "ja",
+        code => code,
+    };
+    find_language(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detected_code(file_name: &str) -> Option<&'static str> {
+        detect_language_from_file_name(file_name).map(|language| language.code)
+    }
+
+    #[test]
+    fn language_tags_at_the_end_of_the_name_are_read() {
+        assert_eq!(detected_code("Movie.2010.en"), Some("en"));
+        assert_eq!(detected_code("Movie.2010.EN"), Some("en"));
+        assert_eq!(detected_code("Movie.eng.forced"), Some("en"));
+        assert_eq!(detected_code("Movie.fr.sdh"), Some("fr"));
+        assert_eq!(detected_code("Movie.pt-br"), Some("pt"));
+        assert_eq!(detected_code("2_English"), Some("en"));
+        assert_eq!(detected_code("Movie.Spanish"), Some("es"));
+    }
+
+    #[test]
+    fn hi_is_hindi_unless_it_follows_a_language_tag() {
+        assert_eq!(detected_code("Movie.hi"), Some("hi"));
+        assert_eq!(detected_code("Movie.2010.hi.forced"), Some("hi"));
+        assert_eq!(detected_code("Movie.en.hi"), Some("en"));
+        assert_eq!(detected_code("Movie.eng.hi.forced"), Some("en"));
+    }
+
+    #[test]
+    fn words_and_release_groups_are_not_taken_for_two_letter_codes() {
+        assert_eq!(detected_code("Movie.2010.720p.WEB-NO"), None);
+        assert_eq!(detected_code("Just.Do.It"), None);
+        assert_eq!(detected_code("What.He"), None);
+        assert_eq!(detected_code("The.Id"), None);
+        assert_eq!(detected_code("The.Italian.Job.2003"), None);
+    }
+}
@@ -1,68 +1,259 @@
+mod cache;
+mod cli;
+mod config;
 mod constants;
-mod imdb;
+mod context;
+mod extras;
+mod file_operations;
+mod http;
+mod interactive;
+mod journal;
+mod languages;
+mod metadata_providers;
 mod models;
+mod naming;
+mod probe;
+mod quarantine;
 mod movie_processors;
+mod subtitle_formats;
+mod subtitle_sync;
 mod subtitles;
 mod utils;
 mod movies;
 mod tv_shows_processors;
 mod tv_shows;
 
-use std::env::args;
 use std::fs;
+use std::io;
 use std::path::Path;
-use inline_colorization::{color_green, color_reset, color_cyan};
-use crate::models::OrganizerMode;
+use std::sync::Arc;
+use clap::{CommandFactory, Parser};
+use inline_colorization::{color_green, color_red, color_reset, color_yellow};
+use crate::cache::{clear_cache, get_cache_stats, Cache};
+use crate::cli::{CacheCommand, Cli, Command, SubtitlesCommand};
+use crate::config::{same_path, Config};
+use crate::context::Context;
+use crate::file_operations::{FileOperations, TransferMode};
+use crate::http::HttpClient;
+use crate::journal::undo_last_run;
+use crate::metadata_providers::MatchMode;
 use crate::movies::handle_movies;
+use crate::quarantine::{purge, DeletionPolicy};
+use crate::subtitle_sync::{sync_subtitle, Retiming};
 use crate::tv_shows::handle_tv_shows;
 
 #[tokio::main]
 async fn main() {
-    let command_opt = args().nth(1);
+    let cli = Cli::parse();
 
-    if command_opt.is_none() {
-        print_help();
-        return;
+    match &cli.command {
+        Command::Completions { shell } => {
+            clap_complete::generate(*shell, &mut Cli::command(), "media-organizer", &mut io::stdout());
+        }
+        Command::Man => {
+            clap_mangen::Man::new(Cli::command())
+                .render(&mut io::stdout())
+                .expect("Failed to render the man page");
+        }
+        command => {
+            let config = Config::load(cli.config.as_deref()).unwrap_or_else(|e| {
+                eprintln!("{color_red}{}{color_reset}", e);
+                std::process::exit(1);
+            });
+
+            if let Command::Cache { action } = command {
+                handle_cache_command(action, &config);
+                return;
+            }
+            if let Command::Subtitles { action } = command {
+                handle_subtitles_command(action, &cli, &config);
+                return;
+            }
+
+            let cache = Arc::new(Cache::open(&config.cache, cli.offline, cli.dry_run));
+            let http = Arc::new(HttpClient::new(&config.http).unwrap_or_else(|e| {
+                eprintln!("{color_red}Failed to create the HTTP client: {}{color_reset}", e);
+                std::process::exit(1);
+            }));
+            for directory_path in command.directories() {
+                process_library(&cli, &config, &cache, &http, directory_path).await;
+                if !cli.dry_run {
+                    cache.flush();
+                }
+            }
+        }
     }
+}
 
-    let directory_opt = args().nth(2);
+fn handle_cache_command(action: &CacheCommand, config: &Config) {
+    match action {
+        CacheCommand::Clear => match clear_cache(&config.cache) {
+            Ok(entry_count) => println!("{color_green}Removed {} cached lookup(s){color_reset}", entry_count),
+            Err(e) => eprintln!("{color_red}Failed to clear the cache: {}{color_reset}", e),
+        },
+        CacheCommand::Stats => {
+            let Some(stats) = get_cache_stats(&config.cache) else {
+                println!("{color_yellow}No cache directory available{color_reset}");
+                return;
+            };
 
-    if directory_opt.is_none() {
-        print_help();
-        return;
+            println!("Cache file: {:?} ({} bytes)", stats.path, stats.size);
+            if stats.entries.is_empty() {
+                println!("{color_yellow}The cache is empty{color_reset}");
+            }
+            for (kind, total, fresh) in stats.entries {
+                println!("  {:<16} {:>6} cached, {:>6} fresh", kind, total, fresh);
+            }
+        }
+    }
+}
+
+fn handle_subtitles_command(action: &SubtitlesCommand, cli: &Cli, config: &Config) {
+    let SubtitlesCommand::Sync { subtitle, offset, from_fps, to_fps, reference } = action;
+    let subtitle_path = fs::canonicalize(subtitle).unwrap_or_else(|e| {
+        eprintln!("{color_red}Failed to read {:?}: {}{color_reset}", subtitle, e);
+        std::process::exit(1);
+    });
+    // journaled in the library the movie directory is in, so undoing the library reverts it
+    let movie_dir_path = subtitle_path.parent().unwrap();
+    let library_path = movie_dir_path.parent().unwrap_or(movie_dir_path);
+    let config = config.for_library(library_path);
+    let ops = FileOperations::with_journal(
+        cli.dry_run,
+        cli.verbose,
+        library_path,
+        get_deletion_policy(cli, &config, library_path),
+    );
+
+    let retiming = match reference {
+        Some(reference_path) => Retiming::Reference(reference_path),
+        None => Retiming::Manual {
+            offset_seconds: offset.unwrap_or(0.0),
+            frame_rates: from_fps.zip(*to_fps),
+        },
+    };
+    match sync_subtitle(&subtitle_path, retiming, &config, &ops) {
+        Ok(dest_path) => println!("{color_green}Wrote the synced subtitle to: {:?}{color_reset}", dest_path),
+        Err(e) => eprintln!("{color_red}Failed to sync {:?}: {}{color_reset}", subtitle_path, e),
+    }
+
+    if ops.is_dry_run() {
+        ops.print_plan(&[movie_dir_path]);
+    }
+}
+
+/// The command line overrides the deletion policy of the config file.
+fn get_deletion_policy(cli: &Cli, config: &Config, library_path: &Path) -> DeletionPolicy {
+    if cli.trash {
+        DeletionPolicy::Trash
+    } else if let Some(quarantine_path) = &cli.quarantine {
+        DeletionPolicy::Quarantine(quarantine_path.clone())
+    } else {
+        config.deletion_policy(library_path)
     }
+}
 
-    let directory = directory_opt.map(String::from).unwrap();
-    let directory_path = Path::new(&directory);
-    if !directory_path.exists() {
-        panic!("Specified source path does not exist");
+async fn process_library(
+    cli: &Cli,
+    config: &Config,
+    cache: &Arc<Cache>,
+    http: &Arc<HttpClient>,
+    directory_path: &Path,
+) {
+    if !directory_path.is_dir() {
+        eprintln!("{color_red}Skipping {:?}, it is not a directory{color_reset}", directory_path);
+        return;
     }
 
-    let command = command_opt.map(OrganizerMode::from).unwrap();
-    let dir_entries = fs::read_dir(&directory_path).unwrap();
+    let mut config = config.for_library(directory_path);
+    if let Some(output_dir) = &cli.output_dir {
+        config.output_directory = Some(output_dir.clone());
+    }
+    if let Some(transfer) = cli.transfer {
+        config.transfer = transfer;
+    }
+    let output_path = config.output_path(directory_path);
+    let quarantine_path = cli
+        .quarantine
+        .clone()
+        .unwrap_or_else(|| config.quarantine_path(directory_path));
+    let deletion_policy = get_deletion_policy(cli, &config, directory_path);
+    let ops = match cli.command {
+        Command::Undo { .. } | Command::Purge { .. } => FileOperations::new(cli.dry_run, cli.verbose),
+        _ => FileOperations::with_journal(cli.dry_run, cli.verbose, directory_path, deletion_policy),
+    };
+    let output_path = Some(output_path).filter(|output_path| !same_path(output_path, directory_path));
+    let organizes = matches!(cli.command, Command::Movies { .. } | Command::TvShows { .. });
+    if organizes && config.transfer != TransferMode::Move && output_path.is_none() {
+        eprintln!(
+            "{color_red}Skipping {:?}, the {} transfer mode needs an output directory{color_reset}",
+            directory_path,
+            format!("{:?}", config.transfer).to_lowercase()
+        );
+        return;
+    }
+    let match_mode = if cli.interactive {
+        MatchMode::Interactive
+    } else if cli.yes {
+        MatchMode::Confident
+    } else {
+        MatchMode::Best
+    };
+    let ctx = Context {
+        config,
+        ops,
+        output_path,
+        match_mode,
+        cache: Arc::clone(cache),
+        http: Arc::clone(http),
+    };
 
     println!();
     println!("Processing media directory: '{}'", &directory_path.to_string_lossy());
     println!();
 
-    match command {
-        OrganizerMode::Movies => {
-            handle_movies(&directory_path, dir_entries).await;
+    if let (true, Some(output_path)) = (organizes, &ctx.output_path) {
+        if let Err(e) = ctx.ops.create_dir_all(output_path) {
+            eprintln!("{color_red}Failed to create the output directory {:?}: {}{color_reset}", output_path, e);
+            return;
         }
-        OrganizerMode::TvShows => {
-            handle_tv_shows(&directory_path, dir_entries).await;
+        println!("Organizing into: '{}'", output_path.to_string_lossy());
+        println!();
+    }
+
+    match &cli.command {
+        Command::Movies { .. } | Command::TvShows { .. } => {
+            let dir_entries = match fs::read_dir(directory_path) {
+                Ok(dir_entries) => dir_entries,
+                Err(e) => {
+                    eprintln!("{color_red}Failed to read {:?}: {}{color_reset}", directory_path, e);
+                    return;
+                }
+            };
+            match cli.command {
+                Command::Movies { .. } => handle_movies(directory_path, dir_entries, &ctx).await,
+                _ => handle_tv_shows(directory_path, dir_entries, &ctx).await,
+            }
         }
+        Command::Undo { .. } => {
+            if let Err(e) = undo_last_run(directory_path, Some(&quarantine_path), &ctx.ops) {
+                eprintln!("{color_red}Failed to undo the last run: {}{color_reset}", e);
+                return;
+            }
+        }
+        Command::Purge { older_than, .. } => {
+            if let Err(e) = purge(&quarantine_path, *older_than, &ctx.ops) {
+                eprintln!("{color_red}Failed to purge the quarantine: {}{color_reset}", e);
+                return;
+            }
+        }
+        Command::Cache { .. } | Command::Subtitles { .. } | Command::Completions { .. } | Command::Man => unreachable!(),
     }
-}
 
-fn print_help() {
-    println!("Welcome to Media Organizer");
-    println!();
-    println!("{color_green}Usage:{color_reset} {color_cyan}media-organizer [command] [directory path]{color_reset}");
-    println!();
-    println!("{color_green}Commands:{color_reset}");
-    println!("     {color_cyan}movies{color_reset}           Reorganize your movie directory");
-    println!("     {color_cyan}tvshows{color_reset}          Reorganize your TV Series directory");
-    println!(" {color_cyan}-h, --help{color_reset}           Print help");
-    println!();
+    if ctx.ops.is_dry_run() {
+        match &ctx.output_path {
+            Some(output_path) => ctx.ops.print_plan(&[directory_path, output_path]),
+            None => ctx.ops.print_plan(&[directory_path]),
+        }
+    }
 }
@@ -0,0 +1,107 @@
+use super::{MetadataProvider, MovieMatch};
+use crate::cache::{cache_key, Cache};
+use async_trait::async_trait;
+use std::error::Error;
+use std::sync::Arc;
+
+/// Answers lookups from the cache when it can and remembers what the wrapped provider returns.
+pub struct CachedProvider {
+    provider: Box<dyn MetadataProvider>,
+    cache: Arc<Cache>,
+}
+
+impl CachedProvider {
+    pub fn new(provider: Box<dyn MetadataProvider>, cache: Arc<Cache>) -> CachedProvider {
+        CachedProvider { provider, cache }
+    }
+
+    fn key(&self, kind: &str, query: &str) -> String {
+        cache_key(&format!("{}-{}", self.provider.name().to_lowercase(), kind), query)
+    }
+
+    fn offline_miss(&self, query: &str) -> Box<dyn Error> {
+        format!("{:?} is not cached and lookups are offline", query).into()
+    }
+}
+
+#[async_trait(?Send)]
+impl MetadataProvider for CachedProvider {
+    fn name(&self) -> &'static str {
+        self.provider.name()
+    }
+
+    /// Providers search by title alone, so the year is not part of the key.
+    async fn search(&self, title: &str, year: Option<u16>) -> Result<Vec<MovieMatch>, Box<dyn Error>> {
+        let key = self.key("search", title);
+        if let Some(matches) = self.cache.get(&key, self.cache.search_ttl()) {
+            return Ok(matches);
+        }
+        if self.cache.is_offline() {
+            return Err(self.offline_miss(title));
+        }
+
+        let matches = self.provider.search(title, year).await?;
+        self.cache.put(&key, &matches);
+        Ok(matches)
+    }
+
+    async fn fetch(&self, id: &str) -> Result<MovieMatch, Box<dyn Error>> {
+        let key = self.key("details", id);
+        if let Some(movie) = self.cache.get(&key, self.cache.details_ttl()) {
+            return Ok(movie);
+        }
+        if self.cache.is_offline() {
+            return Err(self.offline_miss(id));
+        }
+
+        let movie = self.provider.fetch(id).await?;
+        self.cache.put(&key, &movie);
+        Ok(movie)
+    }
+
+    async fn fetch_by_imdb_id(&self, imdb_id: &str) -> Result<MovieMatch, Box<dyn Error>> {
+        let key = self.key("id", imdb_id);
+        if let Some(movie) = self.cache.get(&key, self.cache.details_ttl()) {
+            return Ok(movie);
+        }
+        if self.cache.is_offline() {
+            return Err(self.offline_miss(imdb_id));
+        }
+
+        let movie = self.provider.fetch_by_imdb_id(imdb_id).await?;
+        self.cache.put(&key, &movie);
+        Ok(movie)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CacheConfig;
+    use crate::metadata_providers::omdb::OmdbProvider;
+    use crate::http::tests::{serve, test_http};
+    use crate::metadata_providers::tests::provider_config;
+
+    const SEARCH_RESPONSE: &str = r#"{"Search":[{"Title":"Alien","Year":"1979","imdbID":"tt0078748","Type":"movie"}],"totalResults":"1","Response":"True"}"#;
+
+    #[tokio::test]
+    async fn repeated_lookups_are_answered_from_the_cache() {
+        let directory = std::env::temp_dir().join(format!("media-organizer-cached-provider-{}", std::process::id()));
+        let config = CacheConfig { directory: Some(directory.clone()), ..CacheConfig::default() };
+        let (base_url, requested_paths) = serve(vec![("/?apikey=key&s=", 200, SEARCH_RESPONSE)]);
+        let omdb = || Box::new(OmdbProvider::new(&provider_config(&base_url), test_http()));
+
+        let provider = CachedProvider::new(omdb(), Arc::new(Cache::open(&config, false, false)));
+        let matches = provider.search("Alien", Some(1979)).await.unwrap();
+        let cached_matches = provider.search("alien", None).await.unwrap();
+        assert_eq!(cached_matches[0].imdb_id, matches[0].imdb_id);
+        assert_eq!(requested_paths.lock().unwrap().len(), 1);
+        drop(provider);
+
+        let offline_provider = CachedProvider::new(omdb(), Arc::new(Cache::open(&config, true, false)));
+        assert_eq!(offline_provider.search("Alien", None).await.unwrap()[0].title, "Alien");
+        assert!(offline_provider.search("Aliens", None).await.is_err());
+        assert_eq!(requested_paths.lock().unwrap().len(), 1);
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,167 @@
+use super::{parse_year, MetadataProvider, MovieMatch};
+use crate::config::ProviderConfig;
+use crate::http::HttpClient;
+use crate::utils::url_encode;
+use async_trait::async_trait;
+use scraper::{Html, Selector};
+use std::error::Error;
+use std::sync::Arc;
+
+const IMDB_BASE_URL: &str = "https://www.imdb.com";
+
+/// Scrapes the IMDb website, which needs no API key.
+pub struct ImdbProvider {
+    base_url: String,
+    http: Arc<HttpClient>,
+}
+
+impl ImdbProvider {
+    pub fn new(config: &ProviderConfig, http: Arc<HttpClient>) -> ImdbProvider {
+        ImdbProvider {
+            base_url: config.base_url.as_deref().unwrap_or(IMDB_BASE_URL).trim_end_matches('/').to_string(),
+            http,
+        }
+    }
+
+    async fn get_document(&self, url: &str) -> Result<Html, Box<dyn Error>> {
+        let response = self.http.get_text(url).await?;
+        Ok(Html::parse_document(&response))
+    }
+}
+
+#[async_trait(?Send)]
+impl MetadataProvider for ImdbProvider {
+    fn name(&self) -> &'static str {
+        "IMDb"
+    }
+
+    /// Searches by title alone so remakes from other years show up to be ranked against.
+    async fn search(&self, title: &str, _year: Option<u16>) -> Result<Vec<MovieMatch>, Box<dyn Error>> {
+        let url = format!(
+            "{}/find?q={}&s=tt&ttype=ft&ref_=fn_ft",
+            self.base_url,
+            url_encode(title)
+        );
+        let document = self.get_document(&url).await?;
+
+        let item_selector = Selector::parse("li.ipc-metadata-list-summary-item").unwrap();
+        let link_selector = Selector::parse(".ipc-metadata-list-summary-item__tc a").unwrap();
+        // the first line under the title lists the year and, for anything but films, the type
+        let details_selector = Selector::parse(".ipc-metadata-list-summary-item__tl li").unwrap();
+
+        let matches = document
+            .select(&item_selector)
+            .filter_map(|item| {
+                let link = item.select(&link_selector).next()?;
+                let title = link.text().collect::<String>().trim().to_string();
+                let id = parse_title_id(link.value().attr("href")?)?;
+                let details: Vec<String> = item
+                    .select(&details_selector)
+                    .map(|detail| detail.text().collect::<String>().trim().to_string())
+                    .collect();
+
+                Some(MovieMatch {
+                    title,
+                    year: details.first().and_then(|year| parse_year(year)),
+                    imdb_id: Some(id.clone()),
+                    id,
+                    kind: details.get(1).cloned(),
+                    confidence: 0.0,
+                })
+            })
+            .collect();
+
+        Ok(matches)
+    }
+
+    async fn fetch(&self, id: &str) -> Result<MovieMatch, Box<dyn Error>> {
+        let document = self.get_document(&format!("{}/title/{}/", self.base_url, id)).await?;
+
+        let title_selector = Selector::parse("h1[data-testid=\"hero__pageTitle\"]").unwrap();
+        let title = document
+            .select(&title_selector)
+            .next()
+            .map(|element| element.text().collect::<String>().trim().to_string())
+            .ok_or("IMDb title not found")?;
+
+        let year_selector = Selector::parse("a[href*=\"/releaseinfo\"]").unwrap();
+        let year = document
+            .select(&year_selector)
+            .find_map(|element| parse_year(element.text().collect::<String>().trim()));
+
+        Ok(MovieMatch {
+            title,
+            year,
+            imdb_id: Some(id.to_string()),
+            id: id.to_string(),
+            kind: None,
+            confidence: 0.0,
+        })
+    }
+}
+
+/// Extracts `tt0133093` from a link like `/title/tt0133093/?ref_=fn_ft_1`.
+fn parse_title_id(href: &str) -> Option<String> {
+    let id_start = href.find("/title/")? + "/title/".len();
+    let id = href[id_start..].split(['/', '?']).next()?;
+    (!id.is_empty()).then(|| id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tests::{serve, test_http};
+    use crate::metadata_providers::tests::provider_config;
+
+    /// The result list of a real `/find?q=The+Matrix` page, with the markup around it trimmed.
+    const FIND_PAGE: &str = r#"<html><body><section data-testid="find-results-section-title"><ul class="ipc-metadata-list">
+        <li class="ipc-metadata-list-summary-item ipc-metadata-list-summary-item--click find-result-item">
+            <div class="ipc-metadata-list-summary-item__c"><div class="ipc-metadata-list-summary-item__tc">
+                <a class="ipc-metadata-list-summary-item__t" href="/title/tt0133093/?ref_=fn_ft_ttl_1">The Matrix</a>
+                <ul class="ipc-inline-list ipc-metadata-list-summary-item__tl"><li class="ipc-inline-list__item"><span>1999</span></li></ul>
+            </div></div>
+        </li>
+        <li class="ipc-metadata-list-summary-item ipc-metadata-list-summary-item--click find-result-item">
+            <div class="ipc-metadata-list-summary-item__c"><div class="ipc-metadata-list-summary-item__tc">
+                <a class="ipc-metadata-list-summary-item__t" href="/title/tt0106062/?ref_=fn_ft_ttl_2">Matrix</a>
+                <ul class="ipc-inline-list ipc-metadata-list-summary-item__tl"><li class="ipc-inline-list__item"><span>1993</span></li><li class="ipc-inline-list__item"><span>TV Series</span></li></ul>
+            </div></div>
+        </li>
+    </ul></section></body></html>"#;
+
+    /// The hero of a real `/title/tt0133093/` page, with the markup around it trimmed.
+    const TITLE_PAGE: &str = r#"<html><body><section class="ipc-page-section">
+        <h1 textlength="10" data-testid="hero__pageTitle" class="hero__primary-text-suffix"><span class="hero__primary-text">The Matrix</span></h1>
+        <ul class="ipc-inline-list ipc-inline-list--show-dividers">
+            <li class="ipc-inline-list__item"><a class="ipc-link" href="/title/tt0133093/releaseinfo?ref_=tt_ov_rdat">1999</a></li>
+            <li class="ipc-inline-list__item"><a class="ipc-link" href="/title/tt0133093/parentalguide/certificates?ref_=tt_ov_pg">R</a></li>
+            <li class="ipc-inline-list__item">2h 16m</li>
+        </ul>
+    </section></body></html>"#;
+
+    #[tokio::test]
+    async fn search_parses_the_result_list() {
+        let (base_url, _) = serve(vec![("/find", 200, FIND_PAGE)]);
+        let provider = ImdbProvider::new(&provider_config(&base_url), test_http());
+
+        let matches = provider.search("The Matrix", Some(1999)).await.unwrap();
+        let found: Vec<(&str, Option<u16>, &str, Option<&str>)> = matches
+            .iter()
+            .map(|movie| (movie.title.as_str(), movie.year, movie.id.as_str(), movie.kind.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            [("The Matrix", Some(1999), "tt0133093", None), ("Matrix", Some(1993), "tt0106062", Some("TV Series"))]
+        );
+    }
+
+    #[tokio::test]
+    async fn fetch_parses_the_title_page() {
+        let (base_url, _) = serve(vec![("/title/tt0133093/", 200, TITLE_PAGE), ("/title/", 200, "<html></html>")]);
+        let provider = ImdbProvider::new(&provider_config(&base_url), test_http());
+
+        let movie = provider.fetch("tt0133093").await.unwrap();
+        assert_eq!((movie.title.as_str(), movie.year, movie.imdb_id.as_deref()), ("The Matrix", Some(1999), Some("tt0133093")));
+        assert!(provider.fetch("tt0000000").await.is_err());
+    }
+}
@@ -0,0 +1,167 @@
+pub mod cached;
+pub mod imdb;
+pub mod omdb;
+pub mod scoring;
+pub mod tmdb;
+
+use crate::cache::Cache;
+use crate::http::HttpClient;
+use crate::config::MetadataConfig;
+use crate::metadata_providers::cached::CachedProvider;
+use crate::metadata_providers::scoring::rank_matches;
+use async_trait::async_trait;
+use inline_colorization::{color_reset, color_yellow};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::sync::Arc;
+
+/// A movie as a metadata provider knows it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MovieMatch {
+    pub title: String,
+    pub year: Option<u16>,
+    pub imdb_id: Option<String>,
+    /// The provider's own id, which `fetch` accepts
+    pub id: String,
+    /// What the provider lists it as when it is not a feature film, e.g. `TV Series`
+    pub kind: Option<String>,
+    /// How well it matches what was searched for, from 0 to 1, set when ranking
+    pub confidence: f32,
+}
+
+#[async_trait(?Send)]
+pub trait MetadataProvider {
+    fn name(&self) -> &'static str;
+
+    /// Looks a movie up by title, best match first.
+    async fn search(&self, title: &str, year: Option<u16>) -> Result<Vec<MovieMatch>, Box<dyn Error>>;
+
+    /// Gets a movie by the provider's own id.
+    async fn fetch(&self, id: &str) -> Result<MovieMatch, Box<dyn Error>>;
+
+    /// Gets a movie by its IMDb id, which most providers use as their own.
+    async fn fetch_by_imdb_id(&self, imdb_id: &str) -> Result<MovieMatch, Box<dyn Error>> {
+        self.fetch(imdb_id).await
+    }
+}
+
+/// How a lookup settles on one of the ranked candidates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchMode {
+    /// Take the best candidate, however unsure
+    Best,
+    /// Ask which candidate is right when the best one is not confident enough
+    Interactive,
+    /// Take the best candidate only when it is confident enough
+    Confident,
+}
+
+/// Creates the configured providers in order of priority, each answering from the cache first.
+pub fn build_providers(
+    config: &MetadataConfig,
+    cache: &Arc<Cache>,
+    http: &Arc<HttpClient>,
+) -> Vec<Box<dyn MetadataProvider>> {
+    config
+        .providers
+        .iter()
+        .filter_map(|name| -> Option<Box<dyn MetadataProvider>> {
+            match name.as_str() {
+                "imdb" => Some(Box::new(imdb::ImdbProvider::new(&config.imdb, Arc::clone(http)))),
+                "tmdb" => Some(Box::new(tmdb::TmdbProvider::new(&config.tmdb, Arc::clone(http)))),
+                "omdb" => Some(Box::new(omdb::OmdbProvider::new(&config.omdb, Arc::clone(http)))),
+                _ => None,
+            }
+        })
+        .map(|provider| -> Box<dyn MetadataProvider> {
+            Box::new(CachedProvider::new(provider, Arc::clone(cache)))
+        })
+        .collect()
+}
+
+/// Asks each provider in turn until one finds the movie, falling back to the next one when a
+/// provider fails or has no match. Returns the candidates ranked by confidence, best first.
+pub async fn find_movie(
+    providers: &[Box<dyn MetadataProvider>],
+    title: &str,
+    year: Option<u16>,
+) -> Option<(&'static str, Vec<MovieMatch>)> {
+    for provider in providers {
+        match provider.search(title, year).await {
+            Ok(matches) if !matches.is_empty() => {
+                let mut candidates = rank_matches(title, year, matches);
+                candidates[0] = complete_match(provider.as_ref(), candidates[0].clone()).await;
+                return Some((provider.name(), candidates));
+            }
+            Ok(_) => println!("{color_yellow}No {} results for {:?}{color_reset}", provider.name(), title),
+            Err(e) => println!("{color_yellow}{} lookup failed for {:?}: {}{color_reset}", provider.name(), title, e),
+        }
+    }
+
+    None
+}
+
+/// Asks each provider in turn for the movie with the given IMDb id.
+pub async fn find_movie_by_imdb_id(providers: &[Box<dyn MetadataProvider>], imdb_id: &str) -> Option<MovieMatch> {
+    for provider in providers {
+        match provider.fetch_by_imdb_id(imdb_id).await {
+            Ok(movie) => return Some(MovieMatch { confidence: 1.0, ..movie }),
+            Err(e) => println!("{color_yellow}{} lookup failed for {}: {}{color_reset}", provider.name(), imdb_id, e),
+        }
+    }
+
+    None
+}
+
+/// Search results of some providers leave out the IMDb id, which the details have.
+async fn complete_match(provider: &dyn MetadataProvider, movie: MovieMatch) -> MovieMatch {
+    if movie.imdb_id.is_some() {
+        return movie;
+    }
+
+    match provider.fetch(&movie.id).await {
+        Ok(details) => MovieMatch {
+            imdb_id: details.imdb_id,
+            year: movie.year.or(details.year),
+            ..movie
+        },
+        Err(_) => movie,
+    }
+}
+
+/// The year at the start of a date like `1999-03-31` or a range like `1999–2003`.
+fn parse_year(date: &str) -> Option<u16> {
+    date.get(..4).and_then(|year| year.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CacheConfig, ProviderConfig};
+    use crate::http::tests::{serve, test_http};
+
+    pub(crate) fn provider_config(base_url: &str) -> ProviderConfig {
+        ProviderConfig { base_url: Some(base_url.to_string()), api_key: Some("key".to_string()) }
+    }
+
+    #[tokio::test]
+    async fn lookups_fall_back_to_the_next_provider_when_one_fails() {
+        let (base_url, requested_paths) = serve(vec![
+            ("/search/movie", 500, "{\"status_message\":\"Internal error.\"}"),
+            ("/?apikey=key&s=", 200, r#"{"Search":[{"Title":"The Matrix","Year":"1999","imdbID":"tt0133093","Type":"movie"}],"totalResults":"1","Response":"True"}"#),
+        ]);
+        let config = MetadataConfig {
+            providers: vec!["tmdb".to_string(), "omdb".to_string()],
+            tmdb: provider_config(&base_url),
+            omdb: provider_config(&base_url),
+            ..MetadataConfig::default()
+        };
+        let cache = Arc::new(Cache::open(&CacheConfig { enabled: false, ..CacheConfig::default() }, false, false));
+        let providers = build_providers(&config, &cache, &test_http());
+
+        let (provider_name, candidates) = find_movie(&providers, "The Matrix", Some(1999)).await.unwrap();
+        assert_eq!(provider_name, "OMDb");
+        assert_eq!(candidates[0].imdb_id.as_deref(), Some("tt0133093"));
+        assert_eq!(requested_paths.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,146 @@
+use super::{parse_year, MetadataProvider, MovieMatch};
+use crate::config::ProviderConfig;
+use crate::http::HttpClient;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::error::Error;
+use std::sync::Arc;
+
+const OMDB_BASE_URL: &str = "https://www.omdbapi.com";
+
+/// The Open Movie Database JSON API, which needs an API key and uses IMDb ids as its own.
+pub struct OmdbProvider {
+    base_url: String,
+    api_key: String,
+    http: Arc<HttpClient>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SearchResponse {
+    #[serde(default)]
+    search: Vec<OmdbMovie>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct OmdbMovie {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    year: String,
+    #[serde(rename = "imdbID", default)]
+    imdb_id: String,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+impl OmdbProvider {
+    pub fn new(config: &ProviderConfig, http: Arc<HttpClient>) -> OmdbProvider {
+        OmdbProvider {
+            base_url: config.base_url.as_deref().unwrap_or(OMDB_BASE_URL).trim_end_matches('/').to_string(),
+            api_key: config.api_key.clone().unwrap_or_default(),
+            http,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl MetadataProvider for OmdbProvider {
+    fn name(&self) -> &'static str {
+        "OMDb"
+    }
+
+    /// Searches by title only, since OMDb matches the year exactly and the one in a file name
+    /// is often a year off.
+    async fn search(&self, title: &str, _year: Option<u16>) -> Result<Vec<MovieMatch>, Box<dyn Error>> {
+        let query = [("apikey", self.api_key.as_str()), ("s", title), ("type", "movie")];
+        let response: SearchResponse = self
+            .http
+            .get_json(&format!("{}/", self.base_url), &query)
+            .await?;
+
+        // "Movie not found!" is an empty result, anything else is a real error
+        match response.error {
+            Some(error) if error != "Movie not found!" => Err(error.into()),
+            _ => Ok(response.search.into_iter().map(to_movie_match).collect()),
+        }
+    }
+
+    async fn fetch(&self, id: &str) -> Result<MovieMatch, Box<dyn Error>> {
+        let movie: OmdbMovie = self
+            .http
+            .get_json(&format!("{}/", self.base_url), &[("apikey", self.api_key.as_str()), ("i", id)])
+            .await?;
+
+        match movie.error {
+            Some(error) => Err(error.into()),
+            None => Ok(to_movie_match(movie)),
+        }
+    }
+}
+
+fn to_movie_match(movie: OmdbMovie) -> MovieMatch {
+    MovieMatch {
+        year: parse_year(&movie.year),
+        imdb_id: Some(movie.imdb_id.clone()),
+        id: movie.imdb_id,
+        title: movie.title,
+        kind: None,
+        confidence: 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tests::{serve, test_http};
+    use crate::metadata_providers::tests::provider_config;
+
+    /// Trimmed from a real `?s=The+Matrix&type=movie` response.
+    const SEARCH_RESPONSE: &str = r#"{"Search":[
+        {"Title":"The Matrix","Year":"1999","imdbID":"tt0133093","Type":"movie","Poster":"https://m.media-amazon.com/images/M/MV5B.jpg"},
+        {"Title":"The Matrix Reloaded","Year":"2003","imdbID":"tt0234215","Type":"movie","Poster":"N/A"}
+    ],"totalResults":"2","Response":"True"}"#;
+
+    const DETAILS_RESPONSE: &str = r#"{"Title":"The Matrix","Year":"1999","Rated":"R","Released":"31 Mar 1999","Runtime":"136 min","imdbID":"tt0133093","Type":"movie","Response":"True"}"#;
+
+    #[tokio::test]
+    async fn search_parses_the_results_without_filtering_by_year() {
+        let (base_url, requested_paths) = serve(vec![("/?apikey=key&s=", 200, SEARCH_RESPONSE)]);
+        let provider = OmdbProvider::new(&provider_config(&base_url), test_http());
+
+        let matches = provider.search("The Matrix", Some(1999)).await.unwrap();
+        let found: Vec<(&str, Option<u16>, &str)> =
+            matches.iter().map(|movie| (movie.title.as_str(), movie.year, movie.id.as_str())).collect();
+        assert_eq!(found, [("The Matrix", Some(1999), "tt0133093"), ("The Matrix Reloaded", Some(2003), "tt0234215")]);
+        assert!(!requested_paths.lock().unwrap()[0].contains("&y="));
+    }
+
+    #[tokio::test]
+    async fn movie_not_found_is_no_results_but_other_errors_fail() {
+        let (base_url, _) = serve(vec![
+            ("/?apikey=key&s=Nothing", 200, r#"{"Response":"False","Error":"Movie not found!"}"#),
+            ("/?apikey=key&s=", 200, r#"{"Response":"False","Error":"Too many results."}"#),
+        ]);
+        let provider = OmdbProvider::new(&provider_config(&base_url), test_http());
+
+        assert!(provider.search("Nothing", None).await.unwrap().is_empty());
+        assert!(provider.search("A", None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn fetch_parses_the_details() {
+        let (base_url, _) = serve(vec![
+            ("/?apikey=key&i=tt0133093", 200, DETAILS_RESPONSE),
+            ("/?apikey=key&i=", 200, r#"{"Response":"False","Error":"Incorrect IMDb ID."}"#),
+        ]);
+        let provider = OmdbProvider::new(&provider_config(&base_url), test_http());
+
+        let movie = provider.fetch("tt0133093").await.unwrap();
+        assert_eq!((movie.title.as_str(), movie.year, movie.imdb_id.as_deref()), ("The Matrix", Some(1999), Some("tt0133093")));
+        assert!(provider.fetch("tt0").await.is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use super::MovieMatch;
+
+const TITLE_WEIGHT: f32 = 0.7;
+const YEAR_WEIGHT: f32 = 0.3;
+
+/// A runner-up this close to the best candidate makes the best one a guess.
+const AMBIGUITY_MARGIN: f32 = 0.05;
+
+/// Kinds of search results that are never the movie being looked for.
+const NON_MOVIE_KINDS: [&str; 6] = ["tv series", "tv mini series", "tv episode", "video game", "podcast series", "music video"];
+
+/// Sets each candidate's confidence and sorts them best first.
+pub fn rank_matches(title: &str, year: Option<u16>, matches: Vec<MovieMatch>) -> Vec<MovieMatch> {
+    let mut ranked: Vec<MovieMatch> = matches
+        .into_iter()
+        .map(|movie| MovieMatch {
+            confidence: score_match(title, year, &movie),
+            ..movie
+        })
+        .collect();
+
+    // stable, so equally good candidates keep the provider's order
+    ranked.sort_by(|left, right| right.confidence.total_cmp(&left.confidence));
+    ranked
+}
+
+/// The best of the ranked candidates, if it clears the threshold and stands out from the rest.
+pub fn confident_match(candidates: &[MovieMatch], threshold: f32) -> Option<&MovieMatch> {
+    let best = candidates.first().filter(|best| best.confidence >= threshold)?;
+    match candidates.get(1) {
+        Some(runner_up) if best.confidence - runner_up.confidence < AMBIGUITY_MARGIN => None,
+        _ => Some(best),
+    }
+}
+
+/// How sure we are that the candidate is the movie, from 0 to 1.
+fn score_match(title: &str, year: Option<u16>, movie: &MovieMatch) -> f32 {
+    let title_score = title_similarity(title, &movie.title);
+    let year_score = match (year, movie.year) {
+        (Some(year), Some(movie_year)) => year_proximity(year, movie_year),
+        // nothing to tell candidates apart by, so neither reward nor punish them
+        _ => 0.5,
+    };
+
+    let score = TITLE_WEIGHT * title_score + YEAR_WEIGHT * year_score;
+    match &movie.kind {
+        Some(kind) if NON_MOVIE_KINDS.contains(&kind.to_lowercase().as_str()) => score * 0.5,
+        _ => score,
+    }
+}
+
+/// Releases often differ by a year between countries, anything further off is likely a remake.
+fn year_proximity(year: u16, movie_year: u16) -> f32 {
+    match year.abs_diff(movie_year) {
+        0 => 1.0,
+        1 => 0.8,
+        difference => (1.0 - difference as f32 / 5.0).max(0.0),
+    }
+}
+
+fn title_similarity(left: &str, right: &str) -> f32 {
+    let (left, right) = (normalize_title(left), normalize_title(right));
+    let longest = left.chars().count().max(right.chars().count());
+    if longest == 0 {
+        return 0.0;
+    }
+
+    1.0 - levenshtein_distance(&left, &right) as f32 / longest as f32
+}
+
+/// Lowercase words only, so punctuation and a leading article don't count as differences.
+fn normalize_title(title: &str) -> String {
+    let title = title.to_lowercase().replace('&', " and ");
+    let words: Vec<&str> = title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    match words.split_first() {
+        Some((&"the" | &"a" | &"an", rest)) if !rest.is_empty() => rest.join(" "),
+        _ => words.join(" "),
+    }
+}
+
+fn levenshtein_distance(left: &str, right: &str) -> usize {
+    let right_chars: Vec<char> = right.chars().collect();
+    let mut previous_row: Vec<usize> = (0..=right_chars.len()).collect();
+
+    for (i, left_char) in left.chars().enumerate() {
+        let mut current_row = vec![i + 1];
+        for (j, right_char) in right_chars.iter().enumerate() {
+            let substitution_cost = usize::from(left_char != *right_char);
+            current_row.push(
+                (previous_row[j] + substitution_cost)
+                    .min(previous_row[j + 1] + 1)
+                    .min(current_row[j] + 1),
+            );
+        }
+        previous_row = current_row;
+    }
+
+    previous_row[right_chars.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn movie(title: &str, year: u16) -> MovieMatch {
+        MovieMatch {
+            title: title.to_string(),
+            year: Some(year),
+            imdb_id: None,
+            id: format!("{} {}", title, year),
+            kind: None,
+            confidence: 0.0,
+        }
+    }
+
+    #[test]
+    fn the_release_from_the_searched_year_beats_its_remake() {
+        let matches = vec![movie("Solaris", 1972), movie("Solaris", 2002)];
+
+        let candidates = rank_matches("Solaris", Some(2002), matches);
+        assert_eq!(candidates[0].year, Some(2002));
+        assert_eq!(confident_match(&candidates, 0.8).map(|movie| movie.year), Some(Some(2002)));
+    }
+
+    #[test]
+    fn a_release_a_year_off_is_still_a_confident_match() {
+        let matches = vec![movie("The Thing", 2011), movie("The Thing", 1982)];
+
+        let candidates = rank_matches("The Thing", Some(1981), matches);
+        assert_eq!(candidates[0].year, Some(1982));
+        assert!(candidates[0].confidence > 0.9);
+        assert_eq!(confident_match(&candidates, 0.8).map(|movie| movie.year), Some(Some(1982)));
+    }
+
+    #[test]
+    fn without_a_year_remakes_are_too_close_to_call() {
+        let candidates = rank_matches("Solaris", None, vec![movie("Solaris", 1972), movie("Solaris", 2002)]);
+        assert!(confident_match(&candidates, 0.5).is_none());
+    }
+}
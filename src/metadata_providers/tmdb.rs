@@ -0,0 +1,136 @@
+use super::{parse_year, MetadataProvider, MovieMatch};
+use crate::config::ProviderConfig;
+use crate::http::HttpClient;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::error::Error;
+use std::sync::Arc;
+
+const TMDB_BASE_URL: &str = "https://api.themoviedb.org/3";
+
+/// The Movie Database JSON API, which needs an API key.
+pub struct TmdbProvider {
+    base_url: String,
+    api_key: String,
+    http: Arc<HttpClient>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    results: Vec<TmdbMovie>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FindResponse {
+    movie_results: Vec<TmdbMovie>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TmdbMovie {
+    id: u64,
+    title: String,
+    #[serde(default)]
+    release_date: Option<String>,
+    #[serde(default)]
+    imdb_id: Option<String>,
+}
+
+impl TmdbProvider {
+    pub fn new(config: &ProviderConfig, http: Arc<HttpClient>) -> TmdbProvider {
+        TmdbProvider {
+            base_url: config.base_url.as_deref().unwrap_or(TMDB_BASE_URL).trim_end_matches('/').to_string(),
+            api_key: config.api_key.clone().unwrap_or_default(),
+            http,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl MetadataProvider for TmdbProvider {
+    fn name(&self) -> &'static str {
+        "TMDb"
+    }
+
+    /// The year is left out of the query, which would drop a release dated a year off, and
+    /// only ranks the results.
+    async fn search(&self, title: &str, _year: Option<u16>) -> Result<Vec<MovieMatch>, Box<dyn Error>> {
+        let query = [("api_key", self.api_key.as_str()), ("query", title)];
+        let response: SearchResponse = self
+            .http
+            .get_json(&format!("{}/search/movie", self.base_url), &query)
+            .await?;
+
+        Ok(response.results.into_iter().map(to_movie_match).collect())
+    }
+
+    async fn fetch(&self, id: &str) -> Result<MovieMatch, Box<dyn Error>> {
+        let movie: TmdbMovie = self
+            .http
+            .get_json(&format!("{}/movie/{}", self.base_url, id), &[("api_key", &self.api_key)])
+            .await?;
+
+        Ok(to_movie_match(movie))
+    }
+
+    async fn fetch_by_imdb_id(&self, imdb_id: &str) -> Result<MovieMatch, Box<dyn Error>> {
+        let response: FindResponse = self
+            .http
+            .get_json(&format!("{}/find/{}", self.base_url, imdb_id), &[("api_key", self.api_key.as_str()), ("external_source", "imdb_id")])
+            .await?;
+
+        let movie = response.movie_results.into_iter().next().ok_or("TMDb movie not found")?;
+        Ok(MovieMatch {
+            imdb_id: Some(imdb_id.to_string()),
+            ..to_movie_match(movie)
+        })
+    }
+}
+
+fn to_movie_match(movie: TmdbMovie) -> MovieMatch {
+    MovieMatch {
+        title: movie.title,
+        year: movie.release_date.as_deref().and_then(parse_year),
+        imdb_id: movie.imdb_id.filter(|imdb_id| !imdb_id.is_empty()),
+        id: movie.id.to_string(),
+        kind: None,
+        confidence: 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tests::{serve, test_http};
+    use crate::metadata_providers::tests::provider_config;
+
+    /// Trimmed from a real `/search/movie?query=The+Matrix` response.
+    const SEARCH_RESPONSE: &str = r#"{"page":1,"results":[
+        {"adult":false,"genre_ids":[28,878],"id":603,"original_language":"en","original_title":"The Matrix","overview":"Set in the 22nd century...","popularity":81.9,"release_date":"1999-03-31","title":"The Matrix","video":false,"vote_average":8.2,"vote_count":26000},
+        {"adult":false,"genre_ids":[99],"id":684731,"original_language":"en","original_title":"The Matrix Untitled","overview":"","popularity":0.6,"release_date":"","title":"The Matrix Untitled","video":false,"vote_average":0.0,"vote_count":0}
+    ],"total_pages":1,"total_results":2}"#;
+
+    const FIND_RESPONSE: &str = r#"{"movie_results":[{"adult":false,"id":603,"title":"The Matrix","original_title":"The Matrix","release_date":"1999-03-31","media_type":"movie"}],"person_results":[],"tv_results":[],"tv_episode_results":[],"tv_season_results":[]}"#;
+
+    #[tokio::test]
+    async fn search_parses_the_results_without_filtering_by_year() {
+        let (base_url, requested_paths) = serve(vec![("/search/movie", 200, SEARCH_RESPONSE)]);
+        let provider = TmdbProvider::new(&provider_config(&base_url), test_http());
+
+        let matches = provider.search("The Matrix", Some(1999)).await.unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!((matches[0].title.as_str(), matches[0].year, matches[0].id.as_str()), ("The Matrix", Some(1999), "603"));
+        assert_eq!(matches[0].imdb_id, None);
+        assert_eq!(matches[1].year, None);
+        assert!(!requested_paths.lock().unwrap()[0].contains("year="));
+    }
+
+    #[tokio::test]
+    async fn fetch_by_imdb_id_keeps_the_id() {
+        let (base_url, _) = serve(vec![("/find/tt0133093", 200, FIND_RESPONSE), ("/find/", 200, r#"{"movie_results":[]}"#)]);
+        let provider = TmdbProvider::new(&provider_config(&base_url), test_http());
+
+        let movie = provider.fetch_by_imdb_id("tt0133093").await.unwrap();
+        assert_eq!((movie.title.as_str(), movie.year, movie.imdb_id.as_deref()), ("The Matrix", Some(1999), Some("tt0133093")));
+        assert!(provider.fetch_by_imdb_id("tt0000000").await.is_err());
+    }
+}
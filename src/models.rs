@@ -1,26 +1,13 @@
 use serde::{Deserialize, Serialize};
 
-pub enum OrganizerMode {
-    Movies,
-    TvShows,
-}
-
-impl From<String> for OrganizerMode {
-    fn from(value: String) -> Self {
-        match value.to_lowercase().as_str() {
-            "movies" => OrganizerMode::Movies,
-            "tvshows" => OrganizerMode::TvShows,
-            _ => panic!("Unknown organizer mode: {}", value),
-        }
-    }
-}
-
 #[derive(Debug, Deserialize, Serialize)]
 pub enum MediaEncodingFormat {
     X264,
     X265,
     H264,
     H265,
+    Av1,
+    Vp9,
 }
 
 impl MediaEncodingFormat {
@@ -30,6 +17,8 @@ impl MediaEncodingFormat {
             "x265" | "x.265" => Some(MediaEncodingFormat::X265),
             "h264" | "h.264" => Some(MediaEncodingFormat::H264),
             "h265" | "h.265" => Some(MediaEncodingFormat::H265),
+            "av1" => Some(MediaEncodingFormat::Av1),
+            "vp9" => Some(MediaEncodingFormat::Vp9),
             _ => None,
         }
     }
@@ -40,9 +29,47 @@ pub struct MovieMetadata {
     pub(crate) original_file_name: String,
     pub(crate) media_name: String,
     pub(crate) release_year: Option<u16>,
+    /// The cut of the movie, like `Director's Cut` or `IMAX`
+    pub(crate) edition: Option<String>,
     pub(crate) encoding_format: Option<MediaEncodingFormat>,
     pub(crate) resolution: Option<u16>,
     pub(crate) additional_data: Vec<String>,
     pub(crate) file_extension: String,
     pub(crate) imdb_id: Option<String>,
+    /// OpenSubtitles hash of the video, see [`crate::subtitles::compute_movie_hash`]
+    pub(crate) movie_hash: Option<String>,
+    pub(crate) duration_seconds: Option<u64>,
+    #[serde(default)]
+    pub(crate) audio_tracks: Vec<AudioTrack>,
+    #[serde(default)]
+    pub(crate) subtitle_tracks: Vec<SubtitleTrack>,
+}
+
+/// An audio stream of the video container, with its language as an ISO 639-1 code when known.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AudioTrack {
+    pub(crate) codec: String,
+    pub(crate) language: Option<String>,
+    pub(crate) channels: Option<u16>,
+}
+
+/// A subtitle stream embedded in the video container.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SubtitleTrack {
+    pub(crate) codec: String,
+    pub(crate) language: Option<String>,
+    pub(crate) forced: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TvEpisodeMetadata {
+    pub(crate) original_file_name: String,
+    pub(crate) season: u16,
+    pub(crate) episodes: Vec<u16>,
+    pub(crate) episode_title: Option<String>,
+    pub(crate) release_year: Option<u16>,
+    pub(crate) encoding_format: Option<MediaEncodingFormat>,
+    pub(crate) resolution: Option<u16>,
+    pub(crate) additional_data: Vec<String>,
+    pub(crate) file_extension: String,
 }
@@ -1,29 +1,35 @@
-use crate::constants::{METADATA_FILE_NAME, SUBTITLE_FILE_EXTENSION, VIDEO_FILE_EXTENSIONS};
-use crate::imdb::get_imdb_result;
+use crate::constants::METADATA_FILE_NAME;
+use crate::context::Context;
+use crate::extras::{classify_extra, classify_unnamed_extra, is_extras_folder, Extra, ExtraKind};
+use crate::file_operations::TransferMode;
+use crate::interactive::settle_match;
+use crate::metadata_providers::{build_providers, find_movie};
 use crate::models::MovieMetadata;
+use crate::naming::render_movie_name;
+use crate::probe::probe;
+use crate::subtitle_formats::{normalize_content, normalize_subtitle, SubtitleFormat};
+use crate::subtitles::{compute_movie_hash, download_subtitle, select_part_subtitles, Subtitle};
 use crate::utils::{
-    compose_media_name_from_metadata, merge_base_with_file, parse_to_movie_metadata,
+    find_video_parts, merge_base_with_file, merge_media_info, parse_to_movie_metadata, split_part_number,
 };
 use inline_colorization::{
-    color_blue, color_cyan, color_green, color_magenta, color_reset, color_yellow,
+    color_blue, color_cyan, color_green, color_magenta, color_red, color_reset, color_yellow,
 };
-use std::fs::DirEntry;
-use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::{fs, io};
 
-pub async fn process_directories(directory_entries: Vec<DirEntry>) {
-    for directory in directory_entries {
-        if needs_processing(&directory.path()) {
+pub async fn process_directories(directory_paths: Vec<PathBuf>, ctx: &Context) {
+    for directory_path in directory_paths {
+        if needs_processing(&directory_path, ctx) {
             println!(
                 "{color_green}Processing directory: {:?}{color_reset}",
-                directory.path()
+                directory_path
             );
-            process_directory(directory).await;
+            process_directory(directory_path, ctx).await;
         } else {
             println!(
                 "{color_yellow}Skipping directory: {:?}{color_reset}",
-                directory.path()
+                directory_path
             );
         }
     }
@@ -31,54 +37,59 @@ pub async fn process_directories(directory_entries: Vec<DirEntry>) {
     println!("{color_green}Done processing directories{color_reset}");
 }
 
-async fn process_directory(directory_path: DirEntry) {
-    let directory_name = directory_path.file_name();
+async fn process_directory(directory_path: PathBuf, ctx: &Context) {
+    let directory_name = directory_path.file_name().unwrap_or_default().to_os_string();
     println!("{color_blue}Processing: {:?}{color_reset}", directory_name);
 
-    if let Some(sub_file_entry) = get_subtitle_entry(&directory_path) {
+    let video_file_path = get_video_file_path(&directory_path, ctx);
+    let video_parts = video_file_path
+        .as_ref()
+        .map(|video_file_path| get_video_parts(&directory_path, video_file_path, ctx))
+        .unwrap_or_default();
+    let part_numbers: Vec<u32> = video_parts.iter().map(|(part_number, _)| *part_number).collect();
+
+    let mut subtitles = select_part_subtitles(
+        &get_subtitle_paths(&directory_path, ctx),
+        &ctx.config.subtitle_languages(),
+        &part_numbers,
+        &ctx.ops,
+    );
+    for subtitle in subtitles.iter_mut() {
         println!(
-            "{color_cyan}Found subtitle file: {:?}{color_reset}",
-            sub_file_entry.path()
+            "{color_cyan}Found {} subtitle file: {:?}{color_reset}",
+            subtitle.language.name(),
+            subtitle.path
         );
-        let subtitle_exists_in_movie_dir = fs::read_dir(directory_path.path())
-            .ok()
-            .and_then(|entries| {
-                Some(
-                    entries
-                        .filter_map(Result::ok)
-                        .any(|entry| entry.path() == sub_file_entry.path()),
-                )
-            })
-            .unwrap_or(false);
-
-        if !subtitle_exists_in_movie_dir {
-            let target_path = directory_path.path().join(sub_file_entry.file_name());
-            fs::copy(sub_file_entry.path(), &target_path)
-                .expect("Failed to copy subtitle file to root directory");
-            println!(
-                "{color_green}Copied subtitle to root directory: {:?}{color_reset}",
-                target_path
-            );
+        let subtitle_exists_in_movie_dir =
+            subtitle.path.parent() == Some(directory_path.as_path());
+
+        if !subtitle_exists_in_movie_dir && ctx.config.transfer == TransferMode::Move {
+            let placements = subtitle.placements(&directory_path.join(subtitle.path.file_name().unwrap()));
+            for (source_path, target_path) in &placements {
+                if let Err(e) = ctx.ops.copy(source_path, target_path) {
+                    eprintln!("{color_red}Failed to copy the subtitle file to the root directory: {}{color_reset}", e);
+                    return;
+                }
+                println!(
+                    "{color_green}Copied subtitle to root directory: {:?}{color_reset}",
+                    target_path
+                );
+            }
+            subtitle.path = placements[0].1.clone();
+            subtitle.companion_paths = placements[1..].iter().map(|(_, target_path)| target_path.clone()).collect();
         }
     }
 
-    if let Some(video_file_entry) = get_video_file_entry(&directory_path.path()) {
+    if let Some(video_file_path) = video_file_path {
         println!(
             "{color_magenta}Found video file: {:?}{color_reset}",
-            video_file_entry.path()
+            video_file_path.clone()
         );
+        for (part_number, part_path) in &video_parts {
+            println!("{color_magenta}Found part {} of the movie: {:?}{color_reset}", part_number, part_path);
+        }
 
-        let subtitle_entry = get_subtitle_entry(&directory_path);
-
-        delete_except(directory_path.path(), &video_file_entry, &subtitle_entry)
-            .expect("Failed to clean movie directory");
-        println!("{color_yellow}Cleaned up directory{color_reset}");
-
-        let mut video_file_name = video_file_entry
-            .file_name()
-            .to_str()
-            .map(String::from)
-            .unwrap_or_default();
+        let mut video_file_name = get_movie_file_name(&video_file_path, &video_parts);
 
         if directory_name.len() > video_file_name.len() {
             video_file_name = format!(
@@ -87,8 +98,7 @@ async fn process_directory(directory_path: DirEntry) {
                     .to_str()
                     .map(String::from)
                     .unwrap_or_default(),
-                &video_file_entry
-                    .path()
+                video_file_path
                     .extension()
                     .unwrap()
                     .to_str()
@@ -97,61 +107,146 @@ async fn process_directory(directory_path: DirEntry) {
             );
         }
 
-        let mut parsed_movie_metadata = parse_to_movie_metadata(&video_file_name);
-        let mut composed_file_name = compose_media_name_from_metadata(&parsed_movie_metadata);
+        let movie_paths: Vec<PathBuf> = match video_parts.is_empty() {
+            true => vec![video_file_path.clone()],
+            false => video_parts.iter().map(|(_, part_path)| part_path.clone()).collect(),
+        };
+        let extras = get_extras(&directory_path, &movie_paths, ctx);
+        if ctx.config.transfer != TransferMode::Move {
+            if let Some(output_path) = &ctx.output_path {
+                transfer_movie(&video_file_path, &video_parts, &subtitles, &extras, &video_file_name, output_path, ctx)
+                    .await;
+            }
+            return;
+        }
 
-        let movie_dest_path = merge_base_with_file(
-            &directory_path.path(),
-            &format!(
-                "{}.{}",
-                composed_file_name, &parsed_movie_metadata.file_extension
-            ),
-        );
-        fs::rename(video_file_entry.path(), &movie_dest_path)
-            .expect("Failed to rename the movie file");
-        println!(
-            "{color_green}Renamed movie file to: {:?}{color_reset}",
-            movie_dest_path
-        );
+        // read before the cleanup takes the metadata file away; the parts of a movie have no
+        // hash of the whole
+        let movie_hash = match video_parts.is_empty() {
+            true => get_movie_hash(&directory_path, &video_file_path, ctx),
+            false => None,
+        };
+        let extras_folder_paths = place_extras(&extras, &directory_path, ctx);
+        let kept_paths: Vec<PathBuf> = subtitles
+            .iter()
+            .flat_map(|subtitle| subtitle.paths().cloned())
+            .chain(extras_folder_paths)
+            .chain(movie_paths)
+            .collect();
+        if let Err(e) = delete_except(&directory_path, &video_file_path, &kept_paths, ctx) {
+            eprintln!("{color_red}Failed to clean the movie directory: {}{color_reset}", e);
+            return;
+        }
+        println!("{color_yellow}Cleaned up directory{color_reset}");
 
-        if let Some(subtitle) = subtitle_entry {
-            let sub_dest_path = merge_base_with_file(
-                &directory_path.path(),
-                &format!("{}.en.{}", composed_file_name, SUBTITLE_FILE_EXTENSION),
+        // look the movie up first so the files are named after what it turns out to be
+        let mut parsed_movie_metadata = parse_to_movie_metadata(&video_file_name);
+        parsed_movie_metadata.movie_hash = movie_hash;
+        update_metadata_from_container(&mut parsed_movie_metadata, &ctx.ops.current_path(&video_file_path));
+        if subtitles.is_empty() {
+            update_metadata_from_providers(&mut parsed_movie_metadata, ctx).await;
+        }
+        let naming = &ctx.config.naming;
+        let composed_file_name = render_movie_name(&naming.movie_file, &parsed_movie_metadata, None);
+
+        if video_parts.is_empty() {
+            let movie_dest_path = merge_base_with_file(
+                &directory_path,
+                &format!(
+                    "{}.{}",
+                    composed_file_name, &parsed_movie_metadata.file_extension
+                ),
             );
-            fs::rename(subtitle.path(), &sub_dest_path)
-                .expect("Failed to rename the subtitle file");
+            if let Err(e) = ctx.ops.rename(video_file_path.clone(), &movie_dest_path) {
+                eprintln!("{color_red}Failed to rename the movie file: {}{color_reset}", e);
+                return;
+            }
             println!(
-                "{color_green}Renamed subtitle file to: {:?}{color_reset}",
-                sub_dest_path
+                "{color_green}Renamed movie file to: {:?}{color_reset}",
+                movie_dest_path
             );
-        } else {
-            let imdb_info = get_imdb_result(&composed_file_name).await;
+        }
+        for (part_number, part_path) in &video_parts {
+            let part_dest_path = directory_path
+                .join(get_part_file_name(&composed_file_name, *part_number, part_path));
+            if let Err(e) = ctx.ops.rename(part_path, &part_dest_path) {
+                eprintln!("{color_red}Failed to rename part {} of the movie: {}{color_reset}", part_number, e);
+                return;
+            }
+            println!(
+                "{color_green}Renamed part {} of the movie to: {:?}{color_reset}",
+                part_number, part_dest_path
+            );
+        }
 
-            if let Ok(info) = imdb_info {
-                parsed_movie_metadata.media_name = info.title.to_string();
-                parsed_movie_metadata.imdb_id = Some(info.id.to_string());
-                composed_file_name = compose_media_name_from_metadata(&parsed_movie_metadata);
+        for subtitle in &subtitles {
+            let subtitle_extension = subtitle
+                .path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let subtitle_name = render_movie_name(
+                &naming.subtitle_file,
+                &parsed_movie_metadata,
+                Some(subtitle.language.code),
+            );
+            let sub_dest_path = merge_base_with_file(
+                &directory_path,
+                &get_subtitle_file_name(&subtitle_name, subtitle, &subtitle_extension),
+            );
+            if let Some(written_path) = write_normalized_subtitle(&subtitle.path, Path::new(&sub_dest_path), ctx) {
+                if written_path != subtitle.path {
+                    if let Err(e) = ctx.ops.remove_file(&subtitle.path) {
+                        eprintln!("{color_red}Failed to remove the original subtitle file: {}{color_reset}", e);
+                    }
+                }
+                continue;
+            }
+            for (source_path, dest_path) in subtitle.placements(Path::new(&sub_dest_path)) {
+                if let Err(e) = ctx.ops.rename(source_path, &dest_path) {
+                    eprintln!("{color_red}Failed to rename the subtitle file: {}{color_reset}", e);
+                    return;
+                }
                 println!(
-                    "{color_cyan}Updated metadata from IMDb: {:?}{color_reset}",
-                    &info
+                    "{color_green}Renamed subtitle file to: {:?}{color_reset}",
+                    dest_path
                 );
             }
         }
+        match video_parts.is_empty() {
+            true => download_missing_subtitles(&parsed_movie_metadata, &subtitles, &directory_path, ctx).await,
+            false if ctx.config.subtitles.download => {
+                println!("{color_yellow}Not downloading subtitles for a movie split into parts{color_reset}")
+            }
+            false => {}
+        }
 
-        write_metadata_file(&parsed_movie_metadata, &directory_path.path())
-            .expect("Failed to write movie metadata");
-        println!("{color_green}Metadata file created{color_reset}");
+        match write_metadata_file(&parsed_movie_metadata, &directory_path, ctx) {
+            Ok(()) => println!("{color_green}Metadata file created{color_reset}"),
+            Err(e) => eprintln!("{color_red}Failed to write the movie metadata: {}{color_reset}", e),
+        }
 
-        let movie_dir_dest_path =
-            merge_base_with_file(directory_path.path().parent().unwrap(), &composed_file_name);
-        fs::rename(directory_path.path(), &movie_dir_dest_path).expect(
-            format!(
-                "Failed to rename the movie directory {:?}",
+        let directory_name = render_movie_name(&naming.movie_directory, &parsed_movie_metadata, None);
+        let movie_dir_dest_path = match &ctx.output_path {
+            Some(output_path) => merge_base_with_file(output_path, &directory_name),
+            None => merge_base_with_file(directory_path.parent().unwrap(), &directory_name),
+        };
+        if Path::new(&movie_dir_dest_path) != directory_path
+            && ctx.ops.exists(Path::new(&movie_dir_dest_path))
+        {
+            println!(
+                "{color_yellow}Not moving movie directory, target already exists: {:?}{color_reset}",
                 movie_dir_dest_path
-            )
-            .as_str(),
-        );
+            );
+            return;
+        }
+        if let Err(e) = ctx.ops.rename(&directory_path, &movie_dir_dest_path) {
+            eprintln!(
+                "{color_red}Failed to rename the movie directory to {:?}: {}{color_reset}",
+                movie_dir_dest_path, e
+            );
+            return;
+        }
         println!(
             "{color_blue}Renamed directory to: {:?}{color_reset}",
             movie_dir_dest_path
@@ -159,12 +254,292 @@ async fn process_directory(directory_path: DirEntry) {
     }
 }
 
-fn needs_processing(dir_path: &Path) -> bool {
-    if has_valid_metadata_json(dir_path) {
-        let file_count = fs::read_dir(dir_path)
-            .unwrap()
-            .filter_map(Result::ok)
-            .filter(|entry| entry.path().is_file())
+/// Puts a movie into its own directory in `library_path` the configured way, which leaves the
+/// original where it is unless moving. Returns whether the movie was placed.
+pub async fn transfer_movie(
+    video_path: &Path,
+    video_parts: &[(u32, PathBuf)],
+    subtitles: &[Subtitle],
+    extras: &[Extra],
+    name: &str,
+    library_path: &Path,
+    ctx: &Context,
+) -> bool {
+
+    let mut parsed_movie_metadata = parse_to_movie_metadata(name);
+    if video_parts.is_empty() {
+        parsed_movie_metadata.movie_hash = compute_movie_hash(video_path).ok();
+    }
+    update_metadata_from_container(&mut parsed_movie_metadata, video_path);
+    if subtitles.is_empty() {
+        update_metadata_from_providers(&mut parsed_movie_metadata, ctx).await;
+    }
+
+    let naming = &ctx.config.naming;
+    let movie_dir_dest_path = library_path.join(render_movie_name(
+        &naming.movie_directory,
+        &parsed_movie_metadata,
+        None,
+    ));
+    if movie_dir_dest_path.exists() {
+        println!(
+            "{color_yellow}Skipping movie, target already exists: {:?}{color_reset}",
+            movie_dir_dest_path
+        );
+        return false;
+    }
+    if let Err(e) = ctx.ops.create_dir(&movie_dir_dest_path) {
+        eprintln!("{color_red}Failed to create the movie directory: {}{color_reset}", e);
+        return false;
+    }
+
+    let movie_file_name = render_movie_name(&naming.movie_file, &parsed_movie_metadata, None);
+    if video_parts.is_empty() {
+        let movie_dest_path = movie_dir_dest_path.join(format!(
+            "{}.{}",
+            movie_file_name, parsed_movie_metadata.file_extension
+        ));
+        if let Err(e) = ctx.ops.transfer(video_path, &movie_dest_path, ctx.config.transfer) {
+            eprintln!("{color_red}Failed to transfer the movie file: {}{color_reset}", e);
+            return false;
+        }
+        println!("{color_green}Placed movie file at: {:?}{color_reset}", movie_dest_path);
+    }
+    for (part_number, part_path) in video_parts {
+        let part_dest_path = movie_dir_dest_path.join(get_part_file_name(&movie_file_name, *part_number, part_path));
+        if let Err(e) = ctx.ops.transfer(part_path, &part_dest_path, ctx.config.transfer) {
+            eprintln!("{color_red}Failed to transfer part {} of the movie: {}{color_reset}", part_number, e);
+            return false;
+        }
+        println!("{color_green}Placed part {} of the movie at: {:?}{color_reset}", part_number, part_dest_path);
+    }
+
+    for subtitle in subtitles {
+        let subtitle_extension = subtitle
+            .path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let subtitle_name = render_movie_name(
+            &naming.subtitle_file,
+            &parsed_movie_metadata,
+            Some(subtitle.language.code),
+        );
+        let sub_dest_path = movie_dir_dest_path.join(get_subtitle_file_name(&subtitle_name, subtitle, &subtitle_extension));
+        if let Some(written_path) = write_normalized_subtitle(&subtitle.path, &sub_dest_path, ctx) {
+            if ctx.config.transfer == TransferMode::Move && written_path != subtitle.path {
+                if let Err(e) = ctx.ops.remove_file(&subtitle.path) {
+                    eprintln!("{color_red}Failed to remove the original subtitle file: {}{color_reset}", e);
+                }
+            }
+            continue;
+        }
+        for (source_path, dest_path) in subtitle.placements(&sub_dest_path) {
+            match ctx.ops.transfer(&source_path, &dest_path, ctx.config.transfer) {
+                Ok(()) => println!("{color_green}Placed subtitle file at: {:?}{color_reset}", dest_path),
+                Err(e) => eprintln!("{color_red}Failed to transfer the subtitle file: {}{color_reset}", e),
+            }
+        }
+    }
+
+    for extra in extras {
+        let Some(dest_path) = get_extra_dest_path(extra, &movie_dir_dest_path) else {
+            continue;
+        };
+        let transfer_result = dest_path
+            .parent()
+            .map_or(Ok(()), |extras_folder_path| ctx.ops.create_dir_all(extras_folder_path))
+            .and_then(|_| ctx.ops.transfer(&extra.path, &dest_path, ctx.config.transfer));
+        match transfer_result {
+            Ok(()) => println!("{color_green}Placed extra at: {:?}{color_reset}", dest_path),
+            Err(e) => eprintln!("{color_red}Failed to transfer the extra: {}{color_reset}", e),
+        }
+    }
+
+    match video_parts.is_empty() {
+        true => download_missing_subtitles(&parsed_movie_metadata, subtitles, &movie_dir_dest_path, ctx).await,
+        false if ctx.config.subtitles.download => {
+            println!("{color_yellow}Not downloading subtitles for a movie split into parts{color_reset}")
+        }
+        false => {}
+    }
+
+    match write_metadata_file(&parsed_movie_metadata, &movie_dir_dest_path, ctx) {
+        Ok(()) => println!("{color_green}Metadata file created{color_reset}"),
+        Err(e) => eprintln!("{color_red}Failed to write the movie metadata: {}{color_reset}", e),
+    }
+    true
+}
+
+/// Reads the resolution, codec and tracks from the video itself, keeping what the file name
+/// says when the container can't be read.
+fn update_metadata_from_container(metadata: &mut MovieMetadata, video_path: &Path) {
+    match probe(video_path) {
+        Ok(Some(media_info)) => merge_media_info(metadata, media_info),
+        Ok(None) => {}
+        Err(e) => println!("{color_yellow}Failed to read the video container: {}{color_reset}", e),
+    }
+}
+
+/// The name the movie is parsed from: the video's own, or the name its parts share with the
+/// extension of the first part, like `Movie (1999).avi` for `Movie (1999) CD1.avi`.
+pub fn get_movie_file_name(video_path: &Path, video_parts: &[(u32, PathBuf)]) -> String {
+    let first_part_name = video_parts.first().and_then(|(_, part_path)| {
+        let stem = part_path.file_stem()?.to_string_lossy();
+        let (base_name, _) = split_part_number(&stem)?;
+        let extension = part_path.extension()?.to_string_lossy();
+        Some(format!("{}.{}", base_name, extension))
+    });
+
+    first_part_name.unwrap_or_else(|| {
+        video_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    })
+}
+
+/// Parts are named the way media servers stack them, like `Title (Year) - part1.avi`.
+fn get_part_file_name(movie_file_name: &str, part_number: u32, part_path: &Path) -> String {
+    let extension = part_path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    format!("{}.{}", get_part_name(movie_file_name, part_number, None), extension)
+}
+
+/// Subtitles of a part carry its number before their language, like
+/// `Title (Year) - part1.en.srt`, so they go with the part's video.
+fn get_subtitle_file_name(subtitle_name: &str, subtitle: &Subtitle, extension: &str) -> String {
+    match subtitle.part {
+        Some(part_number) => format!(
+            "{}.{}",
+            get_part_name(subtitle_name, part_number, Some(subtitle.language.code)),
+            extension
+        ),
+        None => format!("{}.{}", subtitle_name, extension),
+    }
+}
+
+fn get_part_name(name: &str, part_number: u32, language: Option<&str>) -> String {
+    let language_suffix = language.map(|code| format!(".{}", code)).unwrap_or_default();
+    match name.strip_suffix(&language_suffix) {
+        Some(stem) if !language_suffix.is_empty() => format!("{} - part{}{}", stem, part_number, language_suffix),
+        _ => format!("{} - part{}", name, part_number),
+    }
+}
+
+/// Downloads a subtitle for each preferred language the movie has none in, once its hash or
+/// IMDb id is known.
+async fn download_missing_subtitles(metadata: &MovieMetadata, subtitles: &[Subtitle], movie_dir_path: &Path, ctx: &Context) {
+    if metadata.movie_hash.is_none() && metadata.imdb_id.is_none() {
+        return;
+    }
+    // downloads themselves are not cached
+    if !ctx.config.subtitles.download || ctx.cache.is_offline() {
+        return;
+    }
+
+    for language in ctx.config.subtitle_languages() {
+        if subtitles.iter().any(|subtitle| subtitle.language.code == language.code) {
+            continue;
+        }
+
+        let content = match download_subtitle(
+            metadata.movie_hash.as_deref(),
+            metadata.imdb_id.as_deref(),
+            language,
+            &ctx.config.subtitles,
+            &ctx.cache,
+            &ctx.http,
+        )
+        .await
+        {
+            Ok(content) => content,
+            Err(e) => {
+                println!("{color_yellow}No {} subtitle downloaded: {}{color_reset}", language.name(), e);
+                continue;
+            }
+        };
+        let (content, format) = match normalize_content(&content, SubtitleFormat::Srt, &ctx.config.subtitles) {
+            Some(normalized) => (normalized.content.into_bytes(), normalized.format),
+            None => (content, SubtitleFormat::Srt),
+        };
+
+        let sub_dest_path = movie_dir_path.join(format!(
+            "{}.{}",
+            render_movie_name(&ctx.config.naming.subtitle_file, metadata, Some(language.code)),
+            format.extension()
+        ));
+        match ctx.ops.write(&sub_dest_path, content) {
+            Ok(()) => println!(
+                "{color_green}Downloaded {} subtitle to: {:?}{color_reset}",
+                language.name(),
+                sub_dest_path
+            ),
+            Err(e) => eprintln!("{color_red}Failed to write the downloaded subtitle: {}{color_reset}", e),
+        }
+    }
+}
+
+/// Writes a cleaned up or converted copy of a text subtitle to `dest_path`, with the extension
+/// of its new format. Returns where it was written, or `None` when the subtitle is fine as it is.
+fn write_normalized_subtitle(subtitle_path: &Path, dest_path: &Path, ctx: &Context) -> Option<PathBuf> {
+    let normalized = normalize_subtitle(&ctx.ops.current_path(subtitle_path), &ctx.config.subtitles)?;
+    let dest_path = dest_path.with_extension(normalized.format.extension());
+    if let Err(e) = ctx.ops.write(&dest_path, normalized.content) {
+        eprintln!("{color_red}Failed to write the cleaned up subtitle: {}{color_reset}", e);
+        return None;
+    }
+
+    if normalized.removed_cue_count > 0 {
+        println!(
+            "{color_yellow}Removed {} ad or empty cue(s) from the subtitle{color_reset}",
+            normalized.removed_cue_count
+        );
+    }
+    println!("{color_green}Wrote cleaned up subtitle to: {:?}{color_reset}", dest_path);
+    Some(dest_path)
+}
+
+async fn update_metadata_from_providers(metadata: &mut MovieMetadata, ctx: &Context) {
+    let providers = build_providers(&ctx.config.metadata, &ctx.cache, &ctx.http);
+    let candidates = find_movie(&providers, &metadata.media_name, metadata.release_year)
+        .await
+        .map(|(_, candidates)| candidates)
+        .unwrap_or_default();
+
+    let chosen_match = settle_match(
+        metadata,
+        candidates,
+        &providers,
+        ctx.match_mode,
+        ctx.config.metadata.confidence_threshold,
+        &mut io::stdin().lock(),
+    )
+    .await;
+    let Some(movie) = chosen_match else {
+        return;
+    };
+
+    metadata.media_name = movie.title.clone();
+    metadata.release_year = metadata.release_year.or(movie.year);
+    metadata.imdb_id = movie.imdb_id.clone();
+    println!(
+        "{color_cyan}Updated metadata ({:.0}% confident): {:?}{color_reset}",
+        movie.confidence * 100.0,
+        &movie
+    );
+}
+
+fn needs_processing(dir_path: &Path, ctx: &Context) -> bool {
+    if read_metadata_file(&ctx.ops.current_path(dir_path)).is_some() {
+        let file_count = ctx
+            .ops
+            .read_dir(dir_path)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|path| ctx.ops.is_file(path))
             .count();
         return file_count < 2;
     }
@@ -172,64 +547,66 @@ fn needs_processing(dir_path: &Path) -> bool {
     true
 }
 
-fn has_valid_metadata_json(dir_path: &Path) -> bool {
+pub fn read_metadata_file(dir_path: &Path) -> Option<MovieMetadata> {
     let metadata_file = dir_path.join(METADATA_FILE_NAME);
+    if !metadata_file.is_file() {
+        return None;
+    }
+
+    let file_content = fs::read_to_string(&metadata_file).ok()?;
+    serde_json::from_str(&file_content).ok()
+}
 
-    if metadata_file.exists() && metadata_file.is_file() {
-        if let Ok(file_content) = fs::read_to_string(&metadata_file) {
-            let deserialization_result: serde_json::Result<MovieMetadata> =
-                serde_json::from_str(&file_content);
-            return deserialization_result.is_ok();
+/// The hash an earlier run stored for the video, as long as the video is still the one it
+/// named, or else the hash worked out from the video itself.
+fn get_movie_hash(dir_path: &Path, video_path: &Path, ctx: &Context) -> Option<String> {
+    let stored_hash = read_metadata_file(&ctx.ops.current_path(dir_path)).and_then(|metadata| {
+        let file_name = format!(
+            "{}.{}",
+            render_movie_name(&ctx.config.naming.movie_file, &metadata, None),
+            metadata.file_extension
+        );
+        match video_path.file_name() == Some(file_name.as_ref()) {
+            true => metadata.movie_hash,
+            false => None,
         }
-    }
+    });
 
-    false
+    stored_hash.or_else(|| compute_movie_hash(&ctx.ops.current_path(video_path)).ok())
 }
 
-fn write_metadata_file(data: &MovieMetadata, directory_path: &Path) -> io::Result<()> {
+fn write_metadata_file(data: &MovieMetadata, directory_path: &Path, ctx: &Context) -> io::Result<()> {
     // ensure the directory exists
-    if !directory_path.exists() {
-        fs::create_dir_all(directory_path)?;
-    }
+    ctx.ops.create_dir_all(directory_path)?;
 
     // define the file path
-    let file_path = merge_base_with_file(&directory_path, METADATA_FILE_NAME);
+    let file_path = merge_base_with_file(directory_path, METADATA_FILE_NAME);
     let json_data = serde_json::to_string_pretty(data)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Serialization error: {}", e)))?;
+        .map_err(|e| io::Error::other(format!("Serialization error: {}", e)))?;
 
     // write the JSON data to the file
-    let mut file = fs::File::create(&file_path)?;
-    file.write_all(json_data.as_bytes())?;
+    ctx.ops.write(Path::new(&file_path), json_data)?;
 
     Ok(())
 }
 
 fn delete_except<P: AsRef<Path>>(
     dir: P,
-    keep: &DirEntry,
-    optional_keep: &Option<DirEntry>,
+    keep: &Path,
+    also_keep: &[PathBuf],
+    ctx: &Context,
 ) -> io::Result<()> {
-    for entry_result in fs::read_dir(&dir)? {
-        let entry = match entry_result {
-            Ok(entry) => entry,
-            Err(err) => {
-                eprintln!("Failed to read directory entry: {}", err);
-                continue;
-            }
-        };
-
-        let path = entry.path();
-
+    for path in ctx.ops.read_dir(dir.as_ref())? {
         // Check if this is a file/directory to keep
-        if path == keep.path() || optional_keep.as_ref().map_or(false, |e| e.path() == path) {
+        if path == keep || also_keep.contains(&path) {
             continue;
         }
 
         // Attempt to delete directories recursively or files, logging on failure
-        let delete_result = if path.is_dir() {
-            fs::remove_dir_all(&path)
+        let delete_result = if ctx.ops.is_dir(&path) {
+            ctx.ops.remove_dir_all(&path)
         } else {
-            fs::remove_file(&path)
+            ctx.ops.remove_file(&path)
         };
 
         if let Err(err) = delete_result {
@@ -240,59 +617,144 @@ fn delete_except<P: AsRef<Path>>(
     Ok(())
 }
 
-fn get_video_file_entry(dir_path: &Path) -> Option<DirEntry> {
-    fs::read_dir(dir_path)
+/// The largest video that is not named as an extra, or the largest video when they all are.
+fn get_video_file_path(dir_path: &Path, ctx: &Context) -> Option<PathBuf> {
+    let (extra_paths, video_file_paths): (Vec<PathBuf>, Vec<PathBuf>) = ctx
+        .ops
+        .read_dir(dir_path)
         .ok()?
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| {
-            let path = entry.path();
-            if let Some(ext) = path.extension() {
-                VIDEO_FILE_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str())
-            } else {
-                false
-            }
-        })
-        .max_by_key(|entry| entry.metadata().map(|meta| meta.len()).unwrap_or(0))
+        .into_iter()
+        .filter(|path| ctx.ops.is_file(path) && ctx.config.is_video_file(path))
+        .partition(|path| classify_extra(path, dir_path).is_some());
+    let size_of = |path: &PathBuf| get_size(path, ctx);
+
+    video_file_paths
+        .into_iter()
+        .max_by_key(size_of)
+        .or_else(|| extra_paths.into_iter().max_by_key(size_of))
 }
 
-fn get_subtitle_entry(dir_entry: &DirEntry) -> Option<DirEntry> {
-    let dir_entries = fs::read_dir(dir_entry.path()).ok()?;
+/// The size of a file, read from where it still is in a dry run.
+fn get_size(path: &Path, ctx: &Context) -> u64 {
+    fs::metadata(ctx.ops.current_path(path)).map(|meta| meta.len()).unwrap_or(0)
+}
 
-    // collect subtitle files in the current directory
-    let mut subtitle_files: Vec<DirEntry> = dir_entries
-        .filter_map(Result::ok)
-        .filter(|entry| {
-            entry.path().is_file()
-                && entry
-                    .path()
-                    .extension()
-                    .map(|ext| ext == SUBTITLE_FILE_EXTENSION)
-                    .unwrap_or(false)
-        })
+/// The parts of a movie split across several videos in the movie directory, like `cd1` and
+/// `cd2`, or none when `video_path` is the whole movie.
+fn get_video_parts(dir_path: &Path, video_path: &Path, ctx: &Context) -> Vec<(u32, PathBuf)> {
+    let Ok(dir_paths) = ctx.ops.read_dir(dir_path) else {
+        return Vec::new();
+    };
+    let video_paths: Vec<PathBuf> = dir_paths
+        .into_iter()
+        .filter(|path| ctx.config.is_video_file(path))
         .collect();
 
-    // if subtitle files are found, prioritize by name
-    if !subtitle_files.is_empty() {
-        subtitle_files.sort_by_key(|entry| {
-            let name = entry.file_name().to_string_lossy().to_lowercase();
-            (
-                !name.contains("english"),
-                !name.contains("en"),
-                name.clone(),
-            )
-        });
-        return subtitle_files.into_iter().next(); // Return the first (best match)
+    find_video_parts(video_path, &video_paths)
+}
+
+/// Every other video in the movie directory and its folders, with the kind of extra it is.
+fn get_extras(dir_path: &Path, movie_paths: &[PathBuf], ctx: &Context) -> Vec<Extra> {
+    let movie_size = movie_paths.iter().map(|path| get_size(path, ctx)).max().unwrap_or(0);
+
+    get_video_paths(dir_path, ctx)
+        .into_iter()
+        .filter(|path| !movie_paths.contains(path))
+        .map(|path| {
+            let kind = classify_extra(&path, dir_path).unwrap_or_else(|| {
+                let duration_ms = probe(&ctx.ops.current_path(&path))
+                    .ok()
+                    .flatten()
+                    .and_then(|media_info| media_info.duration_ms);
+                classify_unnamed_extra(duration_ms, get_size(&path, ctx), movie_size)
+            });
+            Extra { kind, path }
+        })
+        .collect()
+}
+
+/// Moves extras into the folders Plex and Jellyfin look for them in and returns those folders
+/// for the cleanup to keep. Samples are left for the cleanup, unless they are in one of them.
+fn place_extras(extras: &[Extra], movie_dir_path: &Path, ctx: &Context) -> Vec<PathBuf> {
+    let mut extras_folder_paths: Vec<PathBuf> = Vec::new();
+    for extra in extras {
+        let Some(dest_path) = get_extra_dest_path(extra, movie_dir_path) else {
+            continue;
+        };
+        let extras_folder_path = dest_path.parent().unwrap().to_path_buf();
+        if !extras_folder_paths.contains(&extras_folder_path) {
+            extras_folder_paths.push(extras_folder_path.clone());
+        }
+        if dest_path == extra.path {
+            continue;
+        }
+        if ctx.ops.exists(&dest_path) {
+            println!("{color_yellow}Not moving extra, target already exists: {:?}{color_reset}", dest_path);
+            continue;
+        }
+
+        let move_result = ctx.ops
+            .create_dir_all(&extras_folder_path)
+            .and_then(|_| ctx.ops.rename(&extra.path, &dest_path));
+        match move_result {
+            Ok(()) => println!("{color_green}Moved extra to: {:?}{color_reset}", dest_path),
+            Err(e) => eprintln!("{color_red}Failed to move the extra: {}{color_reset}", e),
+        }
     }
 
-    // recursively search subdirectories for subtitle files
-    let dir_entries = fs::read_dir(dir_entry.path()).ok()?;
-    for entry in dir_entries.filter_map(Result::ok) {
-        if entry.path().is_dir() {
-            if let Some(subtitle) = get_subtitle_entry(&entry) {
-                return Some(subtitle);
-            }
+    let kept_samples = extras.iter().filter(|extra| {
+        extra.kind == ExtraKind::Sample && extras_folder_paths.iter().any(|folder_path| extra.path.starts_with(folder_path))
+    });
+    for sample in kept_samples {
+        if let Err(e) = ctx.ops.remove_file(&sample.path) {
+            eprintln!("Failed to delete {:?}: {}", sample.path, e);
+        }
+    }
+
+    extras_folder_paths
+}
+
+/// Extras go in a folder of the movie directory named after their kind, keeping their names.
+fn get_extra_dest_path(extra: &Extra, movie_dir_path: &Path) -> Option<PathBuf> {
+    let folder_name = extra.kind.folder_name()?;
+    Some(movie_dir_path.join(folder_name).join(extra.path.file_name()?))
+}
+
+fn get_video_paths(dir_path: &Path, ctx: &Context) -> Vec<PathBuf> {
+    let Ok(dir_paths) = ctx.ops.read_dir(dir_path) else {
+        return Vec::new();
+    };
+
+    let mut video_paths = Vec::new();
+    for path in dir_paths {
+        if ctx.ops.is_dir(&path) {
+            video_paths.extend(get_video_paths(&path, ctx));
+        } else if ctx.config.is_video_file(&path) {
+            video_paths.push(path);
+        }
+    }
+
+    video_paths
+}
+
+/// Every subtitle in the movie directory and its subdirectories, like `Subs/2_English.srt`,
+/// leaving out the ones of extras.
+fn get_subtitle_paths(dir_path: &Path, ctx: &Context) -> Vec<PathBuf> {
+    let Ok(dir_paths) = ctx.ops.read_dir(dir_path) else {
+        return Vec::new();
+    };
+
+    let mut subtitle_paths = Vec::new();
+    for path in dir_paths {
+        let is_dir = ctx.ops.is_dir(&path);
+        if is_dir && is_extras_folder(&path.file_name().unwrap_or_default().to_string_lossy()) {
+            continue;
+        } else if is_dir {
+            subtitle_paths.extend(get_subtitle_paths(&path, ctx));
+        } else if ctx.config.is_subtitle_file(&path) {
+            subtitle_paths.push(path);
         }
     }
 
-    None
+    subtitle_paths
 }
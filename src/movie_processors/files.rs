@@ -1,12 +1,18 @@
-use crate::constants::VIDEO_FILE_EXTENSIONS;
-use crate::utils::get_raw_file_name_and_extension;
+use crate::context::Context;
+use crate::extras::{classify_extra, Extra, ExtraKind};
+use crate::file_operations::TransferMode;
+use crate::movie_processors::directories::{get_movie_file_name, transfer_movie};
+use crate::subtitles::select_part_subtitles;
+use crate::utils::{find_video_parts, get_raw_file_name_and_extension};
 use inline_colorization::{color_red, color_green, color_reset};
-use std::fs;
+use std::collections::HashSet;
 use std::fs::DirEntry;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-pub fn process_files(directory_path: &Path, file_paths: Vec<DirEntry>) {
-    let video_file_entries = filter_video_files(&file_paths);
+pub fn process_files(directory_path: &Path, file_paths: Vec<DirEntry>, ctx: &Context) {
+    let video_file_entries = filter_video_files(&file_paths, ctx);
+    let video_paths: Vec<PathBuf> = video_file_entries.iter().map(|entry| entry.path()).collect();
+    let mut movie_directory_paths: HashSet<PathBuf> = HashSet::new();
     for video_file_entry in &video_file_entries {
         let raw_video_file_name = &video_file_entry
             .path()
@@ -15,19 +21,29 @@ pub fn process_files(directory_path: &Path, file_paths: Vec<DirEntry>) {
             .map(String::from)
             .unwrap_or_default();
 
-        // Create the movie directory
-        let movie_directory_path = directory_path.join(raw_video_file_name);
-        if let Err(e) = fs::create_dir(&movie_directory_path) {
-            eprintln!(
-                "{color_red}Failed to create the movie sub-directory: {}{color_reset}",
-                e
-            );
-            continue;
+        // Create the movie directory, which the parts of a movie share
+        let video_parts = find_video_parts(&video_file_entry.path(), &video_paths);
+        let movie_directory_name = match video_parts.is_empty() {
+            true => raw_video_file_name.clone(),
+            false => get_raw_file_name_and_extension(&get_movie_file_name(&video_file_entry.path(), &video_parts))
+                .0
+                .to_string(),
+        };
+        let movie_directory_path = directory_path.join(movie_directory_name);
+        if !movie_directory_paths.contains(&movie_directory_path) {
+            if let Err(e) = ctx.ops.create_dir(&movie_directory_path) {
+                eprintln!(
+                    "{color_red}Failed to create the movie sub-directory: {}{color_reset}",
+                    e
+                );
+                continue;
+            }
+            movie_directory_paths.insert(movie_directory_path.clone());
         }
 
         // Move the video file to the sub-directory
         let movie_dest_path = movie_directory_path.join(video_file_entry.file_name());
-        if let Err(e) = fs::rename(video_file_entry.path(), &movie_dest_path) {
+        if let Err(e) = ctx.ops.rename(video_file_entry.path(), &movie_dest_path) {
             eprintln!(
                 "{color_red}Failed to move the movie file to the sub-directory: {}{color_reset}",
                 e
@@ -44,6 +60,7 @@ pub fn process_files(directory_path: &Path, file_paths: Vec<DirEntry>) {
         let related_files = find_files_with_same_prefix(
             &file_paths,
             video_file_entry.file_name().to_str().unwrap(),
+            ctx,
         );
         for related_file_entry in related_files {
             let related_dest_path = movie_directory_path.join(
@@ -53,7 +70,7 @@ pub fn process_files(directory_path: &Path, file_paths: Vec<DirEntry>) {
                     .map(String::from)
                     .unwrap_or_default(),
             );
-            if let Err(e) = fs::rename(related_file_entry.path(), &related_dest_path) {
+            if let Err(e) = ctx.ops.rename(related_file_entry.path(), &related_dest_path) {
                 eprintln!("{color_red}Failed to move a related file to the sub-directory: {}{color_reset}", e);
             } else {
                 println!(
@@ -65,9 +82,77 @@ pub fn process_files(directory_path: &Path, file_paths: Vec<DirEntry>) {
     }
 }
 
+/// Puts loose movies straight into directories of their own in `library_path` instead of
+/// grouping them into directories first, as linking or copying leaves the originals as they
+/// are. The parts of a movie go into one directory together.
+pub async fn transfer_files(file_paths: Vec<DirEntry>, library_path: &Path, ctx: &Context) {
+    let video_file_entries = filter_video_files(&file_paths, ctx);
+    let video_paths: Vec<PathBuf> = video_file_entries.iter().map(|entry| entry.path()).collect();
+    for video_file_entry in video_file_entries {
+        let video_parts = find_video_parts(&video_file_entry.path(), &video_paths);
+        if video_parts.first().is_some_and(|(_, first_part_path)| *first_part_path != video_file_entry.path()) {
+            continue;
+        }
+
+        let movie_paths: Vec<PathBuf> = match video_parts.is_empty() {
+            true => vec![video_file_entry.path()],
+            false => video_parts.iter().map(|(_, part_path)| part_path.clone()).collect(),
+        };
+        let mut related_paths: Vec<PathBuf> = Vec::new();
+        let mut extras: Vec<Extra> = Vec::new();
+        for movie_path in &movie_paths {
+            let movie_file_name = movie_path.file_name().unwrap().to_string_lossy();
+            let (raw_file_name, _) = get_raw_file_name_and_extension(&movie_file_name);
+            for related_entry in find_files_with_same_prefix(&file_paths, &movie_file_name, ctx) {
+                if let Some(kind) = get_extra_kind(&related_entry.path(), raw_file_name) {
+                    extras.push(Extra { kind, path: related_entry.path() });
+                }
+                related_paths.push(related_entry.path());
+            }
+        }
+        let subtitle_paths: Vec<PathBuf> = related_paths
+            .iter()
+            .filter(|path| ctx.config.is_subtitle_file(path))
+            .cloned()
+            .collect();
+        let part_numbers: Vec<u32> = video_parts.iter().map(|(part_number, _)| *part_number).collect();
+        let subtitles = select_part_subtitles(&subtitle_paths, &ctx.config.subtitle_languages(), &part_numbers, &ctx.ops);
+
+        let video_file_name = get_movie_file_name(&video_file_entry.path(), &video_parts);
+        let placed = transfer_movie(
+            &video_file_entry.path(),
+            &video_parts,
+            &subtitles,
+            &extras,
+            &video_file_name,
+            library_path,
+            ctx,
+        )
+        .await;
+        if !placed || ctx.config.transfer != TransferMode::Move {
+            continue;
+        }
+
+        // what the movie's directory would have been cleaned of
+        let placed_paths: Vec<&PathBuf> = subtitles
+            .iter()
+            .flat_map(|subtitle| subtitle.paths())
+            .chain(extras.iter().filter(|extra| extra.kind.folder_name().is_some()).map(|extra| &extra.path))
+            .collect();
+        for related_path in related_paths.iter().filter(|path| !placed_paths.contains(path)) {
+            if let Err(e) = ctx.ops.remove_file(related_path) {
+                eprintln!("{color_red}Failed to remove {:?}: {}{color_reset}", related_path, e);
+            }
+        }
+    }
+}
+
+/// Files named after the video, like its subtitles. Other videos only go along with it when
+/// they are its extras, so a sequel like `Movie II.mkv` keeps a directory of its own.
 fn find_files_with_same_prefix<'a>(
     file_paths: &'a [DirEntry],
     file_name: &str,
+    ctx: &Context,
 ) -> Vec<&'a DirEntry> {
     let (raw_file_name, _) = get_raw_file_name_and_extension(file_name);
     file_paths
@@ -77,22 +162,65 @@ fn find_files_with_same_prefix<'a>(
                 .path()
                 .file_stem()
                 .and_then(|stem| stem.to_str())
-                .map_or(false, |stem_str| {
+                .is_some_and(|stem_str| {
                     stem_str.starts_with(raw_file_name) && stem_str != raw_file_name
                 })
         })
+        .filter(|entry| !ctx.config.is_video_file(&entry.path()) || get_extra_kind(&entry.path(), raw_file_name).is_some())
         .collect()
 }
 
-fn filter_video_files(file_paths: &[DirEntry]) -> Vec<&DirEntry> {
-    file_paths
+/// What kind of extra a video is of the movie named `raw_file_name`, told by the words after
+/// the movie's name, like `Movie-sample.mkv` or `Movie.Trailer.mkv`.
+fn get_extra_kind(path: &Path, raw_file_name: &str) -> Option<ExtraKind> {
+    let file_name = path.file_name()?.to_string_lossy();
+    let rest = file_name.strip_prefix(raw_file_name)?;
+    let separators = [' ', '.', '_', '-'];
+    if !rest.starts_with(separators) {
+        return None;
+    }
+
+    let extra_name = rest.trim_start_matches(separators);
+    classify_extra(Path::new(extra_name), Path::new(""))
+}
+
+/// Videos named after another one, like `Movie-sample.mkv`, go along with it as related files
+/// instead of getting a directory of their own.
+fn filter_video_files<'a>(file_paths: &'a [DirEntry], ctx: &Context) -> Vec<&'a DirEntry> {
+    let video_file_entries: Vec<&DirEntry> = file_paths
         .iter()
-        .filter(|entry| {
-            entry
-                .path()
-                .extension()
-                .and_then(|ext| ext.to_str())
-                .map_or(false, |ext| VIDEO_FILE_EXTENSIONS.contains(&ext))
-        })
+        .filter(|entry| ctx.config.is_video_file(&entry.path()))
+        .collect();
+    let related_paths: Vec<PathBuf> = video_file_entries
+        .iter()
+        .flat_map(|entry| find_files_with_same_prefix(file_paths, &entry.file_name().to_string_lossy(), ctx))
+        .map(|entry| entry.path())
+        .collect();
+
+    video_file_entries
+        .into_iter()
+        .filter(|entry| !related_paths.contains(&entry.path()))
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extras_named_after_the_movie_are_related() {
+        assert_eq!(get_extra_kind(Path::new("/movies/Alien-sample.mkv"), "Alien"), Some(ExtraKind::Sample));
+        assert_eq!(get_extra_kind(Path::new("/movies/Alien.Trailer.1080p.mkv"), "Alien"), Some(ExtraKind::Trailer));
+        assert_eq!(
+            get_extra_kind(Path::new("/movies/Alien Deleted Scenes.mkv"), "Alien"),
+            Some(ExtraKind::DeletedScene)
+        );
+    }
+
+    #[test]
+    fn movies_sharing_a_prefix_are_not_related() {
+        assert_eq!(get_extra_kind(Path::new("/movies/Aliens.mkv"), "Alien"), None);
+        assert_eq!(get_extra_kind(Path::new("/movies/Saw II.mkv"), "Saw"), None);
+        assert_eq!(get_extra_kind(Path::new("/movies/Alien.Covenant.2017.mkv"), "Alien"), None);
+    }
+}
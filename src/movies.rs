@@ -1,26 +1,44 @@
-use std::fs;
 use std::fs::{DirEntry, ReadDir};
-use std::path::Path;
-use inline_colorization::{color_yellow, color_reset};
+use std::path::{Path, PathBuf};
+use inline_colorization::{color_red, color_yellow, color_reset};
+use crate::context::Context;
+use crate::file_operations::TransferMode;
 use crate::movie_processors;
+use crate::utils::is_hidden;
 
-pub async fn handle_movies(directory_path: &Path, dir_entries: ReadDir) {
+pub async fn handle_movies(directory_path: &Path, dir_entries: ReadDir, ctx: &Context) {
     let files: Vec<DirEntry> = dir_entries
-        .map(|entry| entry.unwrap())
-        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                eprintln!("{color_red}Failed to read a directory entry: {}{color_reset}", e);
+                None
+            }
+        })
+        .filter(|entry| entry.path().is_file() && !is_hidden(&entry.path()))
         .collect();
 
-    // collect this into directories
-    if files.len() > 0 {
-        movie_processors::files::process_files(&directory_path, files);
+    if !files.is_empty() && ctx.config.transfer != TransferMode::Move {
+        let library_path = ctx.output_path.as_deref().unwrap_or(directory_path);
+        movie_processors::files::transfer_files(files, library_path, ctx).await;
+    } else if !files.is_empty() {
+        movie_processors::files::process_files(directory_path, files, ctx);
     } else {
         println!("{color_yellow}No files to process{color_reset}")
     }
 
-    let directories = fs::read_dir(&directory_path)
-        .unwrap()
-        .map(|entry| entry.unwrap())
-        .filter(|entry| entry.path().is_dir())
+    // listed through the file operations so a dry run sees the directories it planned above
+    let directory_paths = match ctx.ops.read_dir(directory_path) {
+        Ok(paths) => paths,
+        Err(e) => {
+            eprintln!("{color_red}Failed to read {:?}: {}{color_reset}", directory_path, e);
+            return;
+        }
+    };
+    let mut directories: Vec<PathBuf> = directory_paths
+        .into_iter()
+        .filter(|path| ctx.ops.is_dir(path) && !is_hidden(path) && !ctx.is_output_path(path))
         .collect();
-    movie_processors::directories::process_directories(directories).await;
-}
\ No newline at end of file
+    directories.sort();
+    movie_processors::directories::process_directories(directories, ctx).await;
+}
@@ -0,0 +1,336 @@
+use crate::models::MovieMetadata;
+use crate::utils::clean_filename;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+pub const DEFAULT_MOVIE_TEMPLATE: &str = "{title}< ({year})>< {{edition-{edition}}}>< [{resolution}p]>";
+
+pub const DEFAULT_SUBTITLE_TEMPLATE: &str = "{title}< ({year})>< {{edition-{edition}}}>< [{resolution}p]>.{language}";
+
+const MOVIE_FIELDS: [&str; 7] = ["title", "year", "edition", "resolution", "encoding", "imdb_id", "language"];
+
+/// The fields every movie has a value for.
+const ALWAYS_KNOWN_FIELDS: [&str; 1] = ["title"];
+
+/// A file name template.
+///
+/// * `{field}` is replaced with the field's value, e.g. `{title}` or `{year}`
+/// * `{field|filter|...}` applies filters to the value: `upper`, `lower`, `title`, `sanitize`
+/// * `<...>` is only rendered when every field inside it has a value
+/// * `{{` and `}}` are literal braces, e.g. `{{imdb-{imdb_id}}}` renders `{imdb-tt0133093}`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Field { name: String, filters: Vec<Filter> },
+    Optional(Vec<Segment>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Filter {
+    Upper,
+    Lower,
+    Title,
+    Sanitize,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Template, String> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut position = 0;
+        let segments = parse_segments(&chars, &mut position, false)?;
+
+        Ok(Template {
+            source: source.to_string(),
+            segments,
+        })
+    }
+
+    /// Fields outside `<...>` that have no value are rendered empty.
+    pub fn render(&self, values: &HashMap<&str, String>) -> String {
+        render_segments(&self.segments, values, false).unwrap_or_default()
+    }
+
+    /// The fields outside `<...>`, which the template can't leave out.
+    pub fn required_fields(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Field { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Every field in the template, inside `<...>` or not.
+    pub fn fields(&self) -> Vec<&str> {
+        collect_fields(&self.segments)
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Template::parse(&source)
+    }
+}
+
+impl std::fmt::Display for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Renders a movie template into a name that is safe to use on disk.
+pub fn render_movie_name(template: &Template, metadata: &MovieMetadata, language: Option<&str>) -> String {
+    let mut values = HashMap::new();
+    values.insert("title", metadata.media_name.clone());
+    if let Some(year) = metadata.release_year {
+        values.insert("year", year.to_string());
+    }
+    if let Some(edition) = &metadata.edition {
+        values.insert("edition", edition.clone());
+    }
+    if let Some(resolution) = metadata.resolution {
+        values.insert("resolution", resolution.to_string());
+    }
+    if let Some(encoding_format) = &metadata.encoding_format {
+        values.insert("encoding", format!("{:?}", encoding_format).to_lowercase());
+    }
+    if let Some(imdb_id) = &metadata.imdb_id {
+        values.insert("imdb_id", imdb_id.clone());
+    }
+    if let Some(language) = language {
+        values.insert("language", language.to_string());
+    }
+
+    clean_filename(&template.render(&values)).unwrap_or_else(|| metadata.media_name.clone())
+}
+
+/// Checks a movie template when the config is loaded. A subtitle template must name the language,
+/// or every subtitle of a movie would get the same name. Returns a warning about the fields
+/// outside `<...>` that a movie may have no value for, which are rendered empty.
+pub fn check_movie_template(template: &Template, is_subtitle_template: bool) -> Result<Option<String>, String> {
+    if is_subtitle_template && !template.fields().contains(&"language") {
+        return Err(format!("{:?} has no `{{language}}`, so the subtitles in each language would get the same name", template.source));
+    }
+
+    let is_known = |field: &str| ALWAYS_KNOWN_FIELDS.contains(&field) || (is_subtitle_template && field == "language");
+    let mut missing_fields: Vec<&str> = template.required_fields().filter(|field| !is_known(field)).collect();
+    missing_fields.dedup();
+    if missing_fields.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(format!(
+        "{} in {:?} may have no value, wrap them in `<...>` to leave them out when missing",
+        missing_fields.join(", "),
+        template.source
+    )))
+}
+
+fn collect_fields(segments: &[Segment]) -> Vec<&str> {
+    segments
+        .iter()
+        .flat_map(|segment| match segment {
+            Segment::Literal(_) => Vec::new(),
+            Segment::Field { name, .. } => vec![name.as_str()],
+            Segment::Optional(optional_segments) => collect_fields(optional_segments),
+        })
+        .collect()
+}
+
+fn parse_segments(chars: &[char], position: &mut usize, in_optional: bool) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+
+    while *position < chars.len() {
+        let c = chars[*position];
+        let next = chars.get(*position + 1).copied();
+
+        match c {
+            '{' if next == Some('{') => {
+                literal.push('{');
+                *position += 2;
+            }
+            '}' if next == Some('}') => {
+                literal.push('}');
+                *position += 2;
+            }
+            '{' => {
+                flush_literal(&mut literal, &mut segments);
+                *position += 1;
+                segments.push(parse_field(chars, position)?);
+            }
+            '}' => return Err(format!("unexpected `}}` at position {}, use `}}}}` for a literal brace", *position)),
+            '<' => {
+                flush_literal(&mut literal, &mut segments);
+                *position += 1;
+                let optional_segments = parse_segments(chars, position, true)?;
+                segments.push(Segment::Optional(optional_segments));
+            }
+            '>' if in_optional => {
+                *position += 1;
+                flush_literal(&mut literal, &mut segments);
+                return Ok(segments);
+            }
+            '>' => return Err(format!("unexpected `>` at position {}", *position)),
+            _ => {
+                literal.push(c);
+                *position += 1;
+            }
+        }
+    }
+
+    if in_optional {
+        return Err("unclosed `<`".to_string());
+    }
+
+    flush_literal(&mut literal, &mut segments);
+    Ok(segments)
+}
+
+fn parse_field(chars: &[char], position: &mut usize) -> Result<Segment, String> {
+    let start = *position;
+    while *position < chars.len() && chars[*position] != '}' {
+        *position += 1;
+    }
+    if *position >= chars.len() {
+        return Err(format!("unclosed `{{` at position {}", start - 1));
+    }
+
+    let expression: String = chars[start..*position].iter().collect();
+    *position += 1;
+
+    let mut parts = expression.split('|').map(str::trim);
+    let name = parts.next().unwrap_or_default().to_string();
+    if !MOVIE_FIELDS.contains(&name.as_str()) {
+        return Err(format!("unknown field `{}`, expected one of {:?}", name, MOVIE_FIELDS));
+    }
+
+    let filters = parts
+        .map(|filter| match filter {
+            "upper" => Ok(Filter::Upper),
+            "lower" => Ok(Filter::Lower),
+            "title" => Ok(Filter::Title),
+            "sanitize" => Ok(Filter::Sanitize),
+            _ => Err(format!("unknown filter `{}` on field `{}`", filter, name)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Segment::Field { name, filters })
+}
+
+fn flush_literal(literal: &mut String, segments: &mut Vec<Segment>) {
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(std::mem::take(literal)));
+    }
+}
+
+/// Returns `None` when a field of an optional section is missing so the section can be dropped.
+fn render_segments(segments: &[Segment], values: &HashMap<&str, String>, in_optional: bool) -> Option<String> {
+    let mut result = String::new();
+
+    for segment in segments {
+        match segment {
+            Segment::Literal(literal) => result.push_str(literal),
+            Segment::Field { name, filters } => match values.get(name.as_str()).filter(|value| !value.is_empty()) {
+                Some(value) => result.push_str(&apply_filters(value, filters)),
+                None if in_optional => return None,
+                None => {}
+            },
+            Segment::Optional(optional_segments) => {
+                if let Some(rendered) = render_segments(optional_segments, values, true) {
+                    result.push_str(&rendered);
+                }
+            }
+        }
+    }
+
+    Some(result)
+}
+
+fn apply_filters(value: &str, filters: &[Filter]) -> String {
+    filters.iter().fold(value.to_string(), |value, filter| match filter {
+        Filter::Upper => value.to_uppercase(),
+        Filter::Lower => value.to_lowercase(),
+        Filter::Title => value
+            .split_whitespace()
+            .map(|word| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+                    None => String::new(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        Filter::Sanitize => value
+            .replace(':', " -")
+            .chars()
+            .filter(|c| !"\\/<>\"|?*".contains(*c))
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" "),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values<'a>(pairs: &[(&'a str, &str)]) -> HashMap<&'a str, String> {
+        pairs.iter().map(|(name, value)| (*name, value.to_string())).collect()
+    }
+
+    #[test]
+    fn optional_sections_are_dropped_when_a_field_is_missing() {
+        let template = Template::parse(DEFAULT_MOVIE_TEMPLATE).unwrap();
+        assert_eq!(template.render(&values(&[("title", "Heat"), ("year", "1995")])), "Heat (1995)");
+        assert_eq!(
+            template.render(&values(&[("title", "Heat"), ("year", "1995"), ("edition", "Director's Cut"), ("resolution", "1080")])),
+            "Heat (1995) {edition-Director's Cut} [1080p]"
+        );
+    }
+
+    #[test]
+    fn missing_required_fields_are_rendered_empty() {
+        let template = Template::parse("{title} ({year}) {{imdb-{imdb_id}}}").unwrap();
+        assert_eq!(template.render(&values(&[("title", "Heat"), ("year", "1995")])), "Heat (1995) {imdb-}");
+        assert_eq!(template.required_fields().collect::<Vec<_>>(), vec!["title", "year", "imdb_id"]);
+    }
+
+    #[test]
+    fn filters_are_applied_in_order() {
+        let template = Template::parse("{title|sanitize|upper}").unwrap();
+        assert_eq!(template.render(&values(&[("title", "Mission: Impossible?")])), "MISSION - IMPOSSIBLE");
+    }
+
+    #[test]
+    fn templates_are_checked_once_when_loaded() {
+        let subtitle_template = Template::parse(DEFAULT_SUBTITLE_TEMPLATE).unwrap();
+        assert_eq!(check_movie_template(&subtitle_template, true), Ok(None));
+        let optional_language = Template::parse("{title}<.{language}>").unwrap();
+        assert_eq!(check_movie_template(&optional_language, true), Ok(None));
+        assert!(check_movie_template(&Template::parse(DEFAULT_MOVIE_TEMPLATE).unwrap(), true).is_err());
+
+        let warning = check_movie_template(&Template::parse("{title} ({year}) {{imdb-{imdb_id}}}").unwrap(), false);
+        assert!(warning.unwrap().unwrap().starts_with("year, imdb_id in "));
+        // movies have no language, only their subtitles do
+        assert!(check_movie_template(&Template::parse("{title}.{language}").unwrap(), false).unwrap().is_some());
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        assert!(Template::parse("{title").is_err());
+        assert!(Template::parse("{name}").is_err());
+        assert!(Template::parse("{title|shout}").is_err());
+        assert!(Template::parse("<{year}").is_err());
+        assert!(Template::parse("{title}}").is_err());
+    }
+}
@@ -0,0 +1,418 @@
+use super::{normalize_language, MediaInfo};
+use crate::models::{AudioTrack, MediaEncodingFormat, SubtitleTrack};
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+const EBML_HEADER_ID: u32 = 0x1a45dfa3;
+const SEGMENT_ID: u32 = 0x18538067;
+const SEEK_HEAD_ID: u32 = 0x114d9b74;
+const SEEK_ID: u32 = 0x4dbb;
+const SEEK_ELEMENT_ID: u32 = 0x53ab;
+const SEEK_POSITION_ID: u32 = 0x53ac;
+const INFO_ID: u32 = 0x1549a966;
+const TIMESTAMP_SCALE_ID: u32 = 0x2ad7b1;
+const DURATION_ID: u32 = 0x4489;
+const TRACKS_ID: u32 = 0x1654ae6b;
+const TRACK_ENTRY_ID: u32 = 0xae;
+const TRACK_TYPE_ID: u32 = 0x83;
+const CODEC_ID: u32 = 0x86;
+const LANGUAGE_ID: u32 = 0x22b59c;
+const LANGUAGE_BCP47_ID: u32 = 0x22b59d;
+const FLAG_FORCED_ID: u32 = 0x55aa;
+const VIDEO_ID: u32 = 0xe0;
+const PIXEL_WIDTH_ID: u32 = 0xb0;
+const PIXEL_HEIGHT_ID: u32 = 0xba;
+const AUDIO_ID: u32 = 0xe1;
+const CHANNELS_ID: u32 = 0x9f;
+const CLUSTER_ID: u32 = 0x1f43b675;
+
+const VIDEO_TRACK: u64 = 1;
+const AUDIO_TRACK: u64 = 2;
+const SUBTITLE_TRACK: u64 = 17;
+
+/// Nanoseconds per tick of a segment that doesn't say otherwise.
+const DEFAULT_TIMESTAMP_SCALE: u64 = 1_000_000;
+
+/// The info and tracks of a segment take a few kilobytes, far less than this.
+const MAX_HEADER_ELEMENT_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Reads the segment's info and tracks, which come before the media data in nearly every
+/// file. Ones written after it are found through the seek head.
+pub fn probe<R: Read + Seek>(reader: &mut R) -> io::Result<MediaInfo> {
+    let (id, size) = read_element_header(reader)?;
+    if id != EBML_HEADER_ID {
+        return Err(invalid_data("not a Matroska file"));
+    }
+    skip_element(reader, size)?;
+    if read_element_header(reader)?.0 != SEGMENT_ID {
+        return Err(invalid_data("the Matroska file has no segment"));
+    }
+    let segment_start = reader.stream_position()?;
+
+    let mut info = None;
+    let mut tracks = None;
+    let mut seek_positions = Vec::new();
+    while info.is_none() || tracks.is_none() {
+        let Ok((id, size)) = read_element_header(reader) else {
+            break;
+        };
+        match (id, size) {
+            (CLUSTER_ID, _) | (_, None) => break,
+            (INFO_ID, Some(size)) => info = Some(read_element_body(reader, size)?),
+            (TRACKS_ID, Some(size)) => tracks = Some(read_element_body(reader, size)?),
+            (SEEK_HEAD_ID, Some(size)) => seek_positions.extend(read_seek_positions(&read_element_body(reader, size)?)),
+            (_, size) => skip_element(reader, size)?,
+        }
+    }
+
+    for (id, position) in seek_positions {
+        let target = match id {
+            INFO_ID if info.is_none() => &mut info,
+            TRACKS_ID if tracks.is_none() => &mut tracks,
+            _ => continue,
+        };
+        // a corrupt seek entry only loses what it points to, not the rest of the file
+        let Some(element_start) = segment_start.checked_add(position) else {
+            continue;
+        };
+        let header = reader.seek(SeekFrom::Start(element_start)).and_then(|_| read_element_header(reader));
+        if let Ok((found_id, Some(size))) = header {
+            if found_id == id {
+                *target = read_element_body(reader, size).ok();
+            }
+        }
+    }
+
+    let mut media_info = MediaInfo {
+        duration_ms: info.as_deref().and_then(read_duration),
+        ..MediaInfo::default()
+    };
+    for (id, track_entry) in read_children(tracks.as_deref().unwrap_or_default()) {
+        if id == TRACK_ENTRY_ID {
+            add_track(&mut media_info, track_entry);
+        }
+    }
+
+    Ok(media_info)
+}
+
+fn add_track(media_info: &mut MediaInfo, track_entry: &[u8]) {
+    let children = read_children(track_entry);
+    let find = |id: u32| children.iter().find(|(child_id, _)| *child_id == id).map(|(_, body)| *body);
+    let codec_id = find(CODEC_ID).map(read_string).unwrap_or_default();
+    // tracks without a language are English, as the specification has it
+    let language = find(LANGUAGE_BCP47_ID)
+        .or(find(LANGUAGE_ID))
+        .map_or(Some("en".to_string()), |language| normalize_language(&read_string(language)));
+
+    match find(TRACK_TYPE_ID).map(read_uint) {
+        Some(VIDEO_TRACK) if media_info.video_codec.is_none() && media_info.width.is_none() => {
+            let video = read_children(find(VIDEO_ID).unwrap_or_default());
+            let find_video = |id: u32| video.iter().find(|(child_id, _)| *child_id == id).map(|(_, body)| read_uint(body));
+            media_info.width = find_video(PIXEL_WIDTH_ID).and_then(|width| u32::try_from(width).ok());
+            media_info.height = find_video(PIXEL_HEIGHT_ID).and_then(|height| u32::try_from(height).ok());
+            media_info.video_codec = match codec_id.as_str() {
+                "V_MPEG4/ISO/AVC" => Some(MediaEncodingFormat::H264),
+                "V_MPEGH/ISO/HEVC" => Some(MediaEncodingFormat::H265),
+                "V_AV1" => Some(MediaEncodingFormat::Av1),
+                "V_VP9" => Some(MediaEncodingFormat::Vp9),
+                _ => None,
+            };
+        }
+        Some(AUDIO_TRACK) => {
+            let audio = read_children(find(AUDIO_ID).unwrap_or_default());
+            let channels = audio
+                .iter()
+                .find(|(id, _)| *id == CHANNELS_ID)
+                .map_or(Some(1), |(_, body)| u16::try_from(read_uint(body)).ok());
+            media_info.audio_tracks.push(AudioTrack {
+                codec: audio_codec_name(&codec_id),
+                language,
+                channels,
+            });
+        }
+        Some(SUBTITLE_TRACK) => media_info.subtitle_tracks.push(SubtitleTrack {
+            codec: subtitle_codec_name(&codec_id),
+            language,
+            forced: find(FLAG_FORCED_ID).is_some_and(|forced| read_uint(forced) == 1),
+        }),
+        _ => {}
+    }
+}
+
+fn audio_codec_name(codec_id: &str) -> String {
+    let name = match codec_id {
+        _ if codec_id.starts_with("A_AAC") => "aac",
+        _ if codec_id.starts_with("A_DTS") => "dts",
+        _ if codec_id.starts_with("A_PCM") => "pcm",
+        "A_AC3" => "ac3",
+        "A_EAC3" => "eac3",
+        "A_TRUEHD" => "truehd",
+        "A_FLAC" => "flac",
+        "A_OPUS" => "opus",
+        "A_VORBIS" => "vorbis",
+        "A_MPEG/L3" => "mp3",
+        _ => return codec_id.trim_start_matches("A_").to_lowercase(),
+    };
+
+    name.to_string()
+}
+
+fn subtitle_codec_name(codec_id: &str) -> String {
+    let name = match codec_id {
+        "S_TEXT/UTF8" | "S_TEXT/ASCII" => "srt",
+        "S_TEXT/ASS" | "S_TEXT/SSA" | "S_ASS" | "S_SSA" => "ass",
+        "S_TEXT/WEBVTT" => "vtt",
+        "S_HDMV/PGS" => "pgs",
+        "S_VOBSUB" => "vobsub",
+        _ => return codec_id.trim_start_matches("S_").to_lowercase(),
+    };
+
+    name.to_string()
+}
+
+/// The duration is a float in ticks of the segment's timestamp scale.
+fn read_duration(info: &[u8]) -> Option<u64> {
+    let children = read_children(info);
+    let timestamp_scale = children
+        .iter()
+        .find(|(id, _)| *id == TIMESTAMP_SCALE_ID)
+        .map_or(DEFAULT_TIMESTAMP_SCALE, |(_, body)| read_uint(body));
+    let duration = children.iter().find(|(id, _)| *id == DURATION_ID).and_then(|(_, body)| read_float(body))?;
+
+    Some((duration * timestamp_scale as f64 / 1_000_000.0).round() as u64)
+}
+
+/// Where each top level element is, relative to the start of the segment's data.
+fn read_seek_positions(seek_head: &[u8]) -> Vec<(u32, u64)> {
+    read_children(seek_head)
+        .into_iter()
+        .filter(|(id, _)| *id == SEEK_ID)
+        .filter_map(|(_, seek)| {
+            let children = read_children(seek);
+            let find = |id: u32| children.iter().find(|(child_id, _)| *child_id == id).map(|(_, body)| read_uint(body));
+            Some((u32::try_from(find(SEEK_ELEMENT_ID)?).ok()?, find(SEEK_POSITION_ID)?))
+        })
+        .collect()
+}
+
+/// The child elements of a master element read into memory. A truncated last child keeps
+/// what there is of it.
+fn read_children(data: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut cursor = Cursor::new(data);
+    let mut children = Vec::new();
+    while let Ok((id, Some(size))) = read_element_header(&mut cursor) {
+        let start = cursor.position() as usize;
+        let end = usize::try_from(size).map_or(data.len(), |size| start.saturating_add(size).min(data.len()));
+        children.push((id, &data[start..end]));
+        cursor.set_position(end as u64);
+    }
+
+    children
+}
+
+/// Reads an element's ID, with its length marker left in as IDs are written, and its size,
+/// which is `None` for elements of unknown size.
+fn read_element_header<R: Read>(reader: &mut R) -> io::Result<(u32, Option<u64>)> {
+    let (id, id_length) = read_variable_int(reader)?;
+    if id_length > 4 {
+        return Err(invalid_data("the Matroska element ID is too long"));
+    }
+    let (size, size_length) = read_variable_int(reader)?;
+    let marker = 1u64 << (7 * size_length);
+    let size = size & (marker - 1);
+
+    match size == marker - 1 {
+        true => Ok((id as u32, None)),
+        false => Ok((id as u32, Some(size))),
+    }
+}
+
+/// EBML numbers tell their length in bytes by the number of leading zero bits in their first
+/// byte. Returns the number with the marker bit still set, and its length.
+fn read_variable_int<R: Read>(reader: &mut R) -> io::Result<(u64, usize)> {
+    let mut first_byte = [0];
+    reader.read_exact(&mut first_byte)?;
+    let length = first_byte[0].leading_zeros() as usize + 1;
+    if length > 8 {
+        return Err(invalid_data("invalid EBML number"));
+    }
+
+    let mut rest = [0; 7];
+    reader.read_exact(&mut rest[..length - 1])?;
+    let value = rest[..length - 1]
+        .iter()
+        .fold(first_byte[0] as u64, |value, byte| (value << 8) | *byte as u64);
+
+    Ok((value, length))
+}
+
+fn read_element_body<R: Read>(reader: &mut R, size: u64) -> io::Result<Vec<u8>> {
+    if size > MAX_HEADER_ELEMENT_SIZE {
+        return Err(invalid_data("the Matroska header element is too large"));
+    }
+
+    let mut body = vec![0; size as usize];
+    reader.read_exact(&mut body)?;
+    Ok(body)
+}
+
+fn skip_element<R: Seek>(reader: &mut R, size: Option<u64>) -> io::Result<()> {
+    let size = size.ok_or_else(|| invalid_data("the Matroska element has an unknown size"))?;
+    let size = i64::try_from(size).map_err(|_| invalid_data("the Matroska element is too large"))?;
+    reader.seek(SeekFrom::Current(size))?;
+    Ok(())
+}
+
+fn read_uint(body: &[u8]) -> u64 {
+    body.iter().take(8).fold(0, |value, byte| (value << 8) | *byte as u64)
+}
+
+fn read_float(body: &[u8]) -> Option<f64> {
+    match body.len() {
+        4 => Some(f32::from_be_bytes(body.try_into().ok()?) as f64),
+        8 => Some(f64::from_be_bytes(body.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+fn read_string(body: &[u8]) -> String {
+    String::from_utf8_lossy(body).trim_end_matches('\0').to_string()
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An element with its size written in eight bytes, as muxers that patch it later do.
+    fn element(id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut element = id.to_vec();
+        element.push(0x01);
+        element.extend_from_slice(&(body.len() as u64).to_be_bytes()[1..]);
+        element.extend_from_slice(body);
+        element
+    }
+
+    fn file(segment: &[u8]) -> Vec<u8> {
+        [element(&[0x1a, 0x45, 0xdf, 0xa3], &[]), element(&[0x18, 0x53, 0x80, 0x67], segment)].concat()
+    }
+
+    fn tracks() -> Vec<u8> {
+        let video = [
+            element(&[0x83], &[1]),
+            element(&[0x86], b"V_MPEGH/ISO/HEVC"),
+            element(&[0xe0], &[element(&[0xb0], &[0x0f, 0x00]), element(&[0xba], &[0x06, 0x40])].concat()),
+        ];
+        let audio = [
+            element(&[0x83], &[2]),
+            element(&[0x86], b"A_EAC3"),
+            element(&[0x22, 0xb5, 0x9c], b"ger"),
+            element(&[0xe1], &element(&[0x9f], &[6])),
+        ];
+        let subtitle = [element(&[0x83], &[17]), element(&[0x86], b"S_TEXT/UTF8"), element(&[0x55, 0xaa], &[1])];
+        let entries = [video.concat(), audio.concat(), subtitle.concat()].map(|entry| element(&[0xae], &entry));
+
+        element(&[0x16, 0x54, 0xae, 0x6b], &entries.concat())
+    }
+
+    #[test]
+    fn tracks_and_duration_are_read() {
+        let info = element(&[0x15, 0x49, 0xa9, 0x66], &element(&[0x44, 0x89], &5_400_000f64.to_be_bytes()));
+        let media_info = probe(&mut Cursor::new(file(&[info, tracks()].concat()))).unwrap();
+
+        assert_eq!(media_info.duration_ms, Some(5_400_000));
+        assert_eq!((media_info.width, media_info.height), (Some(3840), Some(1600)));
+        assert!(matches!(media_info.video_codec, Some(MediaEncodingFormat::H265)));
+        assert_eq!(media_info.audio_tracks.len(), 1);
+        assert_eq!(media_info.audio_tracks[0].codec, "eac3");
+        assert_eq!(media_info.audio_tracks[0].language.as_deref(), Some("de"));
+        assert_eq!(media_info.audio_tracks[0].channels, Some(6));
+        assert_eq!(media_info.subtitle_tracks.len(), 1);
+        assert_eq!(media_info.subtitle_tracks[0].codec, "srt");
+        assert_eq!(media_info.subtitle_tracks[0].language.as_deref(), Some("en"));
+        assert!(media_info.subtitle_tracks[0].forced);
+    }
+
+    #[test]
+    fn tracks_after_the_clusters_are_found_through_the_seek_head() {
+        let seek_head = |position: u64| {
+            let seek = [element(&[0x53, 0xab], &[0x16, 0x54, 0xae, 0x6b]), element(&[0x53, 0xac], &position.to_be_bytes())];
+            element(&[0x11, 0x4d, 0x9b, 0x74], &element(&[0x4d, 0xbb], &seek.concat()))
+        };
+        let cluster = element(&[0x1f, 0x43, 0xb6, 0x75], &[0; 16]);
+        let tracks_position = (seek_head(0).len() + cluster.len()) as u64;
+        let segment = [seek_head(tracks_position), cluster, tracks()].concat();
+
+        let media_info = probe(&mut Cursor::new(file(&segment))).unwrap();
+        assert_eq!(media_info.width, Some(3840));
+        assert_eq!(media_info.audio_tracks.len(), 1);
+        assert_eq!(media_info.duration_ms, None);
+    }
+
+    #[test]
+    fn corrupt_seek_entries_are_ignored() {
+        let seek = |id: &[u8], position: u64| {
+            element(&[0x4d, 0xbb], &[element(&[0x53, 0xab], id), element(&[0x53, 0xac], &position.to_be_bytes())].concat())
+        };
+        let seek_head = element(
+            &[0x11, 0x4d, 0x9b, 0x74],
+            &[seek(&[0x16, 0x54, 0xae, 0x6b], u64::MAX), seek(&[0x15, 0x49, 0xa9, 0x66], 1 << 40)].concat(),
+        );
+        let cluster = element(&[0x1f, 0x43, 0xb6, 0x75], &[0; 16]);
+        let segment = [seek_head, cluster, tracks()].concat();
+
+        let media_info = probe(&mut Cursor::new(file(&segment))).unwrap();
+        assert_eq!(media_info.width, None);
+        assert_eq!(media_info.duration_ms, None);
+    }
+
+    /// A file as small as muxers write it: one-byte sizes, a segment of unknown size that is
+    /// still being written, and a 32-bit duration.
+    #[test]
+    fn a_minimal_file_is_read() {
+        let ebml_header = [
+            &[0x1a, 0x45, 0xdf, 0xa3, 0x93][..],
+            &[0x42, 0x86, 0x81, 0x01],
+            &[0x42, 0x82, 0x88],
+            b"matroska",
+            &[0x42, 0x87, 0x81, 0x04],
+        ];
+        let segment_header = [0x18, 0x53, 0x80, 0x67, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        let info = [
+            &[0x15, 0x49, 0xa9, 0x66, 0x8e][..],
+            &[0x2a, 0xd7, 0xb1, 0x83, 0x0f, 0x42, 0x40],
+            &[0x44, 0x89, 0x84, 0x4a, 0xa4, 0xcb, 0x80],
+        ];
+        let tracks = [
+            &[0x16, 0x54, 0xae, 0x6b, 0xb8][..],
+            &[0xae, 0x9e, 0x83, 0x81, 0x01, 0x86, 0x8f],
+            b"V_MPEG4/ISO/AVC",
+            &[0xe0, 0x88, 0xb0, 0x82, 0x07, 0x80, 0xba, 0x82, 0x04, 0x38],
+            &[0xae, 0x96, 0x83, 0x81, 0x02, 0x86, 0x85],
+            b"A_AAC",
+            &[0x22, 0xb5, 0x9c, 0x83],
+            b"eng",
+            &[0xe1, 0x83, 0x9f, 0x81, 0x02],
+        ];
+        let cluster_header = [0x1f, 0x43, 0xb6, 0x75, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        let fixture = [ebml_header.concat(), segment_header.to_vec(), info.concat(), tracks.concat(), cluster_header.to_vec()].concat();
+
+        let media_info = probe(&mut Cursor::new(fixture)).unwrap();
+        assert_eq!(media_info.duration_ms, Some(5_400_000));
+        assert_eq!((media_info.width, media_info.height), (Some(1920), Some(1080)));
+        assert!(matches!(media_info.video_codec, Some(MediaEncodingFormat::H264)));
+        assert_eq!(media_info.audio_tracks.len(), 1);
+        assert_eq!(media_info.audio_tracks[0].codec, "aac");
+        assert_eq!(media_info.audio_tracks[0].language.as_deref(), Some("en"));
+        assert_eq!(media_info.audio_tracks[0].channels, Some(2));
+        assert!(media_info.subtitle_tracks.is_empty());
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        assert!(probe(&mut Cursor::new(b"RIFF\0\0\0\0AVI LIST".to_vec())).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+mod matroska;
+mod mp4;
+
+use crate::languages::find_language;
+use crate::models::{AudioTrack, MediaEncodingFormat, SubtitleTrack};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::Path;
+
+const MATROSKA_SIGNATURE: [u8; 4] = [0x1a, 0x45, 0xdf, 0xa3];
+
+/// Box types an MP4 or QuickTime file starts with.
+const MP4_FIRST_BOXES: [&[u8; 4]; 6] = [b"ftyp", b"moov", b"mdat", b"free", b"skip", b"wide"];
+
+/// Width and height of each resolution label, from the largest down.
+const RESOLUTIONS: [(u32, u32); 4] = [(3840, 2160), (2560, 1440), (1920, 1080), (1280, 720)];
+
+/// What the video container says about its streams, which is more reliable than its file name.
+#[derive(Debug, Default)]
+pub struct MediaInfo {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub video_codec: Option<MediaEncodingFormat>,
+    pub audio_tracks: Vec<AudioTrack>,
+    pub subtitle_tracks: Vec<SubtitleTrack>,
+    pub duration_ms: Option<u64>,
+}
+
+impl MediaInfo {
+    /// The label a release of this size goes by. Films wider than 16:9 are cropped to fewer
+    /// lines than their label and 4:3 ones to a narrower picture, so either side can tell.
+    pub fn resolution(&self) -> Option<u16> {
+        let (width, height) = (self.width?, self.height?);
+        let label = RESOLUTIONS
+            .iter()
+            .find(|(label_width, label_height)| {
+                u64::from(width) * 100 >= u64::from(*label_width) * 95
+                    || u64::from(height) * 100 >= u64::from(*label_height) * 95
+            })
+            .map_or(height, |(_, label_height)| *label_height);
+
+        u16::try_from(label).ok()
+    }
+}
+
+/// Reads the headers of a Matroska, WebM, MP4 or QuickTime file. Returns `None` for any other
+/// kind of file, like an AVI.
+pub fn probe(path: &Path) -> io::Result<Option<MediaInfo>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut signature = [0; 8];
+    if reader.read_exact(&mut signature).is_err() {
+        return Ok(None);
+    }
+    reader.rewind()?;
+
+    if signature[..4] == MATROSKA_SIGNATURE {
+        return matroska::probe(&mut reader).map(Some);
+    }
+    if MP4_FIRST_BOXES.iter().any(|box_type| signature[4..] == box_type[..]) {
+        return mp4::probe(&mut reader).map(Some);
+    }
+
+    Ok(None)
+}
+
+/// Turns an ISO 639-2 or BCP 47 language tag into the ISO 639-1 code subtitles are named with,
+/// keeping tags of languages that have none. Undetermined languages are `None`.
+fn normalize_language(tag: &str) -> Option<String> {
+    let primary_tag = tag.split(['-', '_']).next()?.trim().to_lowercase();
+    if primary_tag.is_empty() || matches!(primary_tag.as_str(), "und" | "mul" | "mis" | "zxx") {
+        return None;
+    }
+
+    match find_language(&primary_tag) {
+        Some(language) => Some(language.code.to_string()),
+        None => Some(primary_tag),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolution(width: u32, height: u32) -> Option<u16> {
+        MediaInfo { width: Some(width), height: Some(height), ..MediaInfo::default() }.resolution()
+    }
+
+    #[test]
+    fn cropped_pictures_keep_their_label() {
+        assert_eq!(resolution(3840, 1600), Some(2160));
+        assert_eq!(resolution(1920, 800), Some(1080));
+        assert_eq!(resolution(1440, 1080), Some(1080));
+        assert_eq!(resolution(1280, 544), Some(720));
+        assert_eq!(resolution(720, 480), Some(480));
+    }
+
+    #[test]
+    fn huge_sizes_do_not_overflow() {
+        assert_eq!(resolution(u32::MAX, u32::MAX), Some(2160));
+        assert_eq!(resolution(640, u32::MAX), Some(2160));
+    }
+
+    #[test]
+    fn language_tags_are_normalized() {
+        assert_eq!(normalize_language("ger").as_deref(), Some("de"));
+        assert_eq!(normalize_language("pt-BR").as_deref(), Some("pt"));
+        assert_eq!(normalize_language("und"), None);
+    }
+}
@@ -0,0 +1,355 @@
+use super::{normalize_language, MediaInfo};
+use crate::models::{AudioTrack, MediaEncodingFormat, SubtitleTrack};
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+/// The movie box holds a few megabytes of sample tables at most.
+const MAX_MOVIE_BOX_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Descriptor tags of the `esds` box that lead to the AAC channel configuration.
+const ES_DESCRIPTOR_TAG: u8 = 0x03;
+const DECODER_CONFIG_DESCRIPTOR_TAG: u8 = 0x04;
+const DECODER_SPECIFIC_INFO_TAG: u8 = 0x05;
+
+/// Finds the `moov` box, at the start of files made for streaming and after the media data
+/// otherwise, and reads its tracks.
+pub fn probe<R: Read + Seek>(reader: &mut R) -> io::Result<MediaInfo> {
+    let file_size = reader.seek(SeekFrom::End(0))?;
+    let mut position = reader.seek(SeekFrom::Start(0))?;
+
+    while position < file_size {
+        let (box_type, size, header_size) = read_box_header(reader, file_size - position)?;
+        if &box_type == b"moov" {
+            if size > MAX_MOVIE_BOX_SIZE {
+                return Err(invalid_data("the MP4 movie box is too large"));
+            }
+            let mut movie = vec![0; (size - header_size) as usize];
+            reader.read_exact(&mut movie)?;
+            return Ok(read_movie(&movie));
+        }
+
+        position += size;
+        reader.seek(SeekFrom::Start(position))?;
+    }
+
+    Err(invalid_data("the MP4 file has no movie box"))
+}
+
+fn read_movie(movie: &[u8]) -> MediaInfo {
+    let mut media_info = MediaInfo {
+        duration_ms: find_box(movie, &[b"mvhd"]).and_then(read_movie_duration),
+        ..MediaInfo::default()
+    };
+    for (box_type, track) in read_boxes(movie) {
+        if &box_type == b"trak" {
+            add_track(&mut media_info, track);
+        }
+    }
+
+    media_info
+}
+
+fn add_track(media_info: &mut MediaInfo, track: &[u8]) {
+    let Some(media) = find_box(track, &[b"mdia"]) else {
+        return;
+    };
+    let Some(handler_type) = find_box(media, &[b"hdlr"]).and_then(|handler| handler.get(8..12)) else {
+        return;
+    };
+    let language = find_box(media, &[b"mdhd"]).and_then(read_language);
+    let Some((format, sample_entry)) = find_box(media, &[b"minf", b"stbl", b"stsd"])
+        .and_then(|descriptions| read_boxes(descriptions.get(8..)?).into_iter().next())
+    else {
+        return;
+    };
+
+    match handler_type {
+        b"vide" if media_info.video_codec.is_none() && media_info.width.is_none() => {
+            // sample entry fields, then reserved and pre-defined ones before the picture size
+            media_info.width = read_u16(sample_entry, 24).map(u32::from);
+            media_info.height = read_u16(sample_entry, 26).map(u32::from);
+            media_info.video_codec = match &format {
+                b"avc1" | b"avc3" => Some(MediaEncodingFormat::H264),
+                b"hvc1" | b"hev1" => Some(MediaEncodingFormat::H265),
+                b"av01" => Some(MediaEncodingFormat::Av1),
+                b"vp09" => Some(MediaEncodingFormat::Vp9),
+                _ => None,
+            };
+        }
+        b"soun" => media_info.audio_tracks.push(AudioTrack {
+            codec: audio_codec_name(&format),
+            language,
+            channels: read_channels(&format, sample_entry),
+        }),
+        b"sbtl" | b"subt" | b"text" => media_info.subtitle_tracks.push(SubtitleTrack {
+            codec: subtitle_codec_name(&format),
+            language,
+            forced: false,
+        }),
+        _ => {}
+    }
+}
+
+fn audio_codec_name(format: &[u8; 4]) -> String {
+    let name = match format {
+        b"mp4a" => "aac",
+        b"ac-3" => "ac3",
+        b"ec-3" => "eac3",
+        b"dtsc" | b"dtsh" | b"dtsl" | b"dtse" => "dts",
+        b"mlpa" => "truehd",
+        b"fLaC" => "flac",
+        b"Opus" => "opus",
+        b".mp3" => "mp3",
+        b"lpcm" | b"sowt" | b"twos" => "pcm",
+        _ => return String::from_utf8_lossy(format).trim().to_lowercase(),
+    };
+
+    name.to_string()
+}
+
+fn subtitle_codec_name(format: &[u8; 4]) -> String {
+    let name = match format {
+        b"tx3g" | b"text" => "mov_text",
+        b"wvtt" => "vtt",
+        b"stpp" => "ttml",
+        _ => return String::from_utf8_lossy(format).trim().to_lowercase(),
+    };
+
+    name.to_string()
+}
+
+/// Audio sample entries have a channel count, which AAC leaves at two and tells the real
+/// layout of in its decoder configuration. QuickTime version 2 entries move the count.
+fn read_channels(format: &[u8; 4], sample_entry: &[u8]) -> Option<u16> {
+    let version = read_u16(sample_entry, 8)?;
+    let (channels, children_offset) = match version {
+        1 => (read_u16(sample_entry, 16)?, 44),
+        2 => (u16::try_from(read_u32(sample_entry, 40)?).ok()?, 64),
+        _ => (read_u16(sample_entry, 16)?, 28),
+    };
+    if format != b"mp4a" {
+        return Some(channels);
+    }
+
+    let aac_channels = sample_entry
+        .get(children_offset..)
+        .and_then(|children| find_box(children, &[b"esds"]))
+        .and_then(|esds| read_aac_channels(esds.get(4..)?));
+    Some(aac_channels.unwrap_or(channels))
+}
+
+/// Walks the elementary stream descriptor down to the AAC audio specific config.
+fn read_aac_channels(descriptors: &[u8]) -> Option<u16> {
+    let (tag, es_descriptor) = read_descriptor(descriptors)?;
+    if tag != ES_DESCRIPTOR_TAG {
+        return None;
+    }
+    let flags = *es_descriptor.get(2)?;
+    let mut offset = 3;
+    if flags & 0x80 != 0 {
+        offset += 2;
+    }
+    if flags & 0x40 != 0 {
+        offset += 1 + *es_descriptor.get(offset)? as usize;
+    }
+    if flags & 0x20 != 0 {
+        offset += 2;
+    }
+
+    let (tag, decoder_config) = read_descriptor(es_descriptor.get(offset..)?)?;
+    if tag != DECODER_CONFIG_DESCRIPTOR_TAG {
+        return None;
+    }
+    let (tag, audio_config) = read_descriptor(decoder_config.get(13..)?)?;
+    if tag != DECODER_SPECIFIC_INFO_TAG {
+        return None;
+    }
+
+    // the object type in five bits, or eleven when escaped, then the sample rate index in four
+    // bits, or 28 when escaped, then the channel configuration in four bits
+    let bits = audio_config
+        .iter()
+        .chain([0; 8].iter())
+        .take(8)
+        .fold(0u64, |bits, byte| (bits << 8) | *byte as u64);
+    let read_bits = |offset: u32, count: u32| (bits >> (64 - offset - count)) & ((1 << count) - 1);
+    let object_type_length = if read_bits(0, 5) == 31 { 11 } else { 5 };
+    let sample_rate_length = if read_bits(object_type_length, 4) == 0xf { 28 } else { 4 };
+
+    match read_bits(object_type_length + sample_rate_length, 4) {
+        channel_configuration @ 1..=6 => Some(channel_configuration as u16),
+        7 => Some(8),
+        _ => None,
+    }
+}
+
+/// Descriptors have a tag and a size of seven bits per byte, for as long as the high bit is set.
+fn read_descriptor(data: &[u8]) -> Option<(u8, &[u8])> {
+    let tag = *data.first()?;
+    let mut size = 0usize;
+    let mut offset = 1;
+    loop {
+        let byte = *data.get(offset)?;
+        size = (size << 7) | (byte & 0x7f) as usize;
+        offset += 1;
+        if byte & 0x80 == 0 || offset > 4 {
+            break;
+        }
+    }
+
+    Some((tag, data.get(offset..offset + size).unwrap_or(&data[offset.min(data.len())..])))
+}
+
+fn read_movie_duration(movie_header: &[u8]) -> Option<u64> {
+    let (timescale, duration) = match movie_header.first()? {
+        1 => (read_u32(movie_header, 20)?, read_u64(movie_header, 24)?),
+        _ => (read_u32(movie_header, 12)?, read_u32(movie_header, 16)? as u64),
+    };
+    if timescale == 0 {
+        return None;
+    }
+
+    Some(duration * 1000 / timescale as u64)
+}
+
+/// The language is packed into three letters of five bits each, offset from 0x60.
+fn read_language(media_header: &[u8]) -> Option<String> {
+    let offset = match media_header.first()? {
+        1 => 32,
+        _ => 20,
+    };
+    let packed = read_u16(media_header, offset)?;
+    let language: String = [10, 5, 0]
+        .iter()
+        .map(|shift| (((packed >> shift) & 0x1f) as u8 + 0x60) as char)
+        .collect();
+
+    normalize_language(&language)
+}
+
+/// Follows a path of box types down from the boxes in `data`.
+fn find_box<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    let (first, rest) = path.split_first()?;
+    let (_, body) = read_boxes(data).into_iter().find(|(box_type, _)| box_type == *first)?;
+
+    match rest.is_empty() {
+        true => Some(body),
+        false => find_box(body, rest),
+    }
+}
+
+fn read_boxes(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut cursor = Cursor::new(data);
+    let mut boxes = Vec::new();
+    loop {
+        let box_start = cursor.position() as usize;
+        let Ok((box_type, size, _)) = read_box_header(&mut cursor, (data.len() - box_start) as u64) else {
+            break;
+        };
+        let start = cursor.position() as usize;
+        let end = usize::try_from(size).map_or(data.len(), |size| box_start.saturating_add(size).min(data.len()));
+        boxes.push((box_type, &data[start..end]));
+        cursor.set_position(end as u64);
+    }
+
+    boxes
+}
+
+/// Reads a box's type, its size including the header, and the size of the header. A size of
+/// zero means the box runs to the end of the file, one that a 64-bit size follows the type.
+fn read_box_header<R: Read>(reader: &mut R, remaining: u64) -> io::Result<([u8; 4], u64, u64)> {
+    let mut header = [0; 8];
+    reader.read_exact(&mut header)?;
+    let box_type: [u8; 4] = header[4..].try_into().unwrap();
+
+    let (size, header_size) = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+        0 => (remaining, 8),
+        1 => {
+            let mut large_size = [0; 8];
+            reader.read_exact(&mut large_size)?;
+            (u64::from_be_bytes(large_size), 16)
+        }
+        size => (size as u64, 8),
+    };
+    if size < header_size {
+        return Err(invalid_data("invalid MP4 box size"));
+    }
+
+    Ok((box_type, size, header_size))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mp4_box(box_type: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        [&(body.len() as u32 + 8).to_be_bytes()[..], box_type, body].concat()
+    }
+
+    fn track(handler_type: &[u8; 4], language: &str, format: &[u8; 4], sample_entry: &[u8]) -> Vec<u8> {
+        let packed = language.bytes().fold(0u16, |packed, letter| (packed << 5) | (letter - 0x60) as u16);
+        let media_header = [&[0; 20][..], &packed.to_be_bytes(), &[0; 2]].concat();
+        let handler = [&[0; 8][..], handler_type, &[0; 12]].concat();
+        let descriptions = [&[0, 0, 0, 0, 0, 0, 0, 1][..], &mp4_box(format, sample_entry)].concat();
+        let media_information = mp4_box(b"stbl", &mp4_box(b"stsd", &descriptions));
+        let media = [mp4_box(b"mdhd", &media_header), mp4_box(b"hdlr", &handler), mp4_box(b"minf", &media_information)];
+
+        mp4_box(b"trak", &mp4_box(b"mdia", &media.concat()))
+    }
+
+    fn movie() -> Vec<u8> {
+        let movie_header = [&[0; 12][..], &1000u32.to_be_bytes(), &7_200_000u32.to_be_bytes(), &[0; 80]].concat();
+        let video_entry = [&[0; 24][..], &1920u16.to_be_bytes(), &800u16.to_be_bytes(), &[0; 50]].concat();
+        // AAC LC at 48 kHz with six channels, behind an entry that says two
+        let audio_specific_config = [0x05, 0x02, 0x11, 0xb0];
+        let decoder_config = [&[0x04, 17][..], &[0x40, 0x15], &[0; 11], &audio_specific_config].concat();
+        let es_descriptor = [&[0x03, 3 + decoder_config.len() as u8, 0, 1, 0][..], &decoder_config].concat();
+        let esds = mp4_box(b"esds", &[&[0; 4][..], &es_descriptor].concat());
+        let audio_entry = [&[0; 16][..], &2u16.to_be_bytes(), &[0; 10], &esds].concat();
+        let tracks = [
+            track(b"vide", "und", b"avc1", &video_entry),
+            track(b"soun", "fre", b"mp4a", &audio_entry),
+            track(b"sbtl", "eng", b"tx3g", &[0; 8]),
+        ];
+
+        mp4_box(b"moov", &[mp4_box(b"mvhd", &movie_header), tracks.concat()].concat())
+    }
+
+    #[test]
+    fn the_movie_box_is_found_after_the_media_data() {
+        let file = [mp4_box(b"ftyp", b"isom\0\0\0\0"), mp4_box(b"mdat", &[0; 64]), movie()].concat();
+        let media_info = probe(&mut Cursor::new(file)).unwrap();
+
+        assert_eq!(media_info.duration_ms, Some(7_200_000));
+        assert_eq!((media_info.width, media_info.height), (Some(1920), Some(800)));
+        assert!(matches!(media_info.video_codec, Some(MediaEncodingFormat::H264)));
+        assert_eq!(media_info.audio_tracks.len(), 1);
+        assert_eq!(media_info.audio_tracks[0].codec, "aac");
+        assert_eq!(media_info.audio_tracks[0].language.as_deref(), Some("fr"));
+        assert_eq!(media_info.audio_tracks[0].channels, Some(6));
+        assert_eq!(media_info.subtitle_tracks.len(), 1);
+        assert_eq!(media_info.subtitle_tracks[0].codec, "mov_text");
+        assert_eq!(media_info.subtitle_tracks[0].language.as_deref(), Some("en"));
+    }
+
+    #[test]
+    fn files_without_a_movie_box_are_rejected() {
+        let file = [mp4_box(b"ftyp", b"isom\0\0\0\0"), mp4_box(b"mdat", &[0; 64])].concat();
+        assert!(probe(&mut Cursor::new(file)).is_err());
+    }
+}
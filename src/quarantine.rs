@@ -0,0 +1,269 @@
+use crate::file_operations::{move_path, FileOperations};
+use crate::utils::url_encode;
+use chrono::Local;
+use inline_colorization::{color_green, color_red, color_reset, color_yellow};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What happens to files and directories the organizers remove from a library.
+#[derive(Debug, Clone)]
+pub enum DeletionPolicy {
+    /// Move them into this directory, keeping their path relative to the library
+    Quarantine(PathBuf),
+    /// Move them to the freedesktop.org trash
+    Trash,
+    /// Remove them permanently
+    Delete,
+}
+
+/// Quarantined items live under `<quarantine>/<run id>/<path relative to the library>`. Items
+/// from outside the library keep their whole path under `<quarantine>/<run id>/external`, so
+/// ones with the same name from different directories don't collide.
+pub fn quarantine_path_for(
+    quarantine_path: &Path,
+    library_root: &Path,
+    run_id: &str,
+    path: &Path,
+) -> PathBuf {
+    let relative_path = path.strip_prefix(library_root).map(Path::to_path_buf).unwrap_or_else(|_| {
+        let components = path.components().filter(|component| matches!(component, Component::Normal(_)));
+        Path::new(EXTERNAL_DIR_NAME).join(components.collect::<PathBuf>())
+    });
+
+    quarantine_path.join(run_id).join(relative_path)
+}
+
+pub fn move_to_quarantine(path: &Path, quarantine_path: &Path) -> io::Result<()> {
+    if let Some(parent) = quarantine_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    move_path(path, quarantine_path)
+}
+
+/// Moves an item to the trash as described by the freedesktop.org trash specification and
+/// returns where it ended up. Items on another device go to that device's `.Trash-$uid`.
+pub fn move_to_trash(path: &Path) -> io::Result<PathBuf> {
+    let path = fs::canonicalize(path)?;
+    let home_trash_path = get_home_trash_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Could not locate the trash directory"))?;
+
+    match trash_into(&path, &home_trash_path) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let top_dir_path = get_mount_point(&path)?;
+            trash_into(&path, &top_dir_path.join(format!(".Trash-{}", get_uid()?)))
+        }
+        result => result,
+    }
+}
+
+/// Puts a deleted item back, removing its trash info file if it was in the trash.
+pub fn restore(holding_path: &Path, path: &Path, ops: &FileOperations) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        ops.create_dir_all(parent)?;
+    }
+    ops.rename(holding_path, path)?;
+
+    let trash_info_path = holding_path
+        .parent()
+        .filter(|parent| parent.file_name().is_some_and(|name| name == "files"))
+        .and_then(|files_path| files_path.parent())
+        .map(|trash_path| {
+            let file_name = holding_path.file_name().unwrap_or_default().to_string_lossy();
+            trash_path.join("info").join(format!("{}.trashinfo", file_name))
+        });
+    if let Some(trash_info_path) = trash_info_path.filter(|info_path| info_path.is_file()) {
+        ops.remove_file(&trash_info_path)?;
+    }
+
+    Ok(())
+}
+
+/// Permanently removes quarantined runs older than the given number of days. An age too large
+/// for the clock to go back by purges nothing, as no run is that old.
+pub fn purge(quarantine_path: &Path, older_than_days: u64, ops: &FileOperations) -> io::Result<()> {
+    if !quarantine_path.is_dir() {
+        println!("{color_yellow}Nothing to purge, {:?} does not exist{color_reset}", quarantine_path);
+        return Ok(());
+    }
+
+    let cutoff = older_than_days
+        .checked_mul(24 * 60 * 60)
+        .and_then(|seconds| SystemTime::now().checked_sub(Duration::from_secs(seconds)));
+    let Some(cutoff) = cutoff else {
+        println!("{color_yellow}Nothing to purge, no run is older than {} day(s){color_reset}", older_than_days);
+        return Ok(());
+    };
+    let mut purged_count = 0;
+
+    for entry in fs::read_dir(quarantine_path)? {
+        let path = entry?.path();
+        let Some(quarantined_at) = get_run_time(&path) else {
+            continue;
+        };
+
+        if path.is_dir() && quarantined_at < cutoff {
+            match ops.remove_dir_all(&path) {
+                Ok(()) => purged_count += 1,
+                Err(e) => eprintln!("{color_red}Failed to purge {:?}: {}{color_reset}", path, e),
+            }
+        }
+    }
+
+    println!(
+        "{color_green}Purged {} quarantined run(s) older than {} day(s){color_reset}",
+        purged_count, older_than_days
+    );
+    Ok(())
+}
+
+/// Where items from outside the library go in a quarantined run.
+const EXTERNAL_DIR_NAME: &str = "external";
+
+/// Run directories are named after the millisecond timestamp the run started at.
+fn get_run_time(run_path: &Path) -> Option<SystemTime> {
+    let run_id = run_path.file_name()?.to_str()?;
+    let millis = run_id.parse::<u64>().ok()?;
+    UNIX_EPOCH.checked_add(Duration::from_millis(millis))
+}
+
+fn get_home_trash_path() -> Option<PathBuf> {
+    let data_home_path = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))?;
+
+    Some(data_home_path.join("Trash"))
+}
+
+fn trash_into(path: &Path, trash_path: &Path) -> io::Result<PathBuf> {
+    let files_path = trash_path.join("files");
+    let info_path = trash_path.join("info");
+    fs::create_dir_all(&files_path)?;
+    fs::create_dir_all(&info_path)?;
+
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let mut trash_name = file_name.clone();
+    let mut counter = 1;
+    while files_path.join(&trash_name).exists() || info_path.join(format!("{}.trashinfo", trash_name)).exists() {
+        counter += 1;
+        trash_name = format!("{}.{}", file_name, counter);
+    }
+
+    let trash_info_path = info_path.join(format!("{}.trashinfo", trash_name));
+    let trash_info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_trash_path(path),
+        Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+    fs::write(&trash_info_path, trash_info)?;
+
+    let trashed_path = files_path.join(&trash_name);
+    if let Err(e) = fs::rename(path, &trashed_path) {
+        let _ = fs::remove_file(&trash_info_path);
+        return Err(e);
+    }
+
+    Ok(trashed_path)
+}
+
+fn encode_trash_path(path: &Path) -> String {
+    path.to_string_lossy()
+        .split('/')
+        .map(url_encode)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(unix)]
+fn get_mount_point(path: &Path) -> io::Result<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    let device = fs::metadata(path)?.dev();
+    let mut mount_point = path.to_path_buf();
+    for ancestor in path.ancestors().skip(1) {
+        if fs::metadata(ancestor)?.dev() != device {
+            break;
+        }
+        mount_point = ancestor.to_path_buf();
+    }
+
+    Ok(mount_point)
+}
+
+#[cfg(not(unix))]
+fn get_mount_point(_path: &Path) -> io::Result<PathBuf> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "The trash is only supported on Unix"))
+}
+
+#[cfg(unix)]
+fn get_uid() -> io::Result<u32> {
+    use std::os::unix::fs::MetadataExt;
+
+    // the process' own /proc entry is owned by the user running it
+    Ok(fs::metadata("/proc/self")?.uid())
+}
+
+#[cfg(not(unix))]
+fn get_uid() -> io::Result<u32> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "The trash is only supported on Unix"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn items_keep_their_path_in_quarantine() {
+        let quarantine_path = Path::new("/q");
+        let library_root = Path::new("/library");
+        let quarantined = |path: &str| quarantine_path_for(quarantine_path, library_root, "1", Path::new(path));
+
+        assert_eq!(quarantined("/library/Movie (2000)/Movie.nfo"), Path::new("/q/1/Movie (2000)/Movie.nfo"));
+        assert_eq!(quarantined("/downloads/a/Movie.nfo"), Path::new("/q/1/external/downloads/a/Movie.nfo"));
+        assert_ne!(quarantined("/downloads/a/Movie.nfo"), quarantined("/downloads/b/Movie.nfo"));
+    }
+
+    #[test]
+    fn items_with_the_same_name_get_numbered_in_the_trash() {
+        let test_path = std::env::temp_dir().join(format!("media-organizer-trash-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&test_path);
+        let trash_path = test_path.join("Trash");
+        for dir_name in ["a", "b", "c"] {
+            fs::create_dir_all(test_path.join(dir_name)).unwrap();
+            fs::write(test_path.join(dir_name).join("Movie.nfo"), dir_name).unwrap();
+        }
+
+        let trashed_paths: Vec<PathBuf> = ["a", "b", "c"]
+            .iter()
+            .map(|dir_name| trash_into(&test_path.join(dir_name).join("Movie.nfo"), &trash_path).unwrap())
+            .collect();
+        let trash_names: Vec<&str> = trashed_paths.iter().map(|path| path.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(trash_names, ["Movie.nfo", "Movie.nfo.2", "Movie.nfo.3"]);
+        assert_eq!(fs::read_to_string(&trashed_paths[1]).unwrap(), "b");
+        let trash_info = fs::read_to_string(trash_path.join("info").join("Movie.nfo.2.trashinfo")).unwrap();
+        assert!(trash_info.contains(&format!("Path={}\n", encode_trash_path(&test_path.join("b").join("Movie.nfo")))));
+        let _ = fs::remove_dir_all(&test_path);
+    }
+
+    #[test]
+    fn purge_removes_only_old_runs_and_survives_huge_ages() {
+        let quarantine_path = std::env::temp_dir().join(format!("media-organizer-purge-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&quarantine_path);
+        let recent_run_id = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis().to_string();
+        for run_id in ["1000", recent_run_id.as_str()] {
+            fs::create_dir_all(quarantine_path.join(run_id).join("Movie (2000)")).unwrap();
+        }
+        let ops = FileOperations::new(false, false);
+
+        purge(&quarantine_path, u64::MAX, &ops).unwrap();
+        purge(&quarantine_path, 1_000_000_000, &ops).unwrap();
+        assert!(quarantine_path.join("1000").is_dir());
+
+        purge(&quarantine_path, 30, &ops).unwrap();
+        assert!(!quarantine_path.join("1000").exists());
+        assert!(quarantine_path.join(&recent_run_id).is_dir());
+        let _ = fs::remove_dir_all(&quarantine_path);
+    }
+}
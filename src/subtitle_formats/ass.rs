@@ -0,0 +1,143 @@
+use super::{parse_timestamp, strip_unsupported_markup, Cue};
+use regex::Regex;
+
+/// Field order of `Dialogue` lines when a file has no `Format` line of its own.
+const DEFAULT_EVENT_FORMAT: [&str; 10] = [
+    "layer", "start", "end", "style", "name", "marginl", "marginr", "marginv", "effect", "text",
+];
+
+const HEADER: &str = "[Script Info]
+ScriptType: v4.00+
+PlayResX: 384
+PlayResY: 288
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,20,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+";
+
+/// Reads the `Dialogue` lines of the `[Events]` section, keeping italics, bold and underline
+/// and dropping every other styling override.
+pub fn parse(text: &str) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    let mut in_events = false;
+    let mut format: Vec<String> = DEFAULT_EVENT_FORMAT.iter().map(|field| field.to_string()).collect();
+
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_events = line.eq_ignore_ascii_case("[events]");
+        } else if !in_events {
+            continue;
+        } else if let Some(fields) = line.strip_prefix("Format:") {
+            format = fields.split(',').map(|field| field.trim().to_lowercase()).collect();
+        } else if let Some(values) = line.strip_prefix("Dialogue:") {
+            cues.extend(parse_dialogue(values, &format));
+        }
+    }
+
+    cues
+}
+
+pub fn write(cues: &[Cue]) -> String {
+    let mut content = String::from(HEADER);
+    for cue in cues {
+        content.push_str(&format!(
+            "Dialogue: 0,{},{},Default,,0,0,0,,{}\n",
+            format_timestamp(cue.start),
+            format_timestamp(cue.end),
+            to_ass_text(&cue.text)
+        ));
+    }
+
+    content
+}
+
+fn parse_dialogue(values: &str, format: &[String]) -> Option<Cue> {
+    // the text is the last field and may have commas of its own
+    let values: Vec<&str> = values.splitn(format.len(), ',').collect();
+    let field = |name: &str| {
+        let index = format.iter().position(|field| field == name)?;
+        values.get(index).copied()
+    };
+
+    let text = field("text")?;
+    // drawings are shapes, not text
+    if text.contains("\\p1") {
+        return None;
+    }
+
+    Some(Cue {
+        start: parse_timestamp(field("start")?)?,
+        end: parse_timestamp(field("end")?)?,
+        text: from_ass_text(text),
+    })
+}
+
+fn from_ass_text(text: &str) -> String {
+    let override_re = Regex::new(r"\{([^}]*)\}").unwrap();
+    let style_re = Regex::new(r"\\([ibu])([01])").unwrap();
+
+    let text = text.replace("\\N", "\n").replace("\\n", "\n").replace("\\h", " ");
+    override_re
+        .replace_all(&text, |captures: &regex::Captures| {
+            style_re
+                .captures_iter(&captures[1])
+                .map(|style| match &style[2] {
+                    "1" => format!("<{}>", &style[1]),
+                    _ => format!("</{}>", &style[1]),
+                })
+                .collect::<String>()
+        })
+        .trim()
+        .to_string()
+}
+
+fn to_ass_text(text: &str) -> String {
+    let tag_re = Regex::new(r"<(/?)([ibu])>").unwrap();
+    let text = strip_unsupported_markup(text);
+    tag_re
+        .replace_all(&text, |captures: &regex::Captures| {
+            let state = if captures[1].is_empty() { "1" } else { "0" };
+            format!("{{\\{}{}}}", &captures[2], state)
+        })
+        .replace('\n', "\\N")
+}
+
+/// ASS times have one digit for hours and centiseconds.
+fn format_timestamp(milliseconds: u64) -> String {
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        milliseconds / 3_600_000,
+        milliseconds / 60_000 % 60,
+        milliseconds / 1000 % 60,
+        milliseconds % 1000 / 10
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dialogue_is_read_with_its_own_format() {
+        let text = "[Script Info]\nTitle: x\n\n[Events]\nFormat: Layer, Start, End, Style, Text\nDialogue: 0,0:00:01.50,0:00:03.00,Default,{\\an8\\i1}Hi,{\\i0} there\\Nfriend\nDialogue: 0,0:00:04.00,0:00:05.00,Default,{\\p1}m 0 0 l 10 10\n";
+        let cues = parse(text);
+        assert_eq!(cues.len(), 1);
+        assert_eq!((cues[0].start, cues[0].end), (1500, 3000));
+        assert_eq!(cues[0].text, "<i>Hi,</i> there\nfriend");
+    }
+
+    #[test]
+    fn written_cues_read_back_the_same() {
+        let cues = vec![
+            Cue { start: 1500, end: 3000, text: "<b>Hi</b>\nthere".to_string() },
+            Cue { start: 3_723_040, end: 3_724_000, text: "Again".to_string() },
+        ];
+        let content = write(&cues);
+        assert!(content.contains("Dialogue: 0,1:02:03.04,1:02:04.00,Default,,0,0,0,,Again"));
+        assert_eq!(parse(&content), cues);
+    }
+}
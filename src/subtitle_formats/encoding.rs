@@ -0,0 +1,86 @@
+use crate::languages::detect_language_from_text;
+use encoding_rs::{Encoding, WINDOWS_1250, WINDOWS_1251, WINDOWS_1252};
+
+/// Languages whose legacy subtitles are written in Windows-1250.
+const CENTRAL_EUROPEAN_LANGUAGES: [&str; 7] = ["pl", "cs", "sk", "hu", "ro", "hr", "sl"];
+
+/// Decodes a subtitle into text without its byte order mark. Files that are not UTF-8 are
+/// taken to be Windows-1252, which covers ISO-8859-1, Windows-1250 for Central European
+/// languages or Windows-1251 for Cyrillic.
+pub fn decode(bytes: &[u8]) -> String {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        return encoding.decode_without_bom_handling(&bytes[bom_length..]).0.into_owned();
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        // only cut off in the middle of a character, as happens to a sample of a file
+        Err(e) if e.error_len().is_none() => String::from_utf8_lossy(bytes).into_owned(),
+        Err(_) => guess_legacy_encoding(bytes).decode_without_bom_handling(bytes).0.into_owned(),
+    }
+}
+
+/// Image based subtitles like PGS `.sup` and VobSub `.sub` files are full of zero bytes,
+/// which text only has when it is UTF-16 with a byte order mark.
+pub fn is_text(bytes: &[u8]) -> bool {
+    Encoding::for_bom(bytes).is_some() || !bytes.contains(&0)
+}
+
+/// European text in Latin script is mostly ASCII with the odd accented letter, while Cyrillic
+/// text is mostly letters outside of ASCII. Which accented letters those are depends on the
+/// language, which its common words tell apart as they are mostly ASCII.
+fn guess_legacy_encoding(bytes: &[u8]) -> &'static Encoding {
+    let ascii_letter_count = bytes.iter().filter(|byte| byte.is_ascii_alphabetic()).count();
+    let high_byte_count = bytes.iter().filter(|byte| **byte >= 0x80).count();
+    if high_byte_count > ascii_letter_count {
+        return WINDOWS_1251;
+    }
+
+    let text = WINDOWS_1250.decode_without_bom_handling(bytes).0;
+    match detect_language_from_text(&text) {
+        Some(language) if CENTRAL_EUROPEAN_LANGUAGES.contains(&language.code) => WINDOWS_1250,
+        _ => WINDOWS_1252,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(text: &str, encoding: &'static Encoding) -> Vec<u8> {
+        encoding.encode(text).0.into_owned()
+    }
+
+    #[test]
+    fn utf8_and_byte_order_marks_are_honored() {
+        assert_eq!(decode("Żółw".as_bytes()), "Żółw");
+        assert_eq!(decode(&[0xef, 0xbb, 0xbf, b'h', b'i']), "hi");
+        assert_eq!(decode(&[0xff, 0xfe, b'h', 0, b'i', 0]), "hi");
+    }
+
+    #[test]
+    fn western_text_is_read_as_windows_1252() {
+        let text = "Je ne sais pas où est le café, mais il est très tard et ce n'est pas le moment.";
+        assert_eq!(decode(&encode(text, WINDOWS_1252)), text);
+    }
+
+    #[test]
+    fn central_european_text_is_read_as_windows_1250() {
+        let polish = "Nie wiem, czy to jest już koniec. Ale tak, to się stało i nie ma na co czekać. Co ty na to? Jak mnie znalazłeś?";
+        assert_eq!(decode(&encode(polish, WINDOWS_1250)), polish);
+        let czech = "Já nevím, jestli je to pravda. Ale tak to je a není co řešit. Jsem tady, už jsi mě viděl? Ne, to se nestalo.";
+        assert_eq!(decode(&encode(czech, WINDOWS_1250)), czech);
+    }
+
+    #[test]
+    fn cyrillic_text_is_read_as_windows_1251() {
+        let text = "Я не знаю, что это такое";
+        assert_eq!(decode(&encode(text, WINDOWS_1251)), text);
+    }
+
+    #[test]
+    fn image_subtitles_are_not_text() {
+        assert!(!is_text(&[0x50, 0x47, 0x00, 0x00]));
+        assert!(is_text(b"1\n00:00:01,000 --> 00:00:02,000\nHi\n"));
+    }
+}
@@ -0,0 +1,208 @@
+mod ass;
+mod encoding;
+mod srt;
+mod sync;
+mod vtt;
+
+use crate::config::SubtitleConfig;
+use regex::Regex;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+pub use encoding::{decode, is_text};
+pub use sync::{align, retime};
+
+/// Lines only subtitle sites put in, matched case-insensitively.
+const AD_PATTERNS: [&str; 12] = [
+    r"opensubtitles",
+    r"subscene",
+    r"addic7ed",
+    r"podnapisi",
+    r"\byify\b",
+    r"\byts\.(mx|am|lt|ag)\b",
+    r"become (a )?vip member",
+    r"advertise your product",
+    r"please rate this subtitle",
+    r"subtitles? (downloaded|provided|ripped) (from|by)",
+    r"sync(ed)? (and|&) correct(ed|ions) by",
+    r"\bwww\.[a-z0-9-]+\.(com|org|net)\b",
+];
+
+/// Text subtitle formats that can be read and written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleFormat {
+    Srt,
+    Vtt,
+    /// Also covers SSA, which only differs in its styles section
+    Ass,
+}
+
+impl SubtitleFormat {
+    pub fn from_path(path: &Path) -> Option<SubtitleFormat> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "srt" => Some(SubtitleFormat::Srt),
+            "vtt" => Some(SubtitleFormat::Vtt),
+            "ass" | "ssa" => Some(SubtitleFormat::Ass),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Vtt => "vtt",
+            SubtitleFormat::Ass => "ass",
+        }
+    }
+}
+
+/// One piece of text on screen, with `<i>`, `<b>` and `<u>` as the only markup.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    /// Milliseconds from the start of the video
+    pub start: u64,
+    pub end: u64,
+    pub text: String,
+}
+
+/// A subtitle rewritten cleaned up and in the configured format.
+pub struct NormalizedSubtitle {
+    pub content: String,
+    pub format: SubtitleFormat,
+    pub removed_cue_count: usize,
+}
+
+/// Reads the cues of a text subtitle on disk, whatever its encoding.
+pub fn read_cues(path: &Path) -> Result<Vec<Cue>, Box<dyn Error>> {
+    let format = SubtitleFormat::from_path(path).ok_or("only SRT, WebVTT and ASS subtitles have readable timing")?;
+    let cues = parse(&decode(&fs::read(path)?), format);
+    if cues.is_empty() {
+        return Err("the subtitle has no cues".into());
+    }
+
+    Ok(cues)
+}
+
+pub fn parse(text: &str, format: SubtitleFormat) -> Vec<Cue> {
+    match format {
+        SubtitleFormat::Srt => srt::parse(text),
+        SubtitleFormat::Vtt => vtt::parse(text),
+        SubtitleFormat::Ass => ass::parse(text),
+    }
+}
+
+pub fn write(cues: &[Cue], format: SubtitleFormat) -> String {
+    match format {
+        SubtitleFormat::Srt => srt::write(cues),
+        SubtitleFormat::Vtt => vtt::write(cues),
+        SubtitleFormat::Ass => ass::write(cues),
+    }
+}
+
+/// Drops ads and empty cues and puts the rest in order, so writing them renumbers them.
+pub fn clean_cues(cues: Vec<Cue>) -> Vec<Cue> {
+    let ad_re = Regex::new(&format!("(?i){}", AD_PATTERNS.join("|"))).unwrap();
+    let mut cues: Vec<Cue> = cues
+        .into_iter()
+        .filter(|cue| !cue.text.trim().is_empty() && !ad_re.is_match(&cue.text))
+        .collect();
+
+    cues.sort_by_key(|cue| (cue.start, cue.end));
+    cues
+}
+
+/// Rewrites a text subtitle as UTF-8 in the configured format, cleaned up if configured to.
+/// Returns `None` when the subtitle is fine as it is or can't be read.
+pub fn normalize_subtitle(path: &Path, config: &SubtitleConfig) -> Option<NormalizedSubtitle> {
+    let format = SubtitleFormat::from_path(path)?;
+    let target_format = config.convert_to.unwrap_or(format);
+    // rewriting ASS loses its styles, which is only worth it to convert
+    if target_format == format && (!config.clean || format == SubtitleFormat::Ass) {
+        return None;
+    }
+
+    normalize_content(&fs::read(path).ok()?, format, config)
+}
+
+/// Like [`normalize_subtitle`], for a subtitle that is not on disk yet.
+pub fn normalize_content(bytes: &[u8], format: SubtitleFormat, config: &SubtitleConfig) -> Option<NormalizedSubtitle> {
+    let target_format = config.convert_to.unwrap_or(format);
+    let text = decode(bytes);
+    let cues = parse(&text, format);
+    if cues.is_empty() {
+        return None;
+    }
+
+    let cue_count = cues.len();
+    let cues = match config.clean {
+        true => clean_cues(cues),
+        false => cues,
+    };
+    let content = write(&cues, target_format);
+
+    // a UTF-8 file that only differs in line endings is left alone
+    let is_plain_utf8 = bytes == text.as_bytes();
+    if target_format == format && is_plain_utf8 && text.replace("\r\n", "\n") == content {
+        return None;
+    }
+
+    Some(NormalizedSubtitle {
+        content,
+        format: target_format,
+        removed_cue_count: cue_count - cues.len(),
+    })
+}
+
+/// Reads `01:02:03,456`, `01:02:03.456`, `02:03.456` and ASS's `1:02:03.45`.
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let (clock, fraction) = timestamp.trim().split_once(['.', ','])?;
+    let parts: Vec<u64> = clock.split(':').map(|part| part.parse().ok()).collect::<Option<_>>()?;
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [hours, minutes, seconds] => (*hours, *minutes, *seconds),
+        [minutes, seconds] => (0, *minutes, *seconds),
+        _ => return None,
+    };
+
+    // fractions are written in as many digits as the format likes
+    let fraction: String = fraction.chars().chain("000".chars()).take(3).collect();
+    let milliseconds: u64 = fraction.parse().ok()?;
+
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + milliseconds)
+}
+
+/// Reads a `start --> end` line, ignoring WebVTT cue settings after it.
+fn parse_timing(line: &str) -> Option<(u64, u64)> {
+    let (start, end) = line.split_once("-->")?;
+    let end = end.split_whitespace().next()?;
+    Some((parse_timestamp(start)?, parse_timestamp(end)?))
+}
+
+fn format_timestamp(milliseconds: u64, separator: char) -> String {
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        milliseconds / 3_600_000,
+        milliseconds / 60_000 % 60,
+        milliseconds / 1000 % 60,
+        separator,
+        milliseconds % 1000
+    )
+}
+
+/// Removes every tag but `<i>`, `<b>` and `<u>`, along with WebVTT timestamps and the ASS
+/// override blocks some SRT files carry, like `{\an8}`.
+fn strip_unsupported_markup(text: &str) -> String {
+    let tag_re = Regex::new(r"(?i)</?([a-z][a-z0-9.]*)[^>]*>|<\d[^>]*>|\{\\[^}]*\}").unwrap();
+    tag_re
+        .replace_all(text, |captures: &regex::Captures| {
+            let tag = captures.get(0).unwrap().as_str().to_lowercase();
+            match captures.get(1).map(|name| name.as_str().to_lowercase()).as_deref() {
+                Some("i" | "b" | "u") if !tag.contains(' ') => tag,
+                _ => String::new(),
+            }
+        })
+        .into_owned()
+}
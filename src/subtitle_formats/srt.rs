@@ -0,0 +1,66 @@
+use super::{format_timestamp, parse_timing, Cue};
+
+/// Reads cues from their timing lines, so missing or wrong cue numbers don't matter.
+pub fn parse(text: &str) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    let mut lines = text.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let Some((start, end)) = parse_timing(line) else {
+            continue;
+        };
+
+        let mut text_lines = Vec::new();
+        while let Some(text_line) = lines.next_if(|line| !line.trim().is_empty()) {
+            text_lines.push(text_line.trim_end());
+        }
+        cues.push(Cue {
+            start,
+            end,
+            text: text_lines.join("\n"),
+        });
+    }
+
+    cues
+}
+
+pub fn write(cues: &[Cue]) -> String {
+    let mut content = String::new();
+    for (index, cue) in cues.iter().enumerate() {
+        content.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            index + 1,
+            format_timestamp(cue.start, ','),
+            format_timestamp(cue.end, ','),
+            cue.text
+        ));
+    }
+
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cues_are_read_from_their_timing_lines() {
+        let text = "1\r\n00:00:01,000 --> 00:00:02,500\r\nHello\r\nthere\r\n\r\n7\n00:01:00,000 --> 00:01:01,000\nAgain\n";
+        let cues = parse(text);
+        assert_eq!(cues.len(), 2);
+        assert_eq!((cues[0].start, cues[0].end), (1000, 2500));
+        assert_eq!(cues[0].text, "Hello\nthere");
+        assert_eq!((cues[1].start, cues[1].end), (60_000, 61_000));
+    }
+
+    #[test]
+    fn written_cues_read_back_the_same() {
+        let cues = vec![
+            Cue { start: 1000, end: 2500, text: "<i>Hello</i>\nthere".to_string() },
+            Cue { start: 3_723_004, end: 3_724_000, text: "Again".to_string() },
+        ];
+        let content = write(&cues);
+        assert!(content.starts_with("1\n00:00:01,000 --> 00:00:02,500\n"));
+        assert_eq!(parse(&content), cues);
+    }
+}
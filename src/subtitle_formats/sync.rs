@@ -0,0 +1,173 @@
+use super::Cue;
+
+/// Frame rate pairs subtitles drift between, from being timed for another release of the video.
+const FRAME_RATE_PAIRS: [(f64, f64); 4] = [(23.976, 24.0), (23.976, 25.0), (24.0, 25.0), (29.97, 30.0)];
+/// Offsets are first found to this many milliseconds, then refined.
+const OFFSET_STEP_MS: i64 = 100;
+/// Offsets further off than this are not considered.
+const MAX_OFFSET_MS: i64 = 10 * 60 * 1000;
+/// Share of cues that have to line up with the reference for an alignment to be trusted.
+const MIN_MATCHED_SHARE: f64 = 0.2;
+
+/// How a subtitle's timing maps onto its reference: every time is multiplied by `ratio`, then
+/// moved by `offset` milliseconds.
+#[derive(Debug, Clone, Copy)]
+pub struct Alignment {
+    pub ratio: f64,
+    pub offset: i64,
+    pub matched_cue_count: usize,
+}
+
+/// Stretches every cue by `ratio`, then moves it by `offset` milliseconds. Cues moved entirely
+/// before the start of the video are dropped.
+pub fn retime(cues: Vec<Cue>, ratio: f64, offset: i64) -> Vec<Cue> {
+    let retime_timestamp = |timestamp: u64| (timestamp as f64 * ratio).round() as i64 + offset;
+    cues.into_iter()
+        .filter(|cue| retime_timestamp(cue.end) > 0)
+        .map(|cue| Cue {
+            start: retime_timestamp(cue.start).max(0) as u64,
+            end: retime_timestamp(cue.end) as u64,
+            ..cue
+        })
+        .collect()
+}
+
+/// Finds the stretch and offset that make the most cues start when the reference's do, trying
+/// no stretch and the common frame rate conversions. Returns `None` when too few cues line up.
+pub fn align(cues: &[Cue], reference: &[Cue]) -> Option<Alignment> {
+    if cues.is_empty() || reference.is_empty() {
+        return None;
+    }
+
+    let mut ratios = vec![1.0];
+    for (slower, faster) in FRAME_RATE_PAIRS {
+        ratios.extend([faster / slower, slower / faster]);
+    }
+
+    // no stretch wins ties, as it is tried first
+    let mut best: Option<Alignment> = None;
+    for ratio in ratios {
+        let offset = find_offset(cues, reference, ratio);
+        let matched_cue_count = count_matched_cues(cues, reference, ratio, offset);
+        if best.is_none_or(|best| matched_cue_count > best.matched_cue_count) {
+            best = Some(Alignment { ratio, offset, matched_cue_count });
+        }
+    }
+
+    best.filter(|best| best.matched_cue_count as f64 >= cues.len() as f64 * MIN_MATCHED_SHARE)
+}
+
+/// Every pair of cue starts votes for the offset between them, and the step with the most
+/// votes around it wins. The offset is then refined to the median of the votes near it.
+fn find_offset(cues: &[Cue], reference: &[Cue], ratio: f64) -> i64 {
+    let step_count = (2 * MAX_OFFSET_MS / OFFSET_STEP_MS + 1) as usize;
+    let step_of = |difference: i64| ((difference + MAX_OFFSET_MS) / OFFSET_STEP_MS) as usize;
+    let differences = || {
+        cues.iter().flat_map(move |cue| {
+            let start = (cue.start as f64 * ratio).round() as i64;
+            reference
+                .iter()
+                .map(move |reference_cue| reference_cue.start as i64 - start)
+                .filter(|difference| difference.abs() <= MAX_OFFSET_MS)
+        })
+    };
+
+    let mut votes = vec![0usize; step_count];
+    for difference in differences() {
+        votes[step_of(difference)] += 1;
+    }
+    let best_step = (0..step_count)
+        .max_by_key(|step| {
+            let neighbours = step.saturating_sub(1)..=(step + 1).min(step_count - 1);
+            votes[neighbours].iter().sum::<usize>()
+        })
+        .unwrap_or(0);
+
+    let mut close_differences: Vec<i64> = differences()
+        .filter(|difference| step_of(*difference).abs_diff(best_step) <= 1)
+        .collect();
+    close_differences.sort_unstable();
+    close_differences
+        .get(close_differences.len() / 2)
+        .copied()
+        .unwrap_or(best_step as i64 * OFFSET_STEP_MS - MAX_OFFSET_MS)
+}
+
+/// Cues that start within one offset step of a reference cue once retimed.
+fn count_matched_cues(cues: &[Cue], reference: &[Cue], ratio: f64, offset: i64) -> usize {
+    let mut reference_starts: Vec<i64> = reference.iter().map(|cue| cue.start as i64).collect();
+    reference_starts.sort_unstable();
+
+    cues.iter()
+        .filter(|cue| {
+            let start = (cue.start as f64 * ratio).round() as i64 + offset;
+            let index = reference_starts.partition_point(|reference_start| *reference_start < start - OFFSET_STEP_MS);
+            reference_starts.get(index).is_some_and(|reference_start| *reference_start <= start + OFFSET_STEP_MS)
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cues at irregular times, like dialogue.
+    fn reference() -> Vec<Cue> {
+        (0..60u64)
+            .map(|index| {
+                let start = 60_000 + index * 3000 + index * index * 137 % 1500;
+                Cue { start, end: start + 1500, text: index.to_string() }
+            })
+            .collect()
+    }
+
+    fn shifted(cues: &[Cue], ratio: f64, offset: i64) -> Vec<Cue> {
+        let shift = |timestamp: u64| ((timestamp as f64 * ratio).round() as i64 + offset) as u64;
+        cues.iter().map(|cue| Cue { start: shift(cue.start), end: shift(cue.end), ..cue.clone() }).collect()
+    }
+
+    #[test]
+    fn offsets_are_found_to_the_millisecond() {
+        let reference = reference();
+        let cues = shifted(&reference, 1.0, -2537);
+
+        assert_eq!(find_offset(&cues, &reference, 1.0), 2537);
+        let alignment = align(&cues, &reference).unwrap();
+        assert_eq!((alignment.ratio, alignment.offset), (1.0, 2537));
+        assert_eq!(alignment.matched_cue_count, reference.len());
+        assert_eq!(retime(cues, alignment.ratio, alignment.offset), reference);
+    }
+
+    #[test]
+    fn frame_rate_drift_is_undone() {
+        let reference = reference();
+        let cues = shifted(&reference, 23.976 / 25.0, 1200);
+
+        let alignment = align(&cues, &reference).unwrap();
+        assert_eq!(alignment.ratio, 25.0 / 23.976);
+        assert!((alignment.offset + 1252).abs() <= 2, "offset {}", alignment.offset);
+        assert_eq!(alignment.matched_cue_count, reference.len());
+    }
+
+    #[test]
+    fn unrelated_subtitles_are_not_aligned() {
+        let reference = reference();
+        let cues: Vec<Cue> = (0..60u64)
+            .map(|index| Cue { start: index * 50_000, end: index * 50_000 + 1000, text: String::new() })
+            .collect();
+
+        assert!(align(&cues, &reference).is_none());
+        assert!(align(&[], &reference).is_none());
+    }
+
+    #[test]
+    fn cues_moved_before_the_start_are_dropped() {
+        let cues = vec![
+            Cue { start: 500, end: 1500, text: "gone".to_string() },
+            Cue { start: 1500, end: 3000, text: "clipped".to_string() },
+        ];
+        let retimed = retime(cues, 1.0, -2000);
+        assert_eq!(retimed.len(), 1);
+        assert_eq!((retimed[0].start, retimed[0].end), (0, 1000));
+    }
+}
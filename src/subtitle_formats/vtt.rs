@@ -0,0 +1,73 @@
+use super::{format_timestamp, srt, strip_unsupported_markup, Cue};
+use regex::Regex;
+
+/// Cues are read like SRT ones, headers and `NOTE`, `STYLE` and `REGION` blocks have no timing
+/// line and are skipped along the way.
+pub fn parse(text: &str) -> Vec<Cue> {
+    srt::parse(text)
+        .into_iter()
+        .map(|cue| Cue {
+            text: decode_entities(&strip_unsupported_markup(&cue.text)),
+            ..cue
+        })
+        .collect()
+}
+
+pub fn write(cues: &[Cue]) -> String {
+    let mut content = String::from("WEBVTT\n\n");
+    for cue in cues {
+        content.push_str(&format!(
+            "{} --> {}\n{}\n\n",
+            format_timestamp(cue.start, '.'),
+            format_timestamp(cue.end, '.'),
+            encode_entities(&strip_unsupported_markup(&cue.text))
+        ));
+    }
+
+    content
+}
+
+/// Escapes what WebVTT would read as markup, keeping the `<i>`, `<b>` and `<u>` tags.
+fn encode_entities(text: &str) -> String {
+    let escape = |text: &str| text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    let tag_re = Regex::new(r"</?[ibu]>").unwrap();
+
+    let mut encoded = String::new();
+    let mut last_end = 0;
+    for tag in tag_re.find_iter(text) {
+        encoded.push_str(&escape(&text[last_end..tag.start()]));
+        encoded.push_str(tag.as_str());
+        last_end = tag.end();
+    }
+    encoded.push_str(&escape(&text[last_end..]));
+    encoded
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_and_notes_are_skipped() {
+        let text = "WEBVTT\n\nNOTE a comment\n\n00:00:01.000 --> 00:00:02.000 align:start\n<v Bob><b>Hi</b> &amp; bye&nbsp;&lt;3\n";
+        let cues = parse(text);
+        assert_eq!(cues.len(), 1);
+        assert_eq!((cues[0].start, cues[0].end), (1000, 2000));
+        assert_eq!(cues[0].text, "<b>Hi</b> & bye <3");
+    }
+
+    #[test]
+    fn markup_characters_in_text_are_escaped() {
+        let cues = vec![Cue { start: 1000, end: 2000, text: "<i>a < b</i> & c > d".to_string() }];
+        let content = write(&cues);
+        assert!(content.contains("<i>a &lt; b</i> &amp; c &gt; d"));
+        assert_eq!(parse(&content), cues);
+    }
+}
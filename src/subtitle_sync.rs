@@ -0,0 +1,77 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use inline_colorization::{color_cyan, color_reset};
+use crate::config::Config;
+use crate::file_operations::FileOperations;
+use crate::languages::detect_subtitle_language;
+use crate::movie_processors::directories::read_metadata_file;
+use crate::naming::render_movie_name;
+use crate::subtitle_formats::{align, read_cues, retime, write, SubtitleFormat};
+
+/// How a subtitle's timing gets corrected.
+pub enum Retiming<'a> {
+    /// Moved by a number of seconds after being stretched from one frame rate to another
+    Manual {
+        offset_seconds: f64,
+        frame_rates: Option<(f64, f64)>,
+    },
+    /// Lined up with a subtitle whose timing is known to be right
+    Reference(&'a Path),
+}
+
+/// Corrects a subtitle's timing and writes it as SRT next to the movie in its directory.
+/// Returns where it was written.
+pub fn sync_subtitle(
+    subtitle_path: &Path,
+    retiming: Retiming,
+    config: &Config,
+    ops: &FileOperations,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let cues = read_cues(subtitle_path)?;
+    let (ratio, offset) = match retiming {
+        Retiming::Manual { offset_seconds, frame_rates } => {
+            let ratio = match frame_rates {
+                Some((from_fps, to_fps)) if from_fps > 0.0 && to_fps > 0.0 => from_fps / to_fps,
+                Some(_) => return Err("frame rates have to be positive".into()),
+                None => 1.0,
+            };
+            (ratio, (offset_seconds * 1000.0).round() as i64)
+        }
+        Retiming::Reference(reference_path) => {
+            let alignment = align(&cues, &read_cues(reference_path)?)
+                .ok_or("too few cues line up with the reference to sync against it")?;
+            println!(
+                "{color_cyan}{} of {} cues line up with the reference{color_reset}",
+                alignment.matched_cue_count,
+                cues.len()
+            );
+            (alignment.ratio, alignment.offset)
+        }
+    };
+
+    if ratio != 1.0 {
+        println!("{color_cyan}Stretching the timing by {:.5}{color_reset}", ratio);
+    }
+    println!("{color_cyan}Shifting the timing by {:+.3}s{color_reset}", offset as f64 / 1000.0);
+
+    let dest_path = get_synced_subtitle_path(subtitle_path, config);
+    ops.write(&dest_path, write(&retime(cues, ratio, offset), SubtitleFormat::Srt))?;
+    Ok(dest_path)
+}
+
+/// Subtitles in an organized movie directory are named after the movie like
+/// `process_directory` names them; any other subtitle just gets an `.srt` extension.
+fn get_synced_subtitle_path(subtitle_path: &Path, config: &Config) -> PathBuf {
+    let movie_dir_path = subtitle_path.parent().unwrap_or(Path::new(""));
+    let Some(metadata) = read_metadata_file(movie_dir_path) else {
+        return subtitle_path.with_extension("srt");
+    };
+
+    let language = detect_subtitle_language(subtitle_path).or_else(|| config.subtitle_languages().first().copied());
+    let subtitle_name = render_movie_name(
+        &config.naming.subtitle_file,
+        &metadata,
+        language.map(|language| language.code),
+    );
+    movie_dir_path.join(format!("{}.srt", subtitle_name))
+}
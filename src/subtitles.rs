@@ -1,28 +1,304 @@
+use flate2::read::GzDecoder;
 use scraper::{Html, Selector};
-use crate::constants::SCRAPER_USER_AGENT;
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use zip::ZipArchive;
+use crate::cache::{cache_key, Cache};
+use crate::config::SubtitleConfig;
+use crate::file_operations::FileOperations;
+use crate::http::HttpClient;
+use crate::languages::{detect_subtitle_language, Language};
+use crate::subtitle_formats::{decode, parse, SubtitleFormat};
+use crate::utils::split_part_number;
 
-pub async fn search_subtitles_by_imdb_id(file_name: &str, imdb_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let download_page_url = get_subtitle_download_page_url_by_imdb_id(imdb_id).await?;
-    let download_url = get_subtitle_download_url(&download_page_url).await?;
-    download_subtitle(file_name, &download_url).await?;
+const OPENSUBTITLES_BASE_URL: &str = "https://www.opensubtitles.org";
+/// How much of each end of a video goes into its OpenSubtitles hash.
+const MOVIE_HASH_CHUNK_SIZE: u64 = 64 * 1024;
 
-    Ok(())
+/// A subtitle picked for one of the preferred languages.
+pub struct Subtitle {
+    pub language: &'static Language,
+    pub path: PathBuf,
+    /// Files that must keep the subtitle's name, like the `.sub` of a VobSub `.idx`
+    pub companion_paths: Vec<PathBuf>,
+    /// The part of a movie split across several videos the subtitle goes with
+    pub part: Option<u32>,
 }
 
-async fn get_subtitle_download_page_url_by_imdb_id(imdb_id: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let url = format!("https://www.opensubtitles.org/en/search/sublanguageid-eng/subformat-srt/imdbid-{}", imdb_id);
+impl Subtitle {
+    /// Every file of the subtitle, paired with where it goes when the subtitle goes to `dest_path`.
+    pub fn placements(&self, dest_path: &Path) -> Vec<(PathBuf, PathBuf)> {
+        let mut placements = vec![(self.path.clone(), dest_path.to_path_buf())];
+        for companion_path in &self.companion_paths {
+            let extension = companion_path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            placements.push((companion_path.clone(), dest_path.with_extension(extension)));
+        }
+        placements
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        std::iter::once(&self.path).chain(&self.companion_paths)
+    }
+}
+
+/// Picks one subtitle per preferred language, in order of preference. A subtitle whose language
+/// can't be told is taken to be in the most preferred language when nothing else is. Subtitles
+/// are read through `ops`, so a dry run finds them where they would have been moved.
+pub fn select_subtitles(
+    subtitle_paths: &[PathBuf],
+    languages: &[&'static Language],
+    ops: &FileOperations,
+) -> Vec<Subtitle> {
+    // full subtitles before forced ones, and those closest to the video first
+    let mut subtitle_paths: Vec<PathBuf> = subtitle_paths
+        .iter()
+        .filter(|path| !is_vobsub_data(path, ops))
+        .cloned()
+        .collect();
+    subtitle_paths.sort_by_key(|path| (is_forced(path), path.components().count(), path.clone()));
+
+    let detected: Vec<(Option<&'static Language>, PathBuf)> = subtitle_paths
+        .into_iter()
+        .map(|path| (detect_subtitle_language(&ops.current_path(&path)), path))
+        .collect();
+
+    languages
+        .iter()
+        .enumerate()
+        .filter_map(|(index, language)| {
+            let (_, path) = detected
+                .iter()
+                .find(|(detected_language, _)| detected_language.is_some_and(|detected_language| detected_language.code == language.code))
+                .or_else(|| match index {
+                    0 => detected.iter().find(|(detected_language, _)| detected_language.is_none()),
+                    _ => None,
+                })?;
+            Some(Subtitle {
+                language,
+                path: path.clone(),
+                companion_paths: get_vobsub_data_path(path, ops).into_iter().collect(),
+                part: None,
+            })
+        })
+        .collect()
+}
+
+/// Picks the subtitles of each part of a movie split across several videos, told apart by
+/// names like `Movie.cd1.en.srt`. Subtitles made for the whole movie are kept for the
+/// languages none of the parts has one in.
+pub fn select_part_subtitles(
+    subtitle_paths: &[PathBuf],
+    languages: &[&'static Language],
+    part_numbers: &[u32],
+    ops: &FileOperations,
+) -> Vec<Subtitle> {
+    if part_numbers.is_empty() {
+        return select_subtitles(subtitle_paths, languages, ops);
+    }
+
+    let mut subtitles = Vec::new();
+    for part_number in part_numbers {
+        let part_subtitle_paths: Vec<PathBuf> = subtitle_paths
+            .iter()
+            .filter(|path| get_subtitle_part(path) == Some(*part_number))
+            .cloned()
+            .collect();
+        subtitles.extend(
+            select_subtitles(&part_subtitle_paths, languages, ops)
+                .into_iter()
+                .map(|subtitle| Subtitle { part: Some(*part_number), ..subtitle }),
+        );
+    }
+
+    let whole_movie_subtitle_paths: Vec<PathBuf> = subtitle_paths
+        .iter()
+        .filter(|path| get_subtitle_part(path).is_none())
+        .cloned()
+        .collect();
+    let missing_languages: Vec<&'static Language> = languages
+        .iter()
+        .filter(|language| !subtitles.iter().any(|subtitle| subtitle.language.code == language.code))
+        .copied()
+        .collect();
+    subtitles.extend(select_subtitles(&whole_movie_subtitle_paths, &missing_languages, ops));
+
+    subtitles
+}
+
+/// The part a subtitle is named for, either at the end of its name or before its language.
+fn get_subtitle_part(path: &Path) -> Option<u32> {
+    let stem = path.file_stem()?.to_string_lossy();
+    split_part_number(&stem)
+        .or_else(|| split_part_number(stem.rsplit_once('.')?.0))
+        .map(|(_, part_number)| part_number)
+}
+
+/// The `.sub` holding the images of a VobSub `.idx`, which is what gets picked for the pair.
+fn get_vobsub_data_path(path: &Path, ops: &FileOperations) -> Option<PathBuf> {
+    if !has_extension(path, "idx") {
+        return None;
+    }
+
+    ["sub", "SUB"]
+        .into_iter()
+        .map(|extension| path.with_extension(extension))
+        .find(|data_path| ops.is_file(data_path))
+}
+
+fn is_vobsub_data(path: &Path, ops: &FileOperations) -> bool {
+    has_extension(path, "sub")
+        && ["idx", "IDX"]
+            .into_iter()
+            .any(|extension| ops.is_file(&path.with_extension(extension)))
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.to_string_lossy().eq_ignore_ascii_case(extension))
+}
+
+fn is_forced(path: &Path) -> bool {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .is_some_and(|stem| stem.split(|c: char| !c.is_alphanumeric()).any(|token| token == "forced"))
+}
+
+/// The OpenSubtitles hash of a video: its size plus the sum of the little-endian 64-bit words
+/// in its first and last 64 KiB, which tells releases apart without reading all of them. Files
+/// too small for the two to be apart have no hash, as OpenSubtitles doesn't hash them either.
+pub fn compute_movie_hash(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    if size < 2 * MOVIE_HASH_CHUNK_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the file is too small to hash"));
+    }
+
+    let mut hash = size;
+    let mut chunk = vec![0; MOVIE_HASH_CHUNK_SIZE as usize];
+    for offset in [0, size - MOVIE_HASH_CHUNK_SIZE] {
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut chunk)?;
+        hash = chunk
+            .chunks_exact(8)
+            .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+            .fold(hash, u64::wrapping_add);
+    }
+
+    Ok(format!("{:016x}", hash))
+}
+
+/// Finds an SRT subtitle for the movie on OpenSubtitles and returns its contents, unpacked from
+/// the archive it is served in. Subtitles matched by hash are for this very release, so those
+/// are searched first and the IMDb id is only the fallback, also when the one found by hash
+/// can't be downloaded.
+pub async fn download_subtitle(
+    movie_hash: Option<&str>,
+    imdb_id: Option<&str>,
+    language: &Language,
+    config: &SubtitleConfig,
+    cache: &Cache,
+    http: &HttpClient,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let base_url = config
+        .opensubtitles
+        .base_url
+        .as_deref()
+        .unwrap_or(OPENSUBTITLES_BASE_URL)
+        .trim_end_matches('/');
+    let mut content = Err("the movie has neither a hash nor an IMDb id".into());
+    for criterion in get_search_criteria(movie_hash, imdb_id) {
+        content = download_subtitle_by(base_url, &criterion, language, cache, http).await;
+        if content.is_ok() {
+            break;
+        }
+    }
+
+    content
+}
+
+async fn download_subtitle_by(
+    base_url: &str,
+    criterion: &str,
+    language: &Language,
+    cache: &Cache,
+    http: &HttpClient,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let download_page_url = get_cached(
+        cache,
+        &cache_key("opensubtitles-search", &format!("{} {}", criterion, language.bibliographic_code)),
+        cache.search_ttl(),
+        get_subtitle_download_page_url(base_url, criterion, language, http),
+    )
+    .await?;
+    let download_url = get_cached(
+        cache,
+        &cache_key("opensubtitles-details", &download_page_url),
+        cache.details_ttl(),
+        get_subtitle_download_url(base_url, &download_page_url, http),
+    )
+    .await?;
+
+    let content = extract_subtitle(http.get_bytes(&download_url).await?)?;
+    if parse(&decode(&content), SubtitleFormat::Srt).is_empty() {
+        return Err("the download is not an SRT subtitle".into());
+    }
+
+    Ok(content)
+}
+
+/// What to search OpenSubtitles by, most precise first. IMDb ids are searched by their number,
+/// without the `tt` they are written with.
+fn get_search_criteria(movie_hash: Option<&str>, imdb_id: Option<&str>) -> Vec<String> {
+    let criteria = [
+        movie_hash.map(|hash| format!("moviehash-{}", hash)),
+        imdb_id.map(|id| format!("imdbid-{}", id.trim_start_matches("tt"))),
+    ];
 
-    let client = reqwest::Client::builder()
-        .user_agent(SCRAPER_USER_AGENT)
-        .build()?;
-    let resp = client.get(&url).send().await?.text().await?;
+    criteria.into_iter().flatten().collect()
+}
+
+/// Answers a lookup from the cache, or runs it and caches the answer.
+async fn get_cached<F>(cache: &Cache, key: &str, max_age: Duration, lookup: F) -> Result<String, Box<dyn std::error::Error>>
+where
+    F: Future<Output = Result<String, Box<dyn std::error::Error>>>,
+{
+    if let Some(value) = cache.get(key, max_age) {
+        return Ok(value);
+    }
+    if cache.is_offline() {
+        return Err(format!("{} is not cached and lookups are offline", key).into());
+    }
+
+    let value = lookup.await?;
+    cache.put(key, &value);
+    Ok(value)
+}
+
+/// Searches by a criterion like `imdbid-0133093` or `moviehash-8e245d9679d31e12`.
+async fn get_subtitle_download_page_url(
+    base_url: &str,
+    criterion: &str,
+    language: &Language,
+    http: &HttpClient,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let url = format!(
+        "{}/en/search/sublanguageid-{}/subformat-srt/{}",
+        base_url, language.bibliographic_code, criterion
+    );
+    let resp = http.get_text(&url).await?;
 
     let document = Html::parse_document(&resp);
     let selector = Selector::parse("a.bnone").unwrap();
 
     for element in document.select(&selector) {
         if let Some(subtitle_download_page_link) = element.attr("href") {
-            let download_page_link = normalize_url(&subtitle_download_page_link);
+            let download_page_link = normalize_url(base_url, subtitle_download_page_link);
             return Ok(download_page_link);
         }
     }
@@ -30,18 +306,19 @@ async fn get_subtitle_download_page_url_by_imdb_id(imdb_id: &str) -> Result<Stri
     Err("Failed to get subtitle download page".into())
 }
 
-async fn get_subtitle_download_url(subtitle_download_page_url: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let client = reqwest::Client::builder()
-        .user_agent(SCRAPER_USER_AGENT)
-        .build()?;
-    let resp = client.get(subtitle_download_page_url).send().await?.text().await?;
+async fn get_subtitle_download_url(
+    base_url: &str,
+    subtitle_download_page_url: &str,
+    http: &HttpClient,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let resp = http.get_text(subtitle_download_page_url).await?;
 
     let document = Html::parse_document(&resp);
     let selector = Selector::parse("a.bt-dwl").unwrap();
 
     for element in document.select(&selector) {
         if let Some(subtitle_download_link) = element.attr("href") {
-            let download_link = normalize_url(&subtitle_download_link);
+            let download_link = normalize_url(base_url, subtitle_download_link);
             return Ok(download_link);
         }
     }
@@ -49,26 +326,105 @@ async fn get_subtitle_download_url(subtitle_download_page_url: &str) -> Result<S
     Err("Failed to get subtitle download link".into())
 }
 
-async fn download_subtitle(file_name: &str, url: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let client = reqwest::Client::builder()
-        .user_agent(SCRAPER_USER_AGENT)
-        .build()?;
-    let response = client.get(url).send().await?;
-
-    if response.status().is_success() {
-        let bytes = response.bytes().await?;
-        std::fs::write(file_name, bytes)?;
-    } else {
-        eprintln!("Failed to download subtitle: {}", response.status());
+/// OpenSubtitles serves subtitles zipped, or gzipped from some mirrors.
+fn extract_subtitle(download: Vec<u8>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut content = Vec::new();
+    match download.as_slice() {
+        [0x50, 0x4b, 0x03, 0x04, ..] => {
+            let mut archive = ZipArchive::new(Cursor::new(download))?;
+            let index = (0..archive.len())
+                .find(|index| {
+                    archive
+                        .by_index(*index)
+                        .is_ok_and(|file| file.name().is_ok_and(|name| name.to_lowercase().ends_with(".srt")))
+                })
+                .ok_or("the archive holds no SRT subtitle")?;
+            archive.by_index(index)?.read_to_end(&mut content)?;
+        }
+        [0x1f, 0x8b, ..] => {
+            GzDecoder::new(download.as_slice()).read_to_end(&mut content)?;
+        }
+        _ => content = download,
     }
 
-    Ok(())
+    Ok(content)
 }
 
-fn normalize_url(url: &str) -> String {
+fn normalize_url(base_url: &str, url: &str) -> String {
     if url.starts_with("/") {
-        format!("https://www.opensubtitles.org{}", url)
+        format!("{}{}", base_url, url)
     } else {
         url.to_string()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CacheConfig;
+    use crate::http::tests::{serve, test_http};
+    use crate::languages::find_language;
+    use std::fs;
+
+    fn hash_of(name: &str, content: &[u8]) -> io::Result<String> {
+        let path = std::env::temp_dir().join(format!("media-organizer-hash-{}-{}", name, std::process::id()));
+        fs::write(&path, content)?;
+        let hash = compute_movie_hash(&path);
+        fs::remove_file(&path)?;
+        hash
+    }
+
+    #[test]
+    fn movie_hash_adds_the_size_and_both_ends() {
+        assert_eq!(hash_of("zeros", &[0; 128 * 1024]).unwrap(), "0000000000020000");
+        assert_eq!(hash_of("ones", &[0xff; 128 * 1024]).unwrap(), "000000000001c000");
+        // the ends would overlap in files under 128 KiB
+        assert!(hash_of("overlapping", &[0xff; 96 * 1024]).is_err());
+        let content: Vec<u8> = (0..128 * 1024 + 8).map(|index| (index * 7 % 256) as u8).collect();
+        assert_eq!(hash_of("pattern", &content).unwrap(), "9fe02060a0e10008");
+    }
+
+    #[test]
+    fn small_files_have_no_movie_hash() {
+        assert!(hash_of("small", &[0; 1024]).is_err());
+    }
+
+    #[test]
+    fn subtitle_parts_are_read_before_the_language() {
+        assert_eq!(get_subtitle_part(Path::new("Movie.cd2.srt")), Some(2));
+        assert_eq!(get_subtitle_part(Path::new("Movie.cd1.en.srt")), Some(1));
+        assert_eq!(get_subtitle_part(Path::new("Movie Part 2.English.srt")), Some(2));
+        assert_eq!(get_subtitle_part(Path::new("Movie.en.srt")), None);
+    }
+
+    #[test]
+    fn hash_is_searched_before_the_imdb_number() {
+        assert_eq!(
+            get_search_criteria(Some("8e245d9679d31e12"), Some("tt0133093")),
+            vec!["moviehash-8e245d9679d31e12", "imdbid-0133093"]
+        );
+        assert_eq!(get_search_criteria(None, Some("tt0133093")), vec!["imdbid-0133093"]);
+        assert!(get_search_criteria(None, None).is_empty());
+    }
+
+    #[tokio::test]
+    async fn imdb_id_is_tried_when_the_subtitle_found_by_hash_fails_to_download() {
+        let (base_url, requested_paths) = serve(vec![
+            ("/en/search/sublanguageid-eng/subformat-srt/moviehash-", 200, r#"<a class="bnone" href="/en/subtitles/1/by-hash">The Matrix</a>"#),
+            ("/en/search/sublanguageid-eng/subformat-srt/imdbid-0133093", 200, r#"<a class="bnone" href="/en/subtitles/2/by-imdb">The Matrix</a>"#),
+            ("/en/subtitles/1/", 200, r#"<a class="bt-dwl" href="/download/1">Download</a>"#),
+            ("/en/subtitles/2/", 200, r#"<a class="bt-dwl" href="/download/2">Download</a>"#),
+            ("/download/2", 200, "1\n00:00:01,000 --> 00:00:02,000\nWake up, Neo.\n"),
+        ]);
+        let mut config = SubtitleConfig::default();
+        config.opensubtitles.base_url = Some(base_url);
+        let cache = Cache::open(&CacheConfig { enabled: false, ..CacheConfig::default() }, false, false);
+        let language = find_language("en").unwrap();
+
+        let content = download_subtitle(Some("8e245d9679d31e12"), Some("tt0133093"), language, &config, &cache, &test_http())
+            .await
+            .unwrap();
+        assert!(String::from_utf8(content).unwrap().contains("Wake up, Neo."));
+        assert!(requested_paths.lock().unwrap().contains(&"/download/1".to_string()));
+    }
+}
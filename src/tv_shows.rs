@@ -1,39 +1,57 @@
+use crate::context::Context;
+use crate::file_operations::TransferMode;
 use crate::tv_shows_processors;
+use crate::utils::is_hidden;
 use inline_colorization::{color_red, color_reset, color_yellow};
 use std::fs;
 use std::fs::{DirEntry, ReadDir};
 use std::path::{Path, PathBuf};
 
-pub async fn handle_tv_shows(dir_path: &Path, dir_entries: ReadDir) {
+pub async fn handle_tv_shows(dir_path: &Path, dir_entries: ReadDir, ctx: &Context) {
     let directories: Vec<DirEntry> = dir_entries
-        .map(|entry| entry.unwrap())
-        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                eprintln!("{color_red}Failed to read a directory entry: {}{color_reset}", e);
+                None
+            }
+        })
+        .filter(|entry| entry.path().is_dir() && !is_hidden(&entry.path()) && !ctx.is_output_path(&entry.path()))
         .collect();
 
-    if directories.len() == 0 {
+    if directories.is_empty() {
         println!("{color_yellow}No TV show directories to process{color_reset}");
         return;
     }
 
-    delete_excluded_files(dir_path, &directories).expect("Failed to delete files");
+    if ctx.config.transfer == TransferMode::Move {
+        if let Err(e) = delete_excluded_files(dir_path, &directories, ctx) {
+            eprintln!("{color_red}Failed to delete files in {:?}: {}{color_reset}", dir_path, e);
+            return;
+        }
+    }
 
-    tv_shows_processors::directories::process_directories(directories).await;
+    tv_shows_processors::directories::process_directories(directories, ctx).await;
 }
 
-fn delete_excluded_files(dir_path: &Path, included_entries: &Vec<DirEntry>) -> std::io::Result<()> {
+fn delete_excluded_files(
+    dir_path: &Path,
+    included_entries: &[DirEntry],
+    ctx: &Context,
+) -> std::io::Result<()> {
     let included_paths: Vec<PathBuf> = included_entries.iter().map(|entry| entry.path()).collect();
 
     for entry in fs::read_dir(dir_path)? {
         let entry = entry?;
         let path = entry.path();
 
-        if !included_paths.contains(&path) {
+        if !included_paths.contains(&path) && !is_hidden(&path) && !ctx.is_output_path(&path) {
             if path.is_dir() {
                 println!("{color_red}Deleting directory path: {path:?}{color_reset}");
-                fs::remove_dir_all(&path)?;
+                ctx.ops.remove_dir_all(&path)?;
             } else {
                 println!("{color_red}Deleting file: {path:?}{color_reset}");
-                fs::remove_file(&path)?;
+                ctx.ops.remove_file(&path)?;
             }
         }
     }
@@ -1,14 +1,341 @@
-use std::fs::{DirEntry, ReadDir};
-use std::path::Path;
-use inline_colorization::{color_green, color_reset};
+use crate::config::Config;
+use crate::context::Context;
+use crate::extras::{classify_extra, is_extras_folder_of, ExtraKind};
+use crate::file_operations::TransferMode;
+use crate::subtitles::select_subtitles;
+use crate::utils::{compose_episode_name, parse_show_name, parse_to_tv_episode_metadata};
+use inline_colorization::{
+    color_blue, color_cyan, color_green, color_magenta, color_red, color_reset, color_yellow,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::fs::DirEntry;
+use std::io;
+use std::path::{Path, PathBuf};
 
-pub async fn process_directories(directory_entries: Vec<DirEntry>)  {
+pub async fn process_directories(directory_entries: Vec<DirEntry>, ctx: &Context) {
     for directory in directory_entries {
         println!("{color_green}Processing directory {:?}{color_reset}", directory.path());
-        process_directory(directory).await;
+        process_directory(directory, ctx).await;
     }
+
+    println!("{color_green}Done processing directories{color_reset}");
+}
+
+async fn process_directory(directory_entry: DirEntry, ctx: &Context) {
+    let show_directory_path = directory_entry.path();
+    let directory_name = directory_entry.file_name().to_string_lossy().into_owned();
+    let show_name = parse_show_name(&directory_name);
+
+    if show_name.is_empty() {
+        println!(
+            "{color_yellow}Skipping directory, could not detect a show name: {:?}{color_reset}",
+            show_directory_path
+        );
+        return;
+    }
+
+    println!("{color_blue}Processing show: {:?}{color_reset}", show_name);
+
+    let video_file_paths = pick_largest_per_episode(get_video_file_paths(&show_directory_path, &show_directory_path, &ctx.config));
+    if video_file_paths.is_empty() {
+        println!("{color_yellow}No episodes found{color_reset}");
+        return;
+    }
+
+    // Episodes go straight into the show's directory in the output library, if there is one,
+    // or into the one in the library when an earlier season already made it
+    let show_dir_dest_path = match &ctx.output_path {
+        Some(output_path) => output_path.join(&show_name),
+        None => show_directory_path.parent().unwrap().join(&show_name),
+    };
+    let merges_into_show_dir = ctx.output_path.is_some()
+        || (show_dir_dest_path != show_directory_path && show_dir_dest_path.exists());
+    let show_dest_path = match merges_into_show_dir {
+        true => show_dir_dest_path.clone(),
+        false => show_directory_path.clone(),
+    };
+    if merges_into_show_dir && ctx.output_path.is_none() {
+        println!("{color_blue}Adding episodes to the show directory: {:?}{color_reset}", show_dir_dest_path);
+    }
+
+    for video_file_path in video_file_paths {
+        let video_file_name = video_file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let Some(episode_metadata) = parse_to_tv_episode_metadata(&video_file_name) else {
+            println!(
+                "{color_yellow}Skipping file, could not detect the episode: {:?}{color_reset}",
+                video_file_path
+            );
+            continue;
+        };
+
+        let episode_name = compose_episode_name(&show_name, &episode_metadata);
+        println!(
+            "{color_magenta}Found episode {:?}: {:?}{color_reset}",
+            episode_name, video_file_path
+        );
+
+        let season_directory_path =
+            show_dest_path.join(format!("Season {:02}", episode_metadata.season));
+        if let Err(e) = ctx.ops.create_dir_all(&season_directory_path) {
+            eprintln!("{color_red}Failed to create the season directory: {}{color_reset}", e);
+            continue;
+        }
+
+        // Find the subtitles before the video is moved away from them
+        let subtitles = select_subtitles(
+            &get_subtitle_paths(&video_file_path, ctx),
+            &ctx.config.subtitle_languages(),
+            &ctx.ops,
+        );
+
+        let episode_dest_path = season_directory_path.join(format!(
+            "{}.{}",
+            episode_name,
+            episode_metadata.file_extension.to_lowercase()
+        ));
+        if !transfer_file(&video_file_path, &episode_dest_path, ctx) {
+            continue;
+        }
+
+        for subtitle in subtitles {
+            println!(
+                "{color_cyan}Found {} subtitle file: {:?}{color_reset}",
+                subtitle.language.name(),
+                subtitle.path
+            );
+            let sub_dest_path = season_directory_path.join(format!(
+                "{}.{}.{}",
+                episode_name,
+                subtitle.language.code,
+                get_extension(&subtitle.path)
+            ));
+            for (source_path, dest_path) in subtitle.placements(&sub_dest_path) {
+                transfer_file(&source_path, &dest_path, ctx);
+            }
+        }
+    }
+
+    // Linked and copied shows stay as they were for whatever still uses the originals
+    if ctx.config.transfer != TransferMode::Move {
+        return;
+    }
+
+    // A show moved into another directory leaves nothing behind worth keeping in one piece
+    if merges_into_show_dir && !contains_files(&show_directory_path) {
+        println!("{color_yellow}Removing emptied show directory: {:?}{color_reset}", show_directory_path);
+        if let Err(e) = ctx.ops.remove_dir_all(&show_directory_path) {
+            eprintln!("{color_red}Failed to remove the emptied show directory: {}{color_reset}", e);
+        }
+        return;
+    }
+
+    if let Err(e) = remove_empty_directories(&show_directory_path, ctx) {
+        eprintln!("{color_red}Failed to clean up empty directories: {}{color_reset}", e);
+    }
+
+    if merges_into_show_dir {
+        return;
+    }
+
+    if show_dir_dest_path != show_directory_path {
+        if show_dir_dest_path.exists() {
+            println!(
+                "{color_yellow}Not renaming show directory, target already exists: {:?}{color_reset}",
+                show_dir_dest_path
+            );
+        } else if let Err(e) = ctx.ops.rename(&show_directory_path, &show_dir_dest_path) {
+            eprintln!("{color_red}Failed to rename the show directory: {}{color_reset}", e);
+        } else {
+            println!(
+                "{color_blue}Renamed directory to: {:?}{color_reset}",
+                show_dir_dest_path
+            );
+        }
+    }
+}
+
+fn transfer_file(source_path: &Path, dest_path: &Path, ctx: &Context) -> bool {
+    if source_path == dest_path {
+        return true;
+    }
+
+    if dest_path.exists() {
+        println!(
+            "{color_yellow}Skipping file, target already exists: {:?}{color_reset}",
+            dest_path
+        );
+        return false;
+    }
+
+    if let Err(e) = ctx.ops.transfer(source_path, dest_path, ctx.config.transfer) {
+        eprintln!(
+            "{color_red}Failed to transfer {:?} to {:?}: {}{color_reset}",
+            source_path, dest_path, e
+        );
+        return false;
+    }
+
+    println!("{color_green}Placed file at: {:?}{color_reset}", dest_path);
+    true
 }
 
-async fn process_directory(directory_entry: DirEntry) {
+/// The videos of the show, leaving out sample and extras folders like `Sample/` or
+/// `Featurettes/` and samples of the episodes. Episode titles are not checked for extras, as
+/// one called "The Interview" is still an episode.
+fn get_video_file_paths(dir_path: &Path, show_directory_path: &Path, config: &Config) -> Vec<PathBuf> {
+    let mut video_file_paths = Vec::new();
+    let Ok(dir_entries) = fs::read_dir(dir_path) else {
+        return video_file_paths;
+    };
+
+    for entry in dir_entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.is_dir() {
+            if is_extras_folder_of(&entry.file_name().to_string_lossy(), show_directory_path) {
+                println!("{color_yellow}Skipping extras directory: {:?}{color_reset}", path);
+                continue;
+            }
+            video_file_paths.extend(get_video_file_paths(&path, show_directory_path, config));
+        } else if config.is_video_file(&path) {
+            if classify_extra(&path, show_directory_path) == Some(ExtraKind::Sample) {
+                println!("{color_yellow}Skipping sample: {:?}{color_reset}", path);
+                continue;
+            }
+            video_file_paths.push(path);
+        }
+    }
 
-}
\ No newline at end of file
+    video_file_paths.sort();
+    video_file_paths
+}
+
+/// Keeps the largest of the videos of the same episode, as the others are samples or worse
+/// copies that would take its name.
+fn pick_largest_per_episode(video_file_paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut largest_paths: HashMap<(u16, Vec<u16>, String), (u64, PathBuf)> = HashMap::new();
+    let mut other_paths = Vec::new();
+    for path in video_file_paths {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let Some(episode_metadata) = parse_to_tv_episode_metadata(&file_name) else {
+            other_paths.push(path);
+            continue;
+        };
+
+        let episode = (episode_metadata.season, episode_metadata.episodes, episode_metadata.file_extension.to_lowercase());
+        let size = fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or_default();
+        match largest_paths.get(&episode) {
+            Some((largest_size, largest_path)) if *largest_size >= size => {
+                println!("{color_yellow}Skipping smaller copy of {:?}: {:?}{color_reset}", largest_path, path);
+            }
+            Some((_, largest_path)) => {
+                println!("{color_yellow}Skipping smaller copy of {:?}: {:?}{color_reset}", path, largest_path);
+                largest_paths.insert(episode, (size, path));
+            }
+            None => {
+                largest_paths.insert(episode, (size, path));
+            }
+        }
+    }
+
+    let mut video_file_paths: Vec<PathBuf> = largest_paths.into_values().map(|(_, path)| path).chain(other_paths).collect();
+    video_file_paths.sort();
+    video_file_paths
+}
+
+/// Looks for subtitles next to the episode (`<episode>.srt`, `<episode>.en.srt`) and in a
+/// `Subs` directory, either flat or in a folder named after the episode.
+fn get_subtitle_paths(video_file_path: &Path, ctx: &Context) -> Vec<PathBuf> {
+    let (Some(video_dir_path), Some(video_stem)) = (video_file_path.parent(), video_file_path.file_stem()) else {
+        return Vec::new();
+    };
+    let video_stem = video_stem.to_string_lossy().into_owned();
+
+    let mut candidate_dirs = vec![video_dir_path.to_path_buf()];
+    for subs_dir_name in ["Subs", "Subtitles", "subs", "subtitles"] {
+        let subs_dir_path = video_dir_path.join(subs_dir_name);
+        candidate_dirs.push(subs_dir_path.join(&video_stem));
+        candidate_dirs.push(subs_dir_path);
+    }
+
+    let mut subtitle_paths = Vec::new();
+    for candidate_dir in candidate_dirs {
+        let is_episode_dir = candidate_dir.file_name() == Some(video_stem.as_ref());
+        let Ok(dir_entries) = fs::read_dir(&candidate_dir) else {
+            continue;
+        };
+
+        subtitle_paths.extend(dir_entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && ctx.config.is_subtitle_file(path))
+            .filter(|path| {
+                let stem = path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                is_episode_dir || stem == video_stem || stem.starts_with(&format!("{}.", video_stem))
+            }));
+    }
+
+    subtitle_paths
+}
+
+fn get_extension(path: &Path) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+fn contains_files(dir_path: &Path) -> bool {
+    let Ok(dir_entries) = fs::read_dir(dir_path) else {
+        return false;
+    };
+
+    dir_entries
+        .filter_map(Result::ok)
+        .any(|entry| !entry.path().is_dir() || contains_files(&entry.path()))
+}
+
+fn remove_empty_directories(dir_path: &Path, ctx: &Context) -> io::Result<()> {
+    for entry in fs::read_dir(dir_path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            remove_empty_directories(&path, ctx)?;
+            if fs::read_dir(&path)?.next().is_none() {
+                println!("{color_yellow}Removing empty directory: {:?}{color_reset}", path);
+                ctx.ops.remove_dir(&path)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_and_extras_in_season_folders_are_skipped() {
+        let show_path = std::env::temp_dir().join(format!("media-organizer-tv-{}", std::process::id())).join("Show.S01");
+        let season_path = show_path.join("Season 1");
+        fs::create_dir_all(season_path.join("Sample")).unwrap();
+        fs::create_dir_all(show_path.join("Featurettes")).unwrap();
+        fs::write(season_path.join("show.s01e01.mkv"), [0; 2048]).unwrap();
+        fs::write(season_path.join("Sample").join("show.s01e01.sample.mkv"), [0; 64]).unwrap();
+        fs::write(season_path.join("show.s01e02.sample.mkv"), [0; 64]).unwrap();
+        fs::write(season_path.join("show.s01e02.mkv"), [0; 2048]).unwrap();
+        fs::write(show_path.join("Featurettes").join("show.s01e03.making.of.mkv"), [0; 2048]).unwrap();
+        // a leftover copy that sorts first but is smaller than the episode
+        fs::write(season_path.join("a.show.s01e01.mkv"), [0; 1024]).unwrap();
+
+        let video_file_paths = pick_largest_per_episode(get_video_file_paths(&show_path, &show_path, &Config::default()));
+        assert_eq!(video_file_paths, vec![season_path.join("show.s01e01.mkv"), season_path.join("show.s01e02.mkv")]);
+        let _ = fs::remove_dir_all(show_path.parent().unwrap());
+    }
+}
@@ -1,30 +1,135 @@
-use crate::models::{MediaEncodingFormat, MovieMetadata};
+use crate::models::{MediaEncodingFormat, MovieMetadata, TvEpisodeMetadata};
+use crate::probe::MediaInfo;
 use percent_encoding::{AsciiSet, NON_ALPHANUMERIC};
 use regex::Regex;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+const YEAR_PATTERN: &str = r"^(19|20)\d{2}$";
+
+const RESOLUTION_PATTERN: &str = r"^(4K|720p|1080p|2160p)$";
+
+const ENCODING_FORMAT_PATTERN: &str = r"(?i)(x264|x265|h264|h265|av1|vp9)";
+
+const RELEASE_TAG_PATTERN: &str = r"(?i)^(web|web-?dl|web-?rip|hdtv|pdtv|bluray|blu-ray|brrip|bdrip|dvdrip|hdrip|proper|repack|internal|amzn|nf|hulu|dsnp|hmax|atvp|aac[\d]*|ac3|dd[p]?[\d]*|dts|10bit|hdr|multi|subbed|dubbed)$";
+
+/// Editions a release can be cut as, matched against its lowercased words with apostrophes
+/// left out.
+const EDITION_PATTERNS: [(&str, &str); 17] = [
+    ("Director's Cut", r"^directors? ?cut$"),
+    ("Extended Cut", r"^extended ?cut$"),
+    ("Extended Edition", r"^extended (edition|version)$"),
+    ("Extended", r"^extended$"),
+    ("Theatrical Cut", r"^theatrical( cut| edition| version)?$"),
+    ("Final Cut", r"^final ?cut$"),
+    ("Ultimate Cut", r"^ultimate cut$"),
+    ("Ultimate Edition", r"^ultimate edition$"),
+    ("Special Edition", r"^special edition$"),
+    ("Collector's Edition", r"^collectors? edition$"),
+    ("Anniversary Edition", r"^([0-9]+(st|nd|rd|th) )?anniversary( edition)?$"),
+    ("Unrated", r"^unrated( cut| edition| version)?$"),
+    ("Uncut", r"^uncut$"),
+    ("IMAX", r"^imax( edition| version)?$"),
+    ("Remastered", r"^(digitally )?remastered$"),
+    ("Criterion", r"^criterion( collection| edition)?$"),
+    ("Open Matte", r"^open matte$"),
+];
+
+/// The longest edition phrase, in words.
+const EDITION_MAX_WORDS: usize = 3;
+
+/// Files with more episodes than this hold a whole season rather than a double episode, and a
+/// marker claiming more is a typo or a number that isn't an episode.
+const MAX_EPISODES_PER_FILE: u16 = 4;
+
+/// Stacked parts of one movie end their names the way media servers expect, like `cd1`,
+/// `part 2` or `disc b`.
+const PART_PATTERN: &str = r"(?i)^(.*?)(?:[ ._-]+|\b)(?:cd|dvd|part|pt|disc|disk)[ ._-]*([0-9]{1,2}|[a-d])$";
 
 pub fn parse_to_movie_metadata(file_name: &str) -> MovieMetadata {
     let (raw_file_name, file_extension) = get_raw_file_name_and_extension(file_name);
     compose_movie_metadata(raw_file_name, file_extension)
 }
 
-pub fn merge_base_with_file(base_path: &Path, file_name: &str) -> String {
-    let merged_path = base_path.join(file_name);
-    merged_path.to_string_lossy().into_owned()
+pub fn parse_to_tv_episode_metadata(file_name: &str) -> Option<TvEpisodeMetadata> {
+    let (raw_file_name, file_extension) = get_raw_file_name_and_extension(file_name);
+    compose_tv_episode_metadata(raw_file_name, file_extension)
+}
+
+/// Fills in what the video's container says, which wins over what its file name claims. Names
+/// that tell the encoder of the right codec apart, like `x264`, keep it.
+pub fn merge_media_info(metadata: &mut MovieMetadata, media_info: MediaInfo) {
+    if let Some(resolution) = media_info.resolution() {
+        metadata.resolution = Some(resolution);
+    }
+    let codec_matches_name = matches!(
+        (&media_info.video_codec, &metadata.encoding_format),
+        (Some(MediaEncodingFormat::H264), Some(MediaEncodingFormat::X264))
+            | (Some(MediaEncodingFormat::H265), Some(MediaEncodingFormat::X265))
+    );
+    if media_info.video_codec.is_some() && !codec_matches_name {
+        metadata.encoding_format = media_info.video_codec;
+    }
+    if let Some(duration_ms) = media_info.duration_ms {
+        metadata.duration_seconds = Some(duration_ms / 1000);
+    }
+    metadata.audio_tracks = media_info.audio_tracks;
+    metadata.subtitle_tracks = media_info.subtitle_tracks;
 }
 
-pub fn compose_media_name_from_metadata(metadata: &MovieMetadata) -> String {
-    let mut result = metadata.media_name.clone();
+/// Splits a name like `Movie.cd2` into the name the parts share and the part number, counting
+/// lettered parts from `a` for the first.
+pub fn split_part_number(name: &str) -> Option<(&str, u32)> {
+    let captures = Regex::new(PART_PATTERN).unwrap().captures(name)?;
+    let base_name = captures.get(1)?.as_str();
+    let part = captures.get(2)?.as_str().to_lowercase();
+    let part_number = match part.parse::<u32>() {
+        Ok(part_number) => part_number,
+        Err(_) => part.chars().next()? as u32 - 'a' as u32 + 1,
+    };
 
-    if let Some(year) = metadata.release_year {
-        result.push_str(&format!(" ({})", year));
+    match base_name.is_empty() {
+        true => None,
+        false => Some((base_name, part_number)),
     }
+}
+
+/// Every part of the movie `video_path` is a part of, in order and numbered, out of
+/// `video_paths`. Empty unless the movie is split into at least two parts.
+pub fn find_video_parts(video_path: &Path, video_paths: &[PathBuf]) -> Vec<(u32, PathBuf)> {
+    let base_name_of = |path: &Path| {
+        let stem = path.file_stem()?.to_string_lossy();
+        split_part_number(&stem).map(|(base_name, part_number)| (base_name.to_lowercase(), part_number))
+    };
+    let Some((base_name, _)) = base_name_of(video_path) else {
+        return Vec::new();
+    };
+
+    let mut parts: Vec<(u32, PathBuf)> = video_paths
+        .iter()
+        .filter_map(|path| match base_name_of(path)? {
+            (part_base_name, part_number) if part_base_name == base_name => Some((part_number, path.clone())),
+            _ => None,
+        })
+        .collect();
+    parts.sort();
+    // the same part in two formats is one part too many
+    parts.dedup_by_key(|(part_number, _)| *part_number);
 
-    if let Some(resolution) = metadata.resolution {
-        result.push_str(&format!(" [{}p]", resolution));
+    match parts.len() {
+        0 | 1 => Vec::new(),
+        _ => parts,
     }
+}
+
+pub fn merge_base_with_file(base_path: &Path, file_name: &str) -> String {
+    let merged_path = base_path.join(file_name);
+    merged_path.to_string_lossy().into_owned()
+}
 
-    clean_filename(&result).unwrap()
+/// Hidden entries include the organizer's own journal and holding area, which are never media.
+pub fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
 }
 
 pub fn get_raw_file_name_and_extension(file_name: &str) -> (&str, String) {
@@ -54,9 +159,20 @@ fn compose_movie_metadata(raw_file_name: &str, file_extension: String) -> MovieM
     let original_file_name = raw_file_name.to_string();
 
     // Regular expressions for extracting metadata
-    let year_re = Regex::new(r"^(19|20)\d{2}$").unwrap();
-    let resolution_re = Regex::new(r"^(4K|720p|1080p|2160p)$").unwrap();
-    let encoding_format_re = Regex::new(r"(?i)(x264|x265|h264|h265)").unwrap();
+    let year_re = Regex::new(YEAR_PATTERN).unwrap();
+    let resolution_re = Regex::new(RESOLUTION_PATTERN).unwrap();
+    let encoding_format_re = Regex::new(ENCODING_FORMAT_PATTERN).unwrap();
+
+    // editions span several words, so they are taken out before the words are sorted
+    let metadata_start = parts
+        .iter()
+        .position(|part| year_re.is_match(part) || resolution_re.is_match(part) || encoding_format_re.is_match(part))
+        .unwrap_or(parts.len());
+    let (editions, parts) = extract_editions(parts, metadata_start);
+    let edition = match editions.is_empty() {
+        true => None,
+        false => Some(editions.join(" ")),
+    };
 
     let mut release_year = None;
     let mut resolution = None;
@@ -72,12 +188,7 @@ fn compose_movie_metadata(raw_file_name: &str, file_extension: String) -> MovieM
                 release_year = part.parse::<u16>().ok();
                 metadata_started = true;
             } else if resolution_re.is_match(part) {
-                resolution = match part {
-                    "4K" | "2160p" => Some(2160),
-                    "1080p" => Some(1080),
-                    "720p" => Some(720),
-                    _ => None,
-                };
+                resolution = parse_resolution(part);
                 metadata_started = true;
             } else if encoding_format_re.is_match(part) {
                 encoding_format = MediaEncodingFormat::from(part);
@@ -92,12 +203,7 @@ fn compose_movie_metadata(raw_file_name: &str, file_extension: String) -> MovieM
             if year_re.is_match(part) {
                 release_year = part.parse::<u16>().ok();
             } else if resolution_re.is_match(part) {
-                resolution = match part {
-                    "4K" | "2160p" => Some(2160),
-                    "1080p" => Some(1080),
-                    "720p" => Some(720),
-                    _ => None,
-                };
+                resolution = parse_resolution(part);
             } else if encoding_format_re.is_match(part) {
                 encoding_format = MediaEncodingFormat::from(part);
             } else {
@@ -112,11 +218,151 @@ fn compose_movie_metadata(raw_file_name: &str, file_extension: String) -> MovieM
         original_file_name,
         media_name: to_title_case(&media_name),
         release_year,
+        edition,
         encoding_format,
         resolution,
         additional_data,
         file_extension,
         imdb_id: None,
+        movie_hash: None,
+        duration_seconds: None,
+        audio_tracks: Vec::new(),
+        subtitle_tracks: Vec::new(),
+    }
+}
+
+/// Takes the edition phrases, like `Directors Cut` or `IMAX`, out of a file name's words and
+/// returns their names along with the words that are left. Only words after the title are
+/// looked at, as titles like `The Final Cut` read just like editions.
+fn extract_editions(parts: Vec<&str>, metadata_start: usize) -> (Vec<String>, Vec<&str>) {
+    let edition_res: Vec<(&str, Regex)> = EDITION_PATTERNS
+        .iter()
+        .map(|(name, pattern)| (*name, Regex::new(pattern).unwrap()))
+        .collect();
+    let words: Vec<String> = parts.iter().map(|part| part.replace(['\'', '’'], "").to_lowercase()).collect();
+
+    let mut editions: Vec<String> = Vec::new();
+    let mut remaining_parts = Vec::new();
+    let mut index = 0;
+    while index < parts.len() {
+        let found_edition = (1..=EDITION_MAX_WORDS.min(parts.len() - index)).rev().find_map(|word_count| {
+            if index < metadata_start {
+                return None;
+            }
+            let phrase = words[index..index + word_count].join(" ");
+            edition_res
+                .iter()
+                .find(|(_, edition_re)| edition_re.is_match(&phrase))
+                .map(|(name, _)| (*name, word_count))
+        });
+
+        match found_edition {
+            Some((name, word_count)) => {
+                if !editions.iter().any(|edition| edition == name) {
+                    editions.push(name.to_string());
+                }
+                index += word_count;
+            }
+            None => {
+                remaining_parts.push(parts[index]);
+                index += 1;
+            }
+        }
+    }
+
+    (editions, remaining_parts)
+}
+
+fn compose_tv_episode_metadata(
+    raw_file_name: &str,
+    file_extension: String,
+) -> Option<TvEpisodeMetadata> {
+    let sanitized_file_name = raw_file_name.replace(['.', '_'], " ");
+    let original_file_name = raw_file_name.to_string();
+
+    // Episode markers: `S01E02`, `S01E02E03`, `S01E02-E03`, `1x02`, `1x02-03`, `Season 1 Episode 2`
+    let episode_marker_res = [
+        Regex::new(r"(?i)\bs(\d{1,2}) ?e(\d{1,3})((?:[ -]*e\d{1,3})*)\b").unwrap(),
+        Regex::new(r"(?i)\b(\d{1,2})x(\d{2,3})((?:-(?:\d{1,2}x)?\d{2,3})*)\b").unwrap(),
+        Regex::new(r"(?i)\bseason ?(\d{1,2})[ -]*episode ?(\d{1,3})((?:-\d{1,3})*)\b").unwrap(),
+    ];
+    let captures = episode_marker_res
+        .iter()
+        .find_map(|marker_re| marker_re.captures(&sanitized_file_name))?;
+
+    let season = captures[1].parse::<u16>().ok()?;
+    let first_episode = captures[2].parse::<u16>().ok()?;
+    let last_episode = captures[3]
+        .split([' ', '-'])
+        .filter_map(|segment| {
+            let digits_start = segment.rfind(|c: char| !c.is_ascii_digit()).map_or(0, |pos| pos + 1);
+            segment[digits_start..].parse::<u16>().ok()
+        })
+        .max()
+        .filter(|last_episode| last_episode.saturating_sub(first_episode) < MAX_EPISODES_PER_FILE)
+        .unwrap_or(first_episode)
+        .max(first_episode);
+    let episodes = (first_episode..=last_episode).collect();
+
+    let year_re = Regex::new(YEAR_PATTERN).unwrap();
+    let resolution_re = Regex::new(RESOLUTION_PATTERN).unwrap();
+    let encoding_format_re = Regex::new(ENCODING_FORMAT_PATTERN).unwrap();
+    let release_tag_re = Regex::new(RELEASE_TAG_PATTERN).unwrap();
+
+    let marker = captures.get(0).unwrap();
+    let show_part = &sanitized_file_name[..marker.start()];
+    let release_year = show_part
+        .split_whitespace()
+        .map(|part| part.trim_matches(|c| c == '(' || c == ')'))
+        .find(|part| year_re.is_match(part))
+        .and_then(|part| part.parse::<u16>().ok());
+
+    let mut resolution = None;
+    let mut encoding_format = None;
+    let mut additional_data = Vec::new();
+    let mut episode_title_parts = Vec::new();
+    let mut metadata_started = false;
+
+    for part in sanitized_file_name[marker.end()..].split_whitespace() {
+        if year_re.is_match(part) {
+            metadata_started = true;
+        } else if resolution_re.is_match(part) {
+            resolution = parse_resolution(part);
+            metadata_started = true;
+        } else if let Some(encoding_match) = encoding_format_re.find(part) {
+            // release groups are often glued to the codec, e.g. `x264-GROUP`
+            encoding_format = MediaEncodingFormat::from(encoding_match.as_str());
+            metadata_started = true;
+        } else if metadata_started || release_tag_re.is_match(part) {
+            additional_data.push(part.to_string());
+            metadata_started = true;
+        } else if part != "-" {
+            episode_title_parts.push(part.to_string());
+        }
+    }
+
+    let episode_title = Some(to_title_case(&episode_title_parts.join(" ")))
+        .filter(|title| !title.is_empty());
+
+    Some(TvEpisodeMetadata {
+        original_file_name,
+        season,
+        episodes,
+        episode_title,
+        release_year,
+        encoding_format,
+        resolution,
+        additional_data,
+        file_extension,
+    })
+}
+
+fn parse_resolution(part: &str) -> Option<u16> {
+    match part {
+        "4K" | "2160p" => Some(2160),
+        "1080p" => Some(1080),
+        "720p" => Some(720),
+        _ => None,
     }
 }
 
@@ -136,7 +382,7 @@ fn to_title_case(input: &str) -> String {
         .join(" ")
 }
 
-fn clean_filename(filename: &str) -> Option<String> {
+pub fn clean_filename(filename: &str) -> Option<String> {
     // Reserved Windows names (case-insensitive)
     let reserved_names = [
         "CON", "PRN", "AUX", "NUL",
@@ -164,3 +410,161 @@ fn clean_filename(filename: &str) -> Option<String> {
     // Ensure the cleaned filename length does not exceed 255 characters
     Some(sanitized.chars().take(255).collect())
 }
+
+pub fn parse_show_name(directory_name: &str) -> String {
+    let sanitized_name = directory_name.replace(['.', '_'], " ");
+    let year_re = Regex::new(r"^\(?(19|20)\d{2}\)?$").unwrap();
+    let season_re = Regex::new(r"(?i)^(s\d{1,2}(e\d{1,3})?|season|complete)$").unwrap();
+    let resolution_re = Regex::new(&format!("(?i){}", RESOLUTION_PATTERN)).unwrap();
+    let encoding_format_re = Regex::new(ENCODING_FORMAT_PATTERN).unwrap();
+    let release_tag_re = Regex::new(RELEASE_TAG_PATTERN).unwrap();
+
+    // the name ends where the season or what the release is made of starts, though a show
+    // can be named like a release tag
+    let show_name_parts: Vec<String> = sanitized_name
+        .split_whitespace()
+        .enumerate()
+        .take_while(|(index, part)| {
+            !year_re.is_match(part)
+                && !season_re.is_match(part)
+                && (*index == 0
+                    || !(resolution_re.is_match(part) || encoding_format_re.is_match(part) || release_tag_re.is_match(part)))
+        })
+        .map(|(_, part)| {
+            // keep qualifiers like "(US)" as they are
+            if part.starts_with('(') {
+                part.to_string()
+            } else {
+                to_title_case(part)
+            }
+        })
+        .collect();
+
+    show_name_parts.join(" ")
+}
+
+pub fn compose_episode_name(show_name: &str, metadata: &TvEpisodeMetadata) -> String {
+    let episode_markers: Vec<String> = metadata
+        .episodes
+        .iter()
+        .map(|episode| format!("E{:02}", episode))
+        .collect();
+    let mut episode_name = format!(
+        "{} - S{:02}{}",
+        show_name,
+        metadata.season,
+        episode_markers.join("-")
+    );
+    if let Some(episode_title) = &metadata.episode_title {
+        episode_name.push_str(&format!(" - {}", episode_title));
+    }
+    clean_filename(&episode_name).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn show_name_ends_at_the_season_or_release_details() {
+        assert_eq!(parse_show_name("Show.Name.S01.720p.WEB"), "Show Name");
+        assert_eq!(parse_show_name("Show.Complete.720p.WEB"), "Show");
+        assert_eq!(parse_show_name("Show.Name.1080p.BluRay.x264"), "Show Name");
+        assert_eq!(parse_show_name("The.Office.(US).2005.Season.1"), "The Office (US)");
+        assert_eq!(parse_show_name("Show Name WEB-DL"), "Show Name");
+    }
+
+    #[test]
+    fn episode_markers_are_recognised() {
+        for file_name in ["Show.S01E02.720p.mkv", "Show 1x02.mkv", "Show Season 1 Episode 2.mkv", "show_s01_e02.mkv"] {
+            let metadata = parse_to_tv_episode_metadata(file_name).unwrap();
+            assert_eq!((metadata.season, metadata.episodes), (1, vec![2]), "{}", file_name);
+        }
+        assert!(parse_to_tv_episode_metadata("Movie.2000.1080p.mkv").is_none());
+    }
+
+    #[test]
+    fn multi_episode_markers_cover_the_range() {
+        let episodes = |file_name: &str| parse_to_tv_episode_metadata(file_name).unwrap().episodes;
+        assert_eq!(episodes("Show.S01E02E03.mkv"), vec![2, 3]);
+        assert_eq!(episodes("Show.S01E02-E04.mkv"), vec![2, 3, 4]);
+        assert_eq!(episodes("Show.1x02-03.mkv"), vec![2, 3]);
+        assert_eq!(episodes("Show.Season.1.Episode.2-3.mkv"), vec![2, 3]);
+    }
+
+    #[test]
+    fn implausible_episode_ranges_are_a_single_episode() {
+        let episodes = |file_name: &str| parse_to_tv_episode_metadata(file_name).unwrap().episodes;
+        assert_eq!(episodes("Show.S01E01E999.mkv"), vec![1]);
+        assert_eq!(episodes("Show.S01E05-E02.mkv"), vec![5]);
+    }
+
+    #[test]
+    fn episode_title_and_release_details_follow_the_marker() {
+        let metadata = parse_to_tv_episode_metadata("Show.Name.2005.S02E03.the.pilot.1080p.WEB-DL.x265-GRP.mkv").unwrap();
+        assert_eq!(metadata.episode_title.as_deref(), Some("The Pilot"));
+        assert_eq!(metadata.release_year, Some(2005));
+        assert_eq!(metadata.resolution, Some(1080));
+        assert!(matches!(metadata.encoding_format, Some(MediaEncodingFormat::X265)));
+        assert_eq!(metadata.file_extension, "mkv");
+        assert_eq!(compose_episode_name("Show Name", &metadata), "Show Name - S02E03 - The Pilot");
+
+        let metadata = parse_to_tv_episode_metadata("Show.S01E01E02.720p.HDTV.mkv").unwrap();
+        assert_eq!(metadata.episode_title, None);
+        assert_eq!(compose_episode_name("Show", &metadata), "Show - S01E01-E02");
+    }
+
+    #[test]
+    fn part_numbers_are_split_off() {
+        assert_eq!(split_part_number("Movie.2000.cd2"), Some(("Movie.2000", 2)));
+        assert_eq!(split_part_number("Movie - Part 1"), Some(("Movie", 1)));
+        assert_eq!(split_part_number("Movie_DISC_03"), Some(("Movie", 3)));
+        assert_eq!(split_part_number("Movie.pt.b"), Some(("Movie", 2)));
+        assert_eq!(split_part_number("cd1"), None);
+        assert_eq!(split_part_number("Scorpion"), None);
+        assert_eq!(split_part_number("Movie.2000"), None);
+    }
+
+    #[test]
+    fn video_parts_are_numbered_in_order() {
+        let video_paths: Vec<PathBuf> = ["Movie.CD2.avi", "Movie.cd1.avi", "Movie.cd1.mkv", "Other.cd1.avi", "Other.avi"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+
+        assert_eq!(
+            find_video_parts(Path::new("Movie.CD2.avi"), &video_paths),
+            vec![(1, PathBuf::from("Movie.cd1.avi")), (2, PathBuf::from("Movie.CD2.avi"))]
+        );
+        assert!(find_video_parts(Path::new("Other.cd1.avi"), &video_paths).is_empty());
+        assert!(find_video_parts(Path::new("Other.avi"), &video_paths).is_empty());
+    }
+
+    #[test]
+    fn edition_after_the_year_is_taken_out_of_the_name() {
+        let metadata = parse_to_movie_metadata("Blade.Runner.1982.Final.Cut.1080p.BluRay.x264.mkv");
+        assert_eq!(metadata.media_name, "Blade Runner");
+        assert_eq!(metadata.release_year, Some(1982));
+        assert_eq!(metadata.edition.as_deref(), Some("Final Cut"));
+        assert_eq!(metadata.resolution, Some(1080));
+    }
+
+    #[test]
+    fn edition_words_in_the_title_stay_in_it() {
+        let metadata = parse_to_movie_metadata("The.Final.Cut.2004.1080p.mkv");
+        assert_eq!(metadata.media_name, "The Final Cut");
+        assert_eq!(metadata.release_year, Some(2004));
+        assert_eq!(metadata.edition, None);
+
+        let metadata = parse_to_movie_metadata("The.Extended.Family.2020.720p.mkv");
+        assert_eq!(metadata.media_name, "The Extended Family");
+        assert_eq!(metadata.edition, None);
+    }
+
+    #[test]
+    fn several_editions_are_joined() {
+        let metadata = parse_to_movie_metadata("The.Lord.of.the.Rings.2001.Extended.Directors.Cut.IMAX.mkv");
+        assert_eq!(metadata.edition.as_deref(), Some("Extended Director's Cut IMAX"));
+        assert_eq!(metadata.additional_data, Vec::<String>::new());
+    }
+}